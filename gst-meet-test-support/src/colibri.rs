@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use colibri::ColibriMessage;
+use futures::{SinkExt as _, StreamExt as _};
+use tokio::{
+  net::TcpListener,
+  sync::{mpsc, Mutex},
+  task::JoinHandle,
+  time::timeout,
+};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, warn};
+
+use crate::TIMEOUT;
+
+/// A stand-in for the Colibri WebSocket of a JVB.
+///
+/// Messages sent by the client are queued and can be awaited with
+/// [next_message](MockColibri::next_message) or [wait_for](MockColibri::wait_for).
+pub struct MockColibri {
+  url: String,
+  received_rx: Mutex<mpsc::UnboundedReceiver<ColibriMessage>>,
+  client_tx: Arc<Mutex<Option<mpsc::UnboundedSender<ColibriMessage>>>>,
+  task: JoinHandle<()>,
+}
+
+impl MockColibri {
+  pub async fn start() -> Result<Self> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!(
+      "ws://{}/colibri-ws/jvb1/conference/endpoint",
+      listener.local_addr()?
+    );
+
+    let (received_tx, received_rx) = mpsc::unbounded_channel();
+    let client_tx = Arc::new(Mutex::new(None));
+
+    let task = {
+      let client_tx = client_tx.clone();
+      tokio::spawn(async move {
+        while let Ok((stream, addr)) = listener.accept().await {
+          debug!("Colibri connection from {}", addr);
+          let websocket = match tokio_tungstenite::accept_async(stream).await {
+            Ok(websocket) => websocket,
+            Err(e) => {
+              warn!("Colibri WebSocket handshake failed: {:?}", e);
+              continue;
+            },
+          };
+          let (mut sink, mut stream) = websocket.split();
+
+          let (tx, mut rx) = mpsc::unbounded_channel::<ColibriMessage>();
+          *client_tx.lock().await = Some(tx);
+          tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+              let json = match serde_json::to_string(&message) {
+                Ok(json) => json,
+                Err(e) => {
+                  warn!("failed to serialise Colibri message: {:?}", e);
+                  continue;
+                },
+              };
+              if sink.send(Message::Text(json)).await.is_err() {
+                break;
+              }
+            }
+          });
+
+          let received_tx = received_tx.clone();
+          tokio::spawn(async move {
+            while let Some(Ok(message)) = stream.next().await {
+              if let Message::Text(json) = message {
+                match serde_json::from_str(&json) {
+                  Ok(message) => {
+                    let _ = received_tx.send(message);
+                  },
+                  Err(e) => warn!("unparseable Colibri message {}: {:?}", json, e),
+                }
+              }
+            }
+          });
+        }
+      })
+    };
+
+    Ok(Self {
+      url,
+      received_rx: Mutex::new(received_rx),
+      client_tx,
+      task,
+    })
+  }
+
+  /// The URL to signal in the `web-socket` element of the session-initiate.
+  pub fn url(&self) -> &str {
+    &self.url
+  }
+
+  /// Wait for the next message from the client.
+  pub async fn next_message(&self) -> Result<ColibriMessage> {
+    timeout(TIMEOUT, self.received_rx.lock().await.recv())
+      .await
+      .context("timed out waiting for Colibri message")?
+      .context("Colibri server stopped")
+  }
+
+  /// Wait for a message from the client matching `f`, discarding any others (for example the
+  /// periodic `EndpointStats`).
+  pub async fn wait_for(&self, f: impl Fn(&ColibriMessage) -> bool) -> Result<ColibriMessage> {
+    timeout(TIMEOUT, async {
+      let mut received_rx = self.received_rx.lock().await;
+      while let Some(message) = received_rx.recv().await {
+        if f(&message) {
+          return Ok(message);
+        }
+      }
+      anyhow::bail!("Colibri server stopped")
+    })
+    .await
+    .context("timed out waiting for Colibri message")?
+  }
+
+  /// Send a message to the connected client, as the bridge would.
+  pub async fn send(&self, message: ColibriMessage) -> Result<()> {
+    self
+      .client_tx
+      .lock()
+      .await
+      .as_ref()
+      .context("no Colibri client connected")?
+      .send(message)
+      .map_err(|_| anyhow::anyhow!("Colibri client disconnected"))
+  }
+}
+
+impl Drop for MockColibri {
+  fn drop(&mut self) {
+    self.task.abort();
+  }
+}
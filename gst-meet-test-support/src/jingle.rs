@@ -0,0 +1,192 @@
+use jitsi_xmpp_parsers::{
+  jingle::{Action, Content, Jingle},
+  jingle_ice_udp::Transport as IceUdpTransport,
+  jingle_rtp::Description as RtpDescription,
+  jingle_ssma::{self, Parameter, SsrcInfo},
+};
+use xmpp_parsers::{
+  jingle::{ContentId, Creator, Senders, SessionId},
+  jingle_grouping::{self, Content as GroupContent},
+  jingle_rtcp_fb::RtcpFb,
+  jingle_rtp::{self, PayloadType, RtcpMux},
+  jingle_rtp_hdrext::RtpHdrext,
+  Jid,
+};
+
+use crate::{
+  MockParticipant, AUDIO_LEVEL_HDREXT_ID, H264_CB_PT, H264_CH_PT, OPUS_PT, RED_PT,
+  TRANSPORT_CC_HDREXT_ID, VP8_PT, VP8_RTX_PT, VP9_PT, VP9_RTX_PT,
+};
+
+const RTP_HDREXT_SSRC_AUDIO_LEVEL: &str = "urn:ietf:params:rtp-hdrext:ssrc-audio-level";
+const RTP_HDREXT_TRANSPORT_CC: &str =
+  "http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01";
+const RTP_HDREXT_DEPENDENCY_DESCRIPTOR: &str =
+  "https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension";
+
+fn rtcp_fb(type_: &str, subtype: Option<&str>) -> RtcpFb {
+  RtcpFb {
+    type_: type_.to_owned(),
+    subtype: subtype.map(ToOwned::to_owned),
+  }
+}
+
+fn source(ssrc: u32, name: String, video_type: Option<&str>, owner: String) -> jingle_ssma::Source {
+  let mut source = jingle_ssma::Source::new(ssrc, Some(name), video_type.map(ToOwned::to_owned));
+  source.parameters.push(Parameter {
+    name: "msid".to_owned(),
+    value: Some(format!("{owner}-0 {ssrc}")),
+  });
+  source.info = Some(SsrcInfo { owner });
+  source
+}
+
+/// The sources that a session-initiate or source-add signals for the given participants.
+pub(crate) fn sources(
+  room: &str,
+  participants: &[MockParticipant],
+  media: &str,
+) -> Vec<jingle_ssma::Source> {
+  participants
+    .iter()
+    .filter_map(|participant| {
+      let owner = format!("{}/{}", room, participant.id);
+      if media == "audio" {
+        participant
+          .audio_ssrc
+          .map(|ssrc| source(ssrc, format!("{}-a0", participant.id), None, owner))
+      }
+      else {
+        participant.video_ssrc.map(|ssrc| {
+          source(
+            ssrc,
+            format!("{}-v0", participant.id),
+            Some("camera"),
+            owner,
+          )
+        })
+      }
+    })
+    .collect()
+}
+
+/// Build a session-initiate offering Opus, VP8 and VP9 (with RTX) as Jicofo would, including the
+/// sources of the given participants.
+pub(crate) fn session_initiate(
+  sid: &str,
+  initiator: Jid,
+  room: &str,
+  participants: &[MockParticipant],
+  transport: &IceUdpTransport,
+) -> Jingle {
+  let mut audio = RtpDescription::new("audio".to_owned());
+  let mut opus = PayloadType::new(OPUS_PT, "opus".to_owned(), 48000, 2);
+  opus.parameters = vec![
+    jingle_rtp::Parameter {
+      name: "minptime".to_owned(),
+      value: "10".to_owned(),
+    },
+    jingle_rtp::Parameter {
+      name: "useinbandfec".to_owned(),
+      value: "1".to_owned(),
+    },
+  ];
+  opus.rtcp_fbs = vec![rtcp_fb("transport-cc", None)];
+  let mut red = PayloadType::new(RED_PT, "red".to_owned(), 48000, 2);
+  red.parameters = vec![jingle_rtp::Parameter {
+    name: String::new(),
+    value: format!("{}/{}", OPUS_PT, OPUS_PT),
+  }];
+  audio.payload_types = vec![opus, red];
+  audio.hdrexts = vec![
+    RtpHdrext::new(
+      AUDIO_LEVEL_HDREXT_ID as u16,
+      RTP_HDREXT_SSRC_AUDIO_LEVEL.to_owned(),
+    ),
+    RtpHdrext::new(
+      TRANSPORT_CC_HDREXT_ID as u16,
+      RTP_HDREXT_TRANSPORT_CC.to_owned(),
+    ),
+  ];
+  audio.rtcp_mux = Some(RtcpMux);
+  audio.ssrcs = sources(room, participants, "audio");
+
+  let mut video = RtpDescription::new("video".to_owned());
+  video.payload_types = [("VP8", VP8_PT, VP8_RTX_PT), ("VP9", VP9_PT, VP9_RTX_PT)]
+    .into_iter()
+    .flat_map(|(name, pt, rtx_pt)| {
+      let mut codec = PayloadType::new(pt, name.to_owned(), 90000, 1);
+      codec.rtcp_fbs = vec![
+        rtcp_fb("ccm", Some("fir")),
+        rtcp_fb("nack", None),
+        rtcp_fb("nack", Some("pli")),
+        rtcp_fb("transport-cc", None),
+      ];
+      let mut rtx = PayloadType::new(rtx_pt, "rtx".to_owned(), 90000, 1);
+      rtx.parameters = vec![jingle_rtp::Parameter {
+        name: "apt".to_owned(),
+        value: pt.to_string(),
+      }];
+      [codec, rtx]
+    })
+    .collect();
+  for (pt, profile_level_id) in [(H264_CB_PT, "42e01f"), (H264_CH_PT, "640c1f")] {
+    let mut codec = PayloadType::new(pt, "H264".to_owned(), 90000, 1);
+    codec.parameters = [
+      ("profile-level-id", profile_level_id),
+      ("packetization-mode", "1"),
+      ("level-asymmetry-allowed", "1"),
+    ]
+    .into_iter()
+    .map(|(name, value)| jingle_rtp::Parameter {
+      name: name.to_owned(),
+      value: value.to_owned(),
+    })
+    .collect();
+    codec.rtcp_fbs = vec![rtcp_fb("nack", Some("pli")), rtcp_fb("transport-cc", None)];
+    video.payload_types.push(codec);
+  }
+  video.hdrexts = vec![
+    RtpHdrext::new(
+      TRANSPORT_CC_HDREXT_ID as u16,
+      RTP_HDREXT_TRANSPORT_CC.to_owned(),
+    ),
+    RtpHdrext::new(11, RTP_HDREXT_DEPENDENCY_DESCRIPTOR.to_owned()),
+  ];
+  video.rtcp_mux = Some(RtcpMux);
+  video.ssrcs = sources(room, participants, "video");
+
+  Jingle::new(Action::SessionInitiate, SessionId(sid.to_owned()))
+    .with_initiator(initiator)
+    .add_content(
+      Content::new(Creator::Initiator, ContentId("audio".to_owned()))
+        .with_senders(Senders::Both)
+        .with_description(audio)
+        .with_transport(transport.clone()),
+    )
+    .add_content(
+      Content::new(Creator::Initiator, ContentId("video".to_owned()))
+        .with_senders(Senders::Both)
+        .with_description(video)
+        .with_transport(transport.clone()),
+    )
+    .set_group(jingle_grouping::Group {
+      semantics: jingle_grouping::Semantics::Bundle,
+      contents: vec![GroupContent::new("audio"), GroupContent::new("video")],
+    })
+}
+
+/// Build a source-add signalling the sources of the given participants.
+pub(crate) fn source_add(sid: &str, room: &str, participants: &[MockParticipant]) -> Jingle {
+  let mut jingle = Jingle::new(Action::SourceAdd, SessionId(sid.to_owned()));
+  for media in ["audio", "video"] {
+    let mut description = RtpDescription::new(media.to_owned());
+    description.ssrcs = sources(room, participants, media);
+    if !description.ssrcs.is_empty() {
+      jingle = jingle.add_content(
+        Content::new(Creator::Initiator, ContentId(media.to_owned())).with_description(description),
+      );
+    }
+  }
+  jingle
+}
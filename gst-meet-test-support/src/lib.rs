@@ -0,0 +1,190 @@
+//! A scripted stand-in for a Jitsi Meet deployment (Prosody, Jicofo and a JVB) for testing
+//! lib-gst-meet end-to-end without a network.
+//!
+//! [MockXmppServer] plays the signalling side, [MockColibri] the bridge's Colibri WebSocket, and
+//! [LoopbackPeer] optionally the bridge's media side. [MockDeployment] wires them together.
+
+mod colibri;
+mod jingle;
+mod peer;
+mod xmpp;
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use jitsi_xmpp_parsers::{
+  jingle::{Jingle, Transport},
+  jingle_dtls_srtp::Fingerprint,
+  jingle_ice_udp::{Transport as IceUdpTransport, WebSocket},
+};
+use uuid::Uuid;
+use xmpp_parsers::{hashes::Algo, jingle_dtls_srtp::Setup};
+
+pub use crate::{
+  colibri::MockColibri,
+  peer::{LoopbackPeer, PeerStream},
+  xmpp::{MockXmppConfig, MockXmppServer, ServerEvent},
+};
+
+/// How long to wait for anything to happen before failing.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The payload types offered in the session-initiate.
+pub const OPUS_PT: u8 = 111;
+pub const RED_PT: u8 = 112;
+pub const VP8_PT: u8 = 100;
+pub const VP8_RTX_PT: u8 = 96;
+pub const VP9_PT: u8 = 101;
+pub const VP9_RTX_PT: u8 = 97;
+/// H.264 in the constrained baseline and constrained high profiles.
+pub const H264_CB_PT: u8 = 126;
+pub const H264_CH_PT: u8 = 127;
+
+/// The ID of the `ssrc-audio-level` header extension offered for audio.
+pub const AUDIO_LEVEL_HDREXT_ID: u8 = 1;
+/// The ID of the transport-cc header extension offered for audio and video.
+pub const TRANSPORT_CC_HDREXT_ID: u8 = 5;
+/// The `ssrc-audio-level` byte that the [LoopbackPeer] writes in every audio packet: voice
+/// activity, at -30 dBov.
+pub const PEER_AUDIO_LEVEL: u8 = 0x80 | 30;
+
+/// A remote participant in the mock conference.
+#[derive(Debug, Clone)]
+pub struct MockParticipant {
+  /// The endpoint ID, which is also the participant's MUC resource.
+  pub id: String,
+  pub nick: String,
+  pub audio_ssrc: Option<u32>,
+  pub video_ssrc: Option<u32>,
+  /// The video codecs advertised in the participant's presence. If empty, none are advertised.
+  pub video_codecs: Vec<String>,
+}
+
+impl MockParticipant {
+  pub fn new(id: &str, audio_ssrc: Option<u32>, video_ssrc: Option<u32>) -> Self {
+    Self {
+      id: id.to_owned(),
+      nick: format!("{} nick", id),
+      audio_ssrc,
+      video_ssrc,
+      video_codecs: vec![],
+    }
+  }
+
+  /// The streams a [LoopbackPeer] should send on behalf of this participant.
+  pub fn peer_streams(&self) -> Vec<PeerStream> {
+    self
+      .audio_ssrc
+      .map(PeerStream::Audio)
+      .into_iter()
+      .chain(self.video_ssrc.map(PeerStream::Video))
+      .collect()
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockDeploymentConfig {
+  pub xmpp_domain: String,
+  /// Username and password pairs accepted for SASL PLAIN. If empty, any credentials are
+  /// accepted.
+  pub accounts: Vec<(String, String)>,
+  /// Occupants of the room when the client joins.
+  pub participants: Vec<MockParticipant>,
+  /// Whether to run a [LoopbackPeer] so that ICE, DTLS and RTP actually flow. Without one, the
+  /// session-initiate carries a transport with no candidates, which is enough to test
+  /// signalling.
+  pub media: bool,
+}
+
+impl Default for MockDeploymentConfig {
+  fn default() -> Self {
+    Self {
+      xmpp_domain: "meet.example.com".to_owned(),
+      accounts: vec![],
+      participants: vec![],
+      media: false,
+    }
+  }
+}
+
+/// A Prosody, Jicofo and JVB for a single client to join.
+pub struct MockDeployment {
+  pub xmpp: MockXmppServer,
+  pub colibri: MockColibri,
+  pub peer: Option<LoopbackPeer>,
+}
+
+impl MockDeployment {
+  pub async fn start(config: MockDeploymentConfig) -> Result<Self> {
+    let colibri = MockColibri::start().await?;
+
+    let (peer, mut transport) = if config.media {
+      let streams: Vec<_> = config
+        .participants
+        .iter()
+        .flat_map(MockParticipant::peer_streams)
+        .collect();
+      let peer = LoopbackPeer::new(&streams)?;
+      let transport = peer.transport()?;
+      (Some(peer), transport)
+    }
+    else {
+      let mut transport = IceUdpTransport::new().with_fingerprint(Fingerprint {
+        hash: Algo::Sha_256,
+        setup: Some(Setup::Passive),
+        value: Uuid::new_v4()
+          .as_bytes()
+          .iter()
+          .chain(Uuid::new_v4().as_bytes())
+          .copied()
+          .collect(),
+      });
+      transport.ufrag = Some(Uuid::new_v4().simple().to_string()[..8].to_owned());
+      transport.pwd = Some(Uuid::new_v4().simple().to_string());
+      (None, transport)
+    };
+    transport.web_socket = Some(WebSocket {
+      url: colibri.url().to_owned(),
+    });
+
+    let xmpp = MockXmppServer::start(MockXmppConfig {
+      xmpp_domain: config.xmpp_domain,
+      accounts: config.accounts,
+      participants: config.participants,
+      transport,
+    })
+    .await?;
+
+    Ok(Self {
+      xmpp,
+      colibri,
+      peer,
+    })
+  }
+
+  pub fn web_socket_url(&self) -> &str {
+    self.xmpp.web_socket_url()
+  }
+
+  pub fn xmpp_domain(&self) -> &str {
+    self.xmpp.xmpp_domain()
+  }
+
+  /// Wait for the client's session-accept and, if there is a [LoopbackPeer], start ICE and media
+  /// towards the transport it signals.
+  pub async fn accept_session(&self) -> Result<Jingle> {
+    let session_accept = self.xmpp.session_accept().await?;
+    if let Some(peer) = &self.peer {
+      let transport = session_accept
+        .contents
+        .iter()
+        .find_map(|content| match &content.transport {
+          Some(Transport::IceUdp(transport)) => Some(transport),
+          _ => None,
+        })
+        .context("session-accept has no transport")?;
+      peer.connect(transport)?;
+    }
+    Ok(session_accept)
+  }
+}
@@ -0,0 +1,369 @@
+use std::{
+  collections::HashMap,
+  net::SocketAddr,
+  sync::{Arc, Mutex},
+  thread,
+  time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use gstreamer::prelude::{
+  ElementExt as _, ElementExtManual as _, GstBinExt as _, GstBinExtManual as _, ObjectExt as _,
+  PadExtManual as _,
+};
+use jitsi_xmpp_parsers::{
+  jingle_dtls_srtp::Fingerprint, jingle_ice_udp::Transport as IceUdpTransport,
+};
+use nice_gst_meet as nice;
+use sha2::{Digest as _, Sha256};
+use tokio::time::{sleep, timeout};
+use tracing::{debug, warn};
+use uuid::Uuid;
+use xmpp_parsers::{hashes::Algo, jingle_dtls_srtp::Setup, jingle_ice_udp};
+
+use crate::{AUDIO_LEVEL_HDREXT_ID, OPUS_PT, PEER_AUDIO_LEVEL, TIMEOUT, VP8_PT};
+
+/// A stream for the [LoopbackPeer] to send, as if it were being forwarded by the bridge from a
+/// remote participant.
+#[derive(Debug, Clone, Copy)]
+pub enum PeerStream {
+  /// Opus audio with the given SSRC.
+  Audio(u32),
+  /// VP8 video with the given SSRC.
+  Video(u32),
+}
+
+/// The media side of a JVB: an ICE agent and a DTLS-SRTP endpoint on the loopback interface.
+///
+/// It sends test audio (with [PEER_AUDIO_LEVEL] in the `ssrc-audio-level` header extension) and
+/// video for the configured [PeerStream]s, and counts the RTP packets
+/// it receives from the client per SSRC. RTCP feedback can be sent to the client with
+/// [LoopbackPeer::send_pli].
+pub struct LoopbackPeer {
+  pipeline: gstreamer::Pipeline,
+  agent: nice::Agent,
+  stream_id: u32,
+  ufrag: String,
+  pwd: String,
+  fingerprint: Vec<u8>,
+  received_packets: Arc<Mutex<HashMap<u32, u64>>>,
+  rtcp_src: gstreamer::Element,
+  main_loop: glib::MainLoop,
+}
+
+/// The SSRC that the [LoopbackPeer] sends RTCP feedback from.
+const RTCP_SSRC: u32 = 1;
+
+const COMPONENT_ID: u32 = 1;
+
+impl LoopbackPeer {
+  pub fn new(streams: &[PeerStream]) -> Result<Self> {
+    let main_context = glib::MainContext::new();
+    let main_loop = glib::MainLoop::new(Some(&main_context), false);
+    thread::Builder::new()
+      .name("loopback-peer-glib".to_owned())
+      .spawn({
+        let main_loop = main_loop.clone();
+        move || main_loop.run()
+      })?;
+
+    let agent = nice::Agent::new(&main_context, nice::Compatibility::Rfc5245);
+    agent.set_ice_tcp(false);
+    agent.set_upnp(false);
+    agent.set_controlling_mode(true);
+    let stream_id = agent.add_stream(1);
+    if !agent.gather_candidates(stream_id) {
+      bail!("nice_agent_gather_candidates failed");
+    }
+    let (ufrag, pwd) = agent
+      .local_credentials(stream_id)
+      .context("no local ICE credentials")?;
+
+    let dtls_key_pair = rcgen::KeyPair::generate()?;
+    let dtls_cert = rcgen::CertificateParams::new(vec!["gst-meet-test-peer".to_owned()])?
+      .self_signed(&dtls_key_pair)?;
+    let fingerprint = Sha256::digest(dtls_cert.der()).to_vec();
+
+    let pipeline = gstreamer::Pipeline::new();
+
+    let nicesrc = gstreamer::ElementFactory::make("nicesrc")
+      .property("stream", stream_id)
+      .property("component", COMPONENT_ID)
+      .property("agent", &agent)
+      .build()?;
+    let nicesink = gstreamer::ElementFactory::make("nicesink")
+      .property("stream", stream_id)
+      .property("component", COMPONENT_ID)
+      .property("agent", &agent)
+      .property("sync", false)
+      .property("async", false)
+      .build()?;
+    // A different connection ID from lib-gst-meet's, as both ends live in the same process.
+    let dtlssrtpdec = gstreamer::ElementFactory::make("dtlssrtpdec")
+      .property("connection-id", "gst-meet-test-peer")
+      .property(
+        "pem",
+        format!("{}\n{}", dtls_cert.pem(), dtls_key_pair.serialize_pem()),
+      )
+      .build()?;
+    let dtlssrtpenc = gstreamer::ElementFactory::make("dtlssrtpenc")
+      .property("connection-id", "gst-meet-test-peer")
+      .property("is-client", false)
+      .build()?;
+    let rtp_sink = gstreamer::ElementFactory::make("fakesink")
+      .property("async", false)
+      .build()?;
+    let rtcp_sink = gstreamer::ElementFactory::make("fakesink")
+      .property("async", false)
+      .build()?;
+    let rtpfunnel = gstreamer::ElementFactory::make("rtpfunnel").build()?;
+    let rtcp_src = gstreamer::ElementFactory::make("appsrc")
+      .property("is-live", true)
+      .property("format", gstreamer::Format::Time)
+      .property("do-timestamp", true)
+      .property(
+        "caps",
+        gstreamer::Caps::new_empty_simple("application/x-rtcp"),
+      )
+      .build()?;
+    pipeline.add_many([
+      &nicesrc,
+      &nicesink,
+      &dtlssrtpdec,
+      &dtlssrtpenc,
+      &rtp_sink,
+      &rtcp_sink,
+      &rtpfunnel,
+      &rtcp_src,
+    ])?;
+
+    nicesrc.link(&dtlssrtpdec)?;
+    dtlssrtpdec.link_pads(Some("rtp_src"), &rtp_sink, None)?;
+    dtlssrtpdec.link_pads(Some("rtcp_src"), &rtcp_sink, None)?;
+    rtpfunnel.link_pads(None, &dtlssrtpenc, Some("rtp_sink_0"))?;
+    rtcp_src.link_pads(None, &dtlssrtpenc, Some("rtcp_sink_0"))?;
+    dtlssrtpenc.link_pads(Some("src"), &nicesink, Some("sink"))?;
+
+    let received_packets = Arc::new(Mutex::new(HashMap::new()));
+    {
+      let received_packets = received_packets.clone();
+      rtp_sink
+        .static_pad("sink")
+        .context("fakesink has no sink pad")?
+        .add_probe(gstreamer::PadProbeType::BUFFER, move |_pad, info| {
+          if let Some(gstreamer::PadProbeData::Buffer(buffer)) = &info.data {
+            if let Ok(map) = buffer.map_readable() {
+              if map.len() >= 12 {
+                let ssrc = u32::from_be_bytes([map[8], map[9], map[10], map[11]]);
+                *received_packets.lock().unwrap().entry(ssrc).or_default() += 1;
+              }
+            }
+          }
+          gstreamer::PadProbeReturn::Ok
+        });
+    }
+
+    for stream in streams {
+      let description = match stream {
+        PeerStream::Audio(ssrc) => format!(
+          "audiotestsrc is-live=true ! opusenc ! rtpopuspay pt={} ssrc={}",
+          OPUS_PT, ssrc
+        ),
+        PeerStream::Video(ssrc) => format!(
+          "videotestsrc is-live=true ! video/x-raw,width=320,height=180 ! vp8enc deadline=1 ! rtpvp8pay pt={} ssrc={} picture-id-mode=15-bit",
+          VP8_PT, ssrc
+        ),
+      };
+      let bin = gstreamer::parse::bin_from_description(&description, true)?;
+      if let PeerStream::Audio(_) = stream {
+        bin
+          .static_pad("src")
+          .context("audio bin has no src pad")?
+          .add_probe(gstreamer::PadProbeType::BUFFER, |_pad, info| {
+            if let Some(gstreamer::PadProbeData::Buffer(buffer)) = &mut info.data {
+              if let Some(packet) = buffer
+                .map_readable()
+                .ok()
+                .and_then(|map| with_audio_level(&map, PEER_AUDIO_LEVEL))
+              {
+                let mut new_buffer = gstreamer::Buffer::from_slice(packet);
+                {
+                  let new_buffer = new_buffer.get_mut().unwrap();
+                  new_buffer.set_pts(buffer.pts());
+                  new_buffer.set_dts(buffer.dts());
+                }
+                *buffer = new_buffer;
+              }
+            }
+            gstreamer::PadProbeReturn::Ok
+          });
+      }
+      pipeline.add(&bin)?;
+      bin.link(&rtpfunnel)?;
+    }
+
+    Ok(Self {
+      pipeline,
+      agent,
+      stream_id,
+      ufrag,
+      pwd,
+      fingerprint,
+      received_packets,
+      rtcp_src,
+      main_loop,
+    })
+  }
+
+  /// The transport to signal in the session-initiate.
+  pub fn transport(&self) -> Result<IceUdpTransport> {
+    let mut transport = IceUdpTransport::new().with_fingerprint(Fingerprint {
+      hash: Algo::Sha_256,
+      setup: Some(Setup::Passive),
+      value: self.fingerprint.clone(),
+    });
+    transport.ufrag = Some(self.ufrag.clone());
+    transport.pwd = Some(self.pwd.clone());
+    for c in self.agent.local_candidates(self.stream_id, COMPONENT_ID) {
+      let addr = c.addr();
+      transport.candidates.push(jingle_ice_udp::Candidate {
+        component: c.component_id() as u8,
+        foundation: c.foundation()?.to_owned(),
+        generation: 0,
+        id: Uuid::new_v4().to_string(),
+        ip: addr.ip(),
+        port: addr.port(),
+        priority: c.priority(),
+        protocol: "udp".to_owned(),
+        type_: jingle_ice_udp::Type::Host,
+        rel_addr: None,
+        rel_port: None,
+        network: None,
+      });
+    }
+    Ok(transport)
+  }
+
+  /// Configure the client's ICE credentials and candidates from its session-accept, and start
+  /// connectivity checks and media.
+  pub fn connect(&self, remote: &IceUdpTransport) -> Result<()> {
+    let (ufrag, pwd) = remote
+      .ufrag
+      .as_ref()
+      .zip(remote.pwd.as_ref())
+      .context("session-accept transport has no ICE credentials")?;
+    if !self
+      .agent
+      .set_remote_credentials(self.stream_id, ufrag, pwd)
+    {
+      bail!("nice_agent_set_remote_credentials failed");
+    }
+    let candidates: Vec<_> = remote
+      .candidates
+      .iter()
+      .map(|c| {
+        let mut candidate = nice::Candidate::new(nice::CandidateType::Host);
+        candidate.set_stream_id(self.stream_id);
+        candidate.set_component_id(c.component as u32);
+        candidate.set_foundation(&c.foundation);
+        candidate.set_addr(SocketAddr::new(c.ip, c.port));
+        candidate.set_priority(c.priority);
+        candidate.set_username(ufrag);
+        candidate.set_password(pwd);
+        candidate
+      })
+      .collect();
+    let candidate_refs: Vec<_> = candidates.iter().collect();
+    let added = self
+      .agent
+      .set_remote_candidates(self.stream_id, COMPONENT_ID, &candidate_refs);
+    if added < candidates.len() as i32 {
+      warn!("some remote candidates failed to add: {}", added);
+    }
+
+    debug!("starting loopback peer pipeline");
+    self.pipeline.set_state(gstreamer::State::Playing)?;
+    Ok(())
+  }
+
+  /// The number of RTP packets received from the client with the given SSRC.
+  pub fn received_packets(&self, ssrc: u32) -> u64 {
+    self
+      .received_packets
+      .lock()
+      .unwrap()
+      .get(&ssrc)
+      .copied()
+      .unwrap_or_default()
+  }
+
+  /// The SSRCs of all RTP streams received from the client so far.
+  pub fn received_ssrcs(&self) -> Vec<u32> {
+    self
+      .received_packets
+      .lock()
+      .unwrap()
+      .keys()
+      .copied()
+      .collect()
+  }
+
+  /// Send the client a PLI for one of its SSRCs, in a compound RTCP packet after an empty
+  /// receiver report, as a bridge does when a receiver needs a keyframe.
+  pub fn send_pli(&self, media_ssrc: u32) -> Result<()> {
+    let mut packet = vec![0x80, 201, 0, 1];
+    packet.extend_from_slice(&RTCP_SSRC.to_be_bytes());
+    packet.extend_from_slice(&[0x81, 206, 0, 2]);
+    packet.extend_from_slice(&RTCP_SSRC.to_be_bytes());
+    packet.extend_from_slice(&media_ssrc.to_be_bytes());
+    let flow_return: gstreamer::FlowReturn = self
+      .rtcp_src
+      .emit_by_name("push-buffer", &[&gstreamer::Buffer::from_slice(packet)]);
+    flow_return.into_result()?;
+    Ok(())
+  }
+
+  /// Wait until at least `count` RTP packets have been received from the client with the given
+  /// SSRC.
+  pub async fn wait_for_packets(&self, ssrc: u32, count: u64) -> Result<()> {
+    timeout(TIMEOUT, async {
+      while self.received_packets(ssrc) < count {
+        sleep(Duration::from_millis(50)).await;
+      }
+    })
+    .await
+    .with_context(|| {
+      format!(
+        "timed out waiting for {} packets with SSRC {} (received {})",
+        count,
+        ssrc,
+        self.received_packets(ssrc)
+      )
+    })
+  }
+}
+
+/// Add a one-byte `ssrc-audio-level` header extension to an RTP packet that has no header
+/// extension yet.
+fn with_audio_level(packet: &[u8], level: u8) -> Option<Vec<u8>> {
+  if packet.len() < 12 || packet[0] & 0x10 != 0 {
+    return None;
+  }
+  let header_len = 12 + 4 * (packet[0] & 0x0f) as usize;
+  if packet.len() < header_len {
+    return None;
+  }
+  let mut extended = Vec::with_capacity(packet.len() + 8);
+  extended.extend_from_slice(&packet[..header_len]);
+  extended[0] |= 0x10;
+  extended.extend_from_slice(&[0xbe, 0xde, 0, 1, AUDIO_LEVEL_HDREXT_ID << 4, level, 0, 0]);
+  extended.extend_from_slice(&packet[header_len..]);
+  Some(extended)
+}
+
+impl Drop for LoopbackPeer {
+  fn drop(&mut self) {
+    let _ = self.pipeline.set_state(gstreamer::State::Null);
+    self.main_loop.quit();
+  }
+}
@@ -0,0 +1,611 @@
+use std::{collections::HashMap, convert::TryFrom, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures::{SinkExt as _, StreamExt as _};
+use jitsi_xmpp_parsers::{
+  jingle::{Action, Jingle},
+  jingle_ice_udp::Transport as IceUdpTransport,
+};
+use minidom::ElementBuilder;
+use tokio::{
+  net::{TcpListener, TcpStream},
+  sync::{mpsc, Mutex},
+  task::JoinHandle,
+  time::timeout,
+};
+use tokio_tungstenite::tungstenite::{
+  handshake::server::{Request, Response},
+  http::HeaderValue,
+  Message,
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+use xmpp_parsers::{Element, Jid};
+
+use crate::{jingle, MockParticipant, TIMEOUT};
+
+const NS_CLIENT: &str = "jabber:client";
+const NS_FRAMING: &str = "urn:ietf:params:xml:ns:xmpp-framing";
+const NS_STREAMS: &str = "http://etherx.jabber.org/streams";
+const NS_SASL: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const NS_BIND: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+const NS_DISCO_INFO: &str = "http://jabber.org/protocol/disco#info";
+const NS_EXTDISCO: &str = "urn:xmpp:extdisco:2";
+const NS_FOCUS: &str = "http://jitsi.org/protocol/focus";
+const NS_JINGLE: &str = "urn:xmpp:jingle:1";
+const NS_MUC_USER: &str = "http://jabber.org/protocol/muc#user";
+const NS_NICK: &str = "http://jabber.org/protocol/nick";
+
+#[derive(Debug, Clone)]
+pub struct MockXmppConfig {
+  pub xmpp_domain: String,
+  /// Username and password pairs accepted for SASL PLAIN. If empty, any credentials are
+  /// accepted.
+  pub accounts: Vec<(String, String)>,
+  /// Occupants of the room when the client joins.
+  pub participants: Vec<MockParticipant>,
+  /// The transport signalled in the session-initiate.
+  pub transport: IceUdpTransport,
+}
+
+/// Something the client did, in the order the server observed it.
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+  Authenticated {
+    mechanism: String,
+    username: Option<String>,
+  },
+  AuthenticationFailed {
+    mechanism: String,
+    username: Option<String>,
+  },
+  ConferenceRequested {
+    room: String,
+    properties: HashMap<String, String>,
+  },
+  JoinedMuc {
+    presence: Element,
+  },
+  /// A presence sent after joining the MUC, for example to update mute state.
+  Presence(Element),
+  SessionAccept(Jingle),
+  SourceAdd(Jingle),
+  SourceRemove(Jingle),
+  /// Any other stanza.
+  Stanza(Element),
+}
+
+struct Client {
+  tx: mpsc::UnboundedSender<Element>,
+  jid: Option<String>,
+  room: Option<String>,
+  sid: Option<String>,
+}
+
+/// A stand-in for Prosody and Jicofo, speaking XMPP over WebSocket.
+///
+/// It scripts the server side of the connection and conference join: SASL, resource binding,
+/// disco, extdisco, the focus conference IQ, MUC presence and the Jingle session-initiate.
+pub struct MockXmppServer {
+  web_socket_url: String,
+  config: MockXmppConfig,
+  events_rx: Mutex<mpsc::UnboundedReceiver<ServerEvent>>,
+  client: Arc<Mutex<Option<Client>>>,
+  task: JoinHandle<()>,
+}
+
+impl MockXmppServer {
+  pub async fn start(config: MockXmppConfig) -> Result<Self> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let web_socket_url = format!("ws://{}/xmpp-websocket", listener.local_addr()?);
+
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let client = Arc::new(Mutex::new(None));
+
+    let task = {
+      let config = config.clone();
+      let client = client.clone();
+      tokio::spawn(async move {
+        while let Ok((stream, addr)) = listener.accept().await {
+          debug!("XMPP connection from {}", addr);
+          let config = config.clone();
+          let client = client.clone();
+          let events_tx = events_tx.clone();
+          tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, config, client, events_tx).await {
+              warn!("mock XMPP connection failed: {:?}", e);
+            }
+          });
+        }
+      })
+    };
+
+    Ok(Self {
+      web_socket_url,
+      config,
+      events_rx: Mutex::new(events_rx),
+      client,
+      task,
+    })
+  }
+
+  pub fn web_socket_url(&self) -> &str {
+    &self.web_socket_url
+  }
+
+  pub fn xmpp_domain(&self) -> &str {
+    &self.config.xmpp_domain
+  }
+
+  /// Wait for the next event.
+  pub async fn next_event(&self) -> Result<ServerEvent> {
+    timeout(TIMEOUT, self.events_rx.lock().await.recv())
+      .await
+      .context("timed out waiting for XMPP event")?
+      .context("XMPP server stopped")
+  }
+
+  /// Wait for an event matching `f`, discarding any others.
+  pub async fn wait_for<T>(&self, f: impl Fn(ServerEvent) -> Option<T>) -> Result<T> {
+    timeout(TIMEOUT, async {
+      let mut events_rx = self.events_rx.lock().await;
+      while let Some(event) = events_rx.recv().await {
+        if let Some(t) = f(event) {
+          return Ok(t);
+        }
+      }
+      Err(anyhow!("XMPP server stopped"))
+    })
+    .await
+    .context("timed out waiting for XMPP event")?
+  }
+
+  /// Wait for the client's session-accept.
+  pub async fn session_accept(&self) -> Result<Jingle> {
+    self
+      .wait_for(|event| match event {
+        ServerEvent::SessionAccept(jingle) => Some(jingle),
+        _ => None,
+      })
+      .await
+  }
+
+  /// Send a stanza to the connected client as-is.
+  pub async fn send(&self, element: Element) -> Result<()> {
+    let locked_client = self.client.lock().await;
+    let client = locked_client.as_ref().context("no XMPP client connected")?;
+    send(&client.tx, element)
+  }
+
+  /// A new occupant joins the room: send their presence, and a source-add for their sources.
+  pub async fn add_participant(&self, participant: &MockParticipant) -> Result<()> {
+    let locked_client = self.client.lock().await;
+    let client = locked_client.as_ref().context("no XMPP client connected")?;
+    let (jid, room) = client
+      .jid
+      .as_ref()
+      .zip(client.room.as_ref())
+      .context("client has not joined the MUC")?;
+    send(
+      &client.tx,
+      participant_presence(&self.config, room, jid, participant, false),
+    )?;
+    if let Some(sid) = &client.sid {
+      let source_add = jingle::source_add(sid, room, std::slice::from_ref(participant));
+      send(
+        &client.tx,
+        iq(
+          "set",
+          &Uuid::new_v4().to_string(),
+          Some(&format!("{}/focus", room)),
+          Some(jid),
+        )
+        .append(Element::from(source_add))
+        .build(),
+      )?;
+    }
+    Ok(())
+  }
+
+  /// An occupant leaves the room.
+  pub async fn remove_participant(&self, participant: &MockParticipant) -> Result<()> {
+    let locked_client = self.client.lock().await;
+    let client = locked_client.as_ref().context("no XMPP client connected")?;
+    let (jid, room) = client
+      .jid
+      .as_ref()
+      .zip(client.room.as_ref())
+      .context("client has not joined the MUC")?;
+    send(
+      &client.tx,
+      participant_presence(&self.config, room, jid, participant, true),
+    )
+  }
+}
+
+impl Drop for MockXmppServer {
+  fn drop(&mut self) {
+    self.task.abort();
+  }
+}
+
+fn send(tx: &mpsc::UnboundedSender<Element>, element: Element) -> Result<()> {
+  tx.send(element)
+    .map_err(|_| anyhow!("XMPP client disconnected"))
+}
+
+fn iq(type_: &str, id: &str, from: Option<&str>, to: Option<&str>) -> ElementBuilder {
+  Element::builder("iq", NS_CLIENT)
+    .attr("type", type_)
+    .attr("id", id)
+    .attr("from", from)
+    .attr("to", to)
+}
+
+fn participant_jid(config: &MockXmppConfig, participant: &MockParticipant) -> String {
+  format!("{}@{}/gst-meet-test", participant.id, config.xmpp_domain)
+}
+
+fn participant_presence(
+  config: &MockXmppConfig,
+  room: &str,
+  to: &str,
+  participant: &MockParticipant,
+  unavailable: bool,
+) -> Element {
+  Element::builder("presence", NS_CLIENT)
+    .attr("from", format!("{}/{}", room, participant.id))
+    .attr("to", to)
+    .attr("type", unavailable.then_some("unavailable"))
+    .append(
+      Element::builder("x", NS_MUC_USER)
+        .append(
+          Element::builder("item", NS_MUC_USER)
+            .attr("affiliation", "none")
+            .attr("role", if unavailable { "none" } else { "participant" })
+            .attr("jid", participant_jid(config, participant))
+            .build(),
+        )
+        .build(),
+    )
+    .append(
+      Element::builder("nick", NS_NICK)
+        .append(participant.nick.as_str())
+        .build(),
+    )
+    .append_all((!participant.video_codecs.is_empty()).then(|| {
+      Element::builder("jitsi_participant_codecList", NS_CLIENT)
+        .append(participant.video_codecs.join(","))
+        .build()
+    }))
+    .build()
+}
+
+async fn handle_connection(
+  stream: TcpStream,
+  config: MockXmppConfig,
+  client: Arc<Mutex<Option<Client>>>,
+  events_tx: mpsc::UnboundedSender<ServerEvent>,
+) -> Result<()> {
+  let websocket =
+    tokio_tungstenite::accept_hdr_async(stream, |_request: &Request, mut response: Response| {
+      response
+        .headers_mut()
+        .insert("sec-websocket-protocol", HeaderValue::from_static("xmpp"));
+      Ok(response)
+    })
+    .await?;
+  let (mut sink, mut stream) = websocket.split();
+
+  let (tx, mut rx) = mpsc::unbounded_channel::<Element>();
+  tokio::spawn(async move {
+    while let Some(element) = rx.recv().await {
+      let mut bytes = Vec::new();
+      if element.write_to(&mut bytes).is_err() {
+        break;
+      }
+      let xml = String::from_utf8_lossy(&bytes).into_owned();
+      debug!("MOCK XMPP >>> {}", xml);
+      if sink.send(Message::Text(xml)).await.is_err() {
+        break;
+      }
+    }
+  });
+
+  *client.lock().await = Some(Client {
+    tx: tx.clone(),
+    jid: None,
+    room: None,
+    sid: None,
+  });
+
+  let mut authenticated = false;
+
+  while let Some(message) = stream.next().await {
+    let xml = match message? {
+      Message::Text(xml) => xml,
+      Message::Close(_) => break,
+      _ => continue,
+    };
+    debug!("MOCK XMPP <<< {}", xml);
+    let element: Element = xml.parse()?;
+
+    if element.is("open", NS_FRAMING) {
+      send(
+        &tx,
+        Element::builder("open", NS_FRAMING)
+          .attr("from", config.xmpp_domain.as_str())
+          .attr("id", Uuid::new_v4().to_string())
+          .attr("version", "1.0")
+          .build(),
+      )?;
+      let features = if authenticated {
+        Element::builder("features", NS_STREAMS)
+          .append(Element::builder("bind", NS_BIND).build())
+          .build()
+      }
+      else {
+        Element::builder("features", NS_STREAMS)
+          .append(
+            Element::builder("mechanisms", NS_SASL)
+              .append(
+                Element::builder("mechanism", NS_SASL)
+                  .append("ANONYMOUS")
+                  .build(),
+              )
+              .append(
+                Element::builder("mechanism", NS_SASL)
+                  .append("PLAIN")
+                  .build(),
+              )
+              .build(),
+          )
+          .build()
+      };
+      send(&tx, features)?;
+    }
+    else if element.is("auth", NS_SASL) {
+      let mechanism = element.attr("mechanism").unwrap_or_default().to_owned();
+      let (username, password) = if mechanism == "PLAIN" {
+        let data = BASE64.decode(element.text().trim())?;
+        let mut parts = data.split(|b| *b == 0).skip(1);
+        (
+          parts
+            .next()
+            .map(|s| String::from_utf8_lossy(s).into_owned()),
+          parts
+            .next()
+            .map(|s| String::from_utf8_lossy(s).into_owned()),
+        )
+      }
+      else {
+        (None, None)
+      };
+      let accepted = config.accounts.is_empty()
+        || mechanism != "PLAIN"
+        || config
+          .accounts
+          .iter()
+          .any(|(u, p)| Some(u) == username.as_ref() && Some(p) == password.as_ref());
+      if accepted {
+        authenticated = true;
+        send(&tx, Element::builder("success", NS_SASL).build())?;
+        let _ = events_tx.send(ServerEvent::Authenticated {
+          mechanism,
+          username,
+        });
+      }
+      else {
+        send(
+          &tx,
+          Element::builder("failure", NS_SASL)
+            .append(Element::builder("not-authorized", NS_SASL).build())
+            .build(),
+        )?;
+        let _ = events_tx.send(ServerEvent::AuthenticationFailed {
+          mechanism,
+          username,
+        });
+      }
+    }
+    else if element.is("iq", NS_CLIENT) {
+      handle_iq(&config, &client, &tx, &events_tx, element).await?;
+    }
+    else if element.is("presence", NS_CLIENT) {
+      handle_presence(&config, &client, &tx, &events_tx, element).await?;
+    }
+    else {
+      let _ = events_tx.send(ServerEvent::Stanza(element));
+    }
+  }
+
+  Ok(())
+}
+
+async fn handle_iq(
+  config: &MockXmppConfig,
+  client: &Arc<Mutex<Option<Client>>>,
+  tx: &mpsc::UnboundedSender<Element>,
+  events_tx: &mpsc::UnboundedSender<ServerEvent>,
+  element: Element,
+) -> Result<()> {
+  let type_ = element.attr("type").unwrap_or_default();
+  let id = element.attr("id").unwrap_or_default().to_owned();
+  let to = element.attr("to").map(ToOwned::to_owned);
+  let from = element
+    .attr("from")
+    .map(ToOwned::to_owned)
+    .or(client.lock().await.as_ref().and_then(|c| c.jid.clone()));
+  let payload = element.children().next().cloned();
+
+  if type_ == "result" || type_ == "error" {
+    let _ = events_tx.send(ServerEvent::Stanza(element));
+    return Ok(());
+  }
+
+  let reply = match payload {
+    Some(payload) if payload.is("bind", NS_BIND) => {
+      // lib-gst-meet derives its endpoint ID from the part of the node before the first '-'.
+      let jid = format!("{}@{}/gst-meet-test", Uuid::new_v4(), config.xmpp_domain);
+      if let Some(client) = client.lock().await.as_mut() {
+        client.jid = Some(jid.clone());
+      }
+      iq("result", &id, None, Some(&jid))
+        .append(
+          Element::builder("bind", NS_BIND)
+            .append(
+              Element::builder("jid", NS_BIND)
+                .append(jid.as_str())
+                .build(),
+            )
+            .build(),
+        )
+        .build()
+    },
+    Some(payload) if payload.is("query", NS_DISCO_INFO) => {
+      iq("result", &id, to.as_deref(), from.as_deref())
+        .append(
+          Element::builder("query", NS_DISCO_INFO)
+            .append(
+              Element::builder("identity", NS_DISCO_INFO)
+                .attr("category", "server")
+                .attr("type", "im")
+                .build(),
+            )
+            .append(
+              Element::builder("feature", NS_DISCO_INFO)
+                .attr("var", NS_DISCO_INFO)
+                .build(),
+            )
+            .build(),
+        )
+        .build()
+    },
+    Some(payload) if payload.is("services", NS_EXTDISCO) => {
+      iq("result", &id, to.as_deref(), from.as_deref())
+        .append(Element::builder("services", NS_EXTDISCO).build())
+        .build()
+    },
+    Some(payload) if payload.is("conference", NS_FOCUS) => {
+      let room = payload.attr("room").unwrap_or_default().to_owned();
+      let properties = payload
+        .children()
+        .filter(|child| child.is("property", NS_FOCUS))
+        .filter_map(|child| {
+          Some((
+            child.attr("name")?.to_owned(),
+            child.attr("value")?.to_owned(),
+          ))
+        })
+        .collect();
+      let _ = events_tx.send(ServerEvent::ConferenceRequested {
+        room: room.clone(),
+        properties,
+      });
+      // lib-gst-meet matches the focus's response on the exact `from` it sent the request to.
+      iq("result", &id, to.as_deref(), from.as_deref())
+        .append(
+          Element::builder("conference", NS_FOCUS)
+            .attr("ready", "true")
+            .attr("room", room)
+            .build(),
+        )
+        .build()
+    },
+    Some(payload) if payload.is("jingle", NS_JINGLE) => {
+      let jingle = Jingle::try_from(payload).map_err(|e| anyhow!("invalid Jingle: {:?}", e))?;
+      let reply = iq("result", &id, to.as_deref(), from.as_deref()).build();
+      let event = match jingle.action {
+        Action::SessionAccept => ServerEvent::SessionAccept(jingle),
+        Action::SourceAdd => ServerEvent::SourceAdd(jingle),
+        Action::SourceRemove => ServerEvent::SourceRemove(jingle),
+        _ => ServerEvent::Stanza(element),
+      };
+      send(tx, reply)?;
+      let _ = events_tx.send(event);
+      return Ok(());
+    },
+    _ => {
+      // Pings and anything else we don't script get an empty result.
+      let _ = events_tx.send(ServerEvent::Stanza(element));
+      iq("result", &id, to.as_deref(), from.as_deref()).build()
+    },
+  };
+
+  send(tx, reply)
+}
+
+async fn handle_presence(
+  config: &MockXmppConfig,
+  client: &Arc<Mutex<Option<Client>>>,
+  tx: &mpsc::UnboundedSender<Element>,
+  events_tx: &mpsc::UnboundedSender<ServerEvent>,
+  element: Element,
+) -> Result<()> {
+  let mut locked_client = client.lock().await;
+  let client = locked_client.as_mut().context("no client")?;
+  if client.room.is_some() {
+    let _ = events_tx.send(ServerEvent::Presence(element));
+    return Ok(());
+  }
+
+  let to = element
+    .attr("to")
+    .context("MUC presence has no to")?
+    .to_owned();
+  let room = to.split('/').next().context("invalid MUC JID")?.to_owned();
+  let jid = client.jid.clone().context("presence before bind")?;
+  client.room = Some(room.clone());
+  let _ = events_tx.send(ServerEvent::JoinedMuc { presence: element });
+
+  // Prosody sends the existing occupants' presence before the self-presence, but lib-gst-meet
+  // only tracks occupants once it has seen its self-presence, so they are sent afterwards.
+  send(
+    tx,
+    Element::builder("presence", NS_CLIENT)
+      .attr("from", to.as_str())
+      .attr("to", jid.as_str())
+      .append(
+        Element::builder("x", NS_MUC_USER)
+          .append(
+            Element::builder("item", NS_MUC_USER)
+              .attr("affiliation", "none")
+              .attr("role", "participant")
+              .attr("jid", jid.as_str())
+              .build(),
+          )
+          .append(
+            Element::builder("status", NS_MUC_USER)
+              .attr("code", "110")
+              .build(),
+          )
+          .build(),
+      )
+      .build(),
+  )?;
+
+  for participant in &config.participants {
+    send(
+      tx,
+      participant_presence(config, &room, &jid, participant, false),
+    )?;
+  }
+
+  let sid = Uuid::new_v4().to_string();
+  client.sid = Some(sid.clone());
+  let focus = format!("{}/focus", room);
+  let session_initiate = jingle::session_initiate(
+    &sid,
+    focus.parse::<Jid>()?,
+    &room,
+    &config.participants,
+    &config.transport,
+  );
+  send(
+    tx,
+    iq("set", &Uuid::new_v4().to_string(), Some(&focus), Some(&jid))
+      .append(Element::from(session_initiate))
+      .build(),
+  )
+}
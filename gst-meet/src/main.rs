@@ -11,7 +11,9 @@ use gstreamer::{
 };
 use http::Uri;
 use lib_gst_meet::{
-  init_tracing, Authentication, Connection, JitsiConference, JitsiConferenceConfig, MediaType,
+  init_tracing, parse_video_codecs, AudioRecvConfig, AudioSendConfig, Authentication, BitrateUnit,
+  CodecRegistry, Connection, JitsiConference, JitsiConferenceConfig, JitterBufferConfig, MediaType,
+  ScalabilityMode, VideoScalePolicy,
 };
 use structopt::StructOpt;
 use tokio::{signal::ctrl_c, task, time::timeout};
@@ -59,19 +61,78 @@ struct Opt {
   #[structopt(
     long,
     default_value = "vp8",
-    help = "The codec to transmit and receive video using. One of: av1, vp9, vp8, h264"
+    help = "The codecs to transmit and receive video using, in order of preference and separated by commas. The first one supported by the conference is used for sending. Any of: av1, vp9, vp8, h264"
   )]
   video_codec: String,
 
+  #[structopt(
+    long,
+    help = "The H.264 profile that the video encoder produces, e.g. constrained-baseline or high. The offered H.264 payload type with this profile is used."
+  )]
+  h264_profile: Option<String>,
+
+  #[structopt(
+    long,
+    number_of_values = 1,
+    help = "A decoder to use for a codec, e.g. 'h264=openh264dec' or 'av1=dav1ddec max-threads=4'. Can be given several times; for each codec, the first decoder that is installed is used, falling back to decodebin3 if none is."
+  )]
+  decoder: Vec<String>,
+
   #[structopt(long, default_value = "gst-meet")]
   nick: String,
 
   #[structopt(long)]
   region: Option<String>,
 
-  #[structopt(long)]
+  #[structopt(
+    long,
+    help = "A GStreamer pipeline containing elements named 'audio' and/or 'video', which are sent to the conference. If it also contains a capsfilter named 'video_caps' between a videoscale and the video encoder, its height is lowered to follow the bridge's sender video constraints."
+  )]
   send_pipeline: Option<String>,
 
+  #[structopt(
+    long,
+    help = "A GStreamer pipeline containing an element named 'video', which is sent as an additional desktop (screenshare) video source alongside the video from --send-pipeline. It must be encoded in the same codec."
+  )]
+  send_desktop_pipeline: Option<String>,
+
+  #[structopt(
+    long,
+    help = "Join without sending any media. No payloaders or send SSRCs are created, so this is cheaper than muting, and other participants will not see a tile for us. Cannot be combined with --send-pipeline."
+  )]
+  receive_only: bool,
+
+  #[structopt(
+    long,
+    help = "Only negotiate audio. No video elements are created, and the bridge is asked not to forward any video to us."
+  )]
+  audio_only: bool,
+
+  #[structopt(
+    long,
+    default_value = "1",
+    help = "The number of simulcast layers to send video in, from 1 (no simulcast) to 3. With more than one, the send pipeline must contain an encoder for each layer, named 'video_0' (lowest resolution) to 'video_<N-1>', instead of a single 'video' element."
+  )]
+  simulcast_layers: u8,
+
+  #[structopt(
+    long,
+    help = "Send AV1 video as SVC in this scalability mode (L1T2 or L1T3), with the dependency descriptor header extension. The video encoder must be configured with the matching temporal layering. Only single spatial layer modes are supported, and VP9 only in L1T1."
+  )]
+  scalability_mode: Option<ScalabilityMode>,
+
+  #[structopt(
+    long,
+    help = "Estimate the available send bandwidth from the bridge's transport-cc feedback (requires the rtpgccbwe element from gst-plugins-rs). If the send pipeline contains an encoder named 'video_encoder', its bitrate follows the estimate, in the unit given by --video-encoder-bitrate-unit."
+  )]
+  bandwidth_estimation: bool,
+
+  #[structopt(
+    long,
+    help = "The unit of the bitrate property of the 'video_encoder' element: bps (e.g. vp8enc, vp9enc, openh264enc, rav1enc) or kbps (e.g. x264enc, x265enc, av1enc). Required for its bitrate to follow the bandwidth estimate."
+  )]
+  video_encoder_bitrate_unit: Option<BitrateUnit>,
+
   #[structopt(
     long,
     help = "A GStreamer pipeline which will be instantiated at startup. If an element named 'audio' is found, every remote participant's audio will be linked to it (and any 'audio' element in the recv-pipeline-participant-template will be ignored). If an element named 'video' is found, every remote participant's video will be linked to it (and any 'video' element in the recv-pipeline-participant-template will be ignored)."
@@ -109,6 +170,18 @@ struct Opt {
   )]
   video_type: Option<String>,
 
+  #[structopt(
+    long,
+    help = "Pass received audio to the recv-pipeline still encoded (depayloaded Opus) instead of decoding it."
+  )]
+  recv_audio_encoded: bool,
+
+  #[structopt(
+    long,
+    help = "Pass received video to the recv-pipeline still encoded (depayloaded, in the negotiated codec) instead of decoding and scaling it."
+  )]
+  recv_video_encoded: bool,
+
   #[structopt(
     long,
     default_value = "1280",
@@ -123,6 +196,37 @@ struct Opt {
   )]
   recv_video_scale_height: u16,
 
+  #[structopt(
+    long,
+    default_value = "stretch",
+    help = "How to scale received video to the recv-video-scale size. One of: stretch (change the aspect ratio to fill it), letterbox (fill it, adding black borders), fit (scale down to fit within it, keeping the aspect ratio), none (keep the sender's resolution)"
+  )]
+  recv_video_scale_policy: VideoScalePolicy,
+
+  #[structopt(
+    long,
+    help = "The raw format to convert received video to before passing it to the recv-pipeline, e.g. I420. By default, the decoder's output format is kept."
+  )]
+  recv_video_format: Option<String>,
+
+  #[structopt(
+    long,
+    help = "The sample rate to convert received audio to before passing it to the recv-pipeline, e.g. 16000."
+  )]
+  recv_audio_rate: Option<u32>,
+
+  #[structopt(
+    long,
+    help = "The number of channels to convert received audio to before passing it to the recv-pipeline."
+  )]
+  recv_audio_channels: Option<u32>,
+
+  #[structopt(
+    long,
+    help = "The sample format to convert received audio to before passing it to the recv-pipeline, e.g. S16LE."
+  )]
+  recv_audio_format: Option<String>,
+
   #[structopt(
     long,
     default_value = "200",
@@ -130,12 +234,42 @@ struct Opt {
   )]
   buffer_size: u32,
 
+  #[structopt(
+    long,
+    help = "Jitterbuffer settings for received audio, as rtpjitterbuffer property=value pairs: latency, mode, drop-on-latency, do-retransmission, faststart-min-packets, max-misorder-time. For example: 'latency=50 faststart-min-packets=3'. Unset properties default to --buffer-size latency with late packets dropped."
+  )]
+  audio_jitterbuffer: Option<String>,
+
+  #[structopt(
+    long,
+    help = "Jitterbuffer settings for received video, like --audio-jitterbuffer. Retransmission is enabled by default."
+  )]
+  video_jitterbuffer: Option<String>,
+
   #[structopt(long)]
   start_bitrate: Option<u32>,
 
   #[structopt(long)]
   stereo: Option<bool>,
 
+  #[structopt(
+    long,
+    help = "Send stereo audio. The Opus encoder in the send pipeline is made to produce two channels."
+  )]
+  send_stereo: bool,
+
+  #[structopt(
+    long,
+    help = "The duration of audio in each packet in milliseconds, to signal to other participants and to payload audio with. The Opus encoder's frame-size should match."
+  )]
+  ptime: Option<u32>,
+
+  #[structopt(
+    long,
+    help = "The maximum average bitrate in bits per second to ask other participants to send audio at."
+  )]
+  max_average_bitrate: Option<u32>,
+
   #[structopt(short, long, parse(from_occurrences))]
   verbose: u8,
 
@@ -207,6 +341,13 @@ async fn main_inner() -> Result<()> {
 
   // Parse pipelines early so that we don't bother connecting to the conference if it's invalid.
 
+  if opt.receive_only && opt.send_pipeline.is_some() {
+    bail!("--receive-only cannot be combined with --send-pipeline");
+  }
+  if (opt.receive_only || opt.audio_only) && opt.send_desktop_pipeline.is_some() {
+    bail!("--send-desktop-pipeline cannot be combined with --receive-only or --audio-only");
+  }
+
   let send_pipeline = opt
     .send_pipeline
     .as_ref()
@@ -214,6 +355,13 @@ async fn main_inner() -> Result<()> {
     .transpose()
     .context("failed to parse send pipeline")?;
 
+  let send_desktop_pipeline = opt
+    .send_desktop_pipeline
+    .as_ref()
+    .map(|pipeline| gstreamer::parse::bin_from_description(pipeline, false))
+    .transpose()
+    .context("failed to parse send desktop pipeline")?;
+
   let recv_pipeline = opt
     .recv_pipeline
     .as_ref()
@@ -299,13 +447,33 @@ async fn main_inner() -> Result<()> {
     nick,
     region,
     video_codec,
+    h264_profile,
+    decoder,
     recv_pipeline_participant_template,
     send_video_height,
+    recv_audio_encoded,
+    recv_video_encoded,
     recv_video_scale_height,
     recv_video_scale_width,
+    recv_video_scale_policy,
+    recv_video_format,
+    recv_audio_rate,
+    recv_audio_channels,
+    recv_audio_format,
     buffer_size,
+    audio_jitterbuffer,
+    video_jitterbuffer,
     start_bitrate,
     stereo,
+    send_stereo,
+    ptime,
+    max_average_bitrate,
+    receive_only,
+    audio_only,
+    simulcast_layers,
+    scalability_mode,
+    bandwidth_estimation,
+    video_encoder_bitrate_unit,
     #[cfg(feature = "log-rtp")]
     log_rtp,
     #[cfg(feature = "log-rtp")]
@@ -313,18 +481,61 @@ async fn main_inner() -> Result<()> {
     ..
   } = opt;
 
+  let mut audio_jitterbuffer_config = JitterBufferConfig::new(MediaType::Audio, buffer_size);
+  if let Some(settings) = &audio_jitterbuffer {
+    audio_jitterbuffer_config
+      .update_from_str(settings)
+      .context("invalid --audio-jitterbuffer")?;
+  }
+  let mut video_jitterbuffer_config = JitterBufferConfig::new(MediaType::Video, buffer_size);
+  if let Some(settings) = &video_jitterbuffer {
+    video_jitterbuffer_config
+      .update_from_str(settings)
+      .context("invalid --video-jitterbuffer")?;
+  }
+
+  let mut codec_registry = CodecRegistry::default();
+  for decoder in &decoder {
+    codec_registry.add_decoder(decoder)?;
+  }
+
   let config = JitsiConferenceConfig {
     muc: room_jid.parse()?,
     focus: focus_jid.parse()?,
     nick,
     region,
-    video_codec,
+    video_codecs: parse_video_codecs(&video_codec),
+    h264_profile,
+    codec_registry,
     extra_muc_features: vec![],
+    receive_only,
+    audio_only,
+    simulcast_layers,
+    scalability_mode,
+    bandwidth_estimation,
     start_bitrate: start_bitrate.unwrap_or(800),
     stereo: stereo.unwrap_or_default(),
+    audio_send: AudioSendConfig {
+      stereo: send_stereo,
+      ptime,
+    },
+    audio_recv: AudioRecvConfig {
+      rate: recv_audio_rate,
+      channels: recv_audio_channels,
+      format: recv_audio_format
+        .as_deref()
+        .map(AudioRecvConfig::parse_format)
+        .transpose()?,
+      max_average_bitrate,
+    },
+    recv_audio_encoded,
+    recv_video_encoded,
     recv_video_scale_height,
     recv_video_scale_width,
-    buffer_size,
+    recv_video_scale_policy,
+    recv_video_format,
+    audio_jitterbuffer: audio_jitterbuffer_config,
+    video_jitterbuffer: video_jitterbuffer_config,
     #[cfg(feature = "log-rtp")]
     log_rtp,
     #[cfg(feature = "log-rtp")]
@@ -341,20 +552,23 @@ async fn main_inner() -> Result<()> {
     .set_send_resolution(send_video_height.into())
     .await;
 
-  conference
-    .send_colibri_message(ColibriMessage::ReceiverVideoConstraints {
-      last_n: Some(opt.last_n.map(i32::from).unwrap_or(-1)),
-      selected_endpoints: opt
-        .select_endpoints
-        .map(|endpoints| endpoints.split(',').map(ToOwned::to_owned).collect()),
-      on_stage_endpoints: None,
-      default_constraints: Some(Constraints {
-        max_height: Some(opt.recv_video_scale_height.into()),
-        ideal_height: None,
-      }),
-      constraints: None,
-    })
-    .await?;
+  // In audio-only mode, the library has already asked the bridge not to forward any video.
+  if !audio_only {
+    conference
+      .send_colibri_message(ColibriMessage::ReceiverVideoConstraints {
+        last_n: Some(opt.last_n.map(i32::from).unwrap_or(-1)),
+        selected_endpoints: opt
+          .select_endpoints
+          .map(|endpoints| endpoints.split(',').map(ToOwned::to_owned).collect()),
+        on_stage_endpoints: None,
+        default_constraints: Some(Constraints {
+          max_height: Some(opt.recv_video_scale_height.into()),
+          ideal_height: None,
+        }),
+        constraints: None,
+      })
+      .await?;
+  }
 
   if let Some(video_type) = opt.video_type {
     conference
@@ -380,7 +594,18 @@ async fn main_inner() -> Result<()> {
       conference.set_muted(MediaType::Audio, true).await?;
     }
 
-    if let Some(video) = bin.by_name("video") {
+    if simulcast_layers > 1 {
+      let video_sink = conference.video_sink_element().await?;
+      for layer in 0..simulcast_layers {
+        let name = format!("video_{}", layer);
+        let video = bin
+          .by_name(&name)
+          .with_context(|| format!("no {} element in send pipeline for simulcast", name))?;
+        info!("Found {} element in pipeline, linking...", name);
+        video.link_pads(None, &video_sink, Some(&format!("sink_{}", layer)))?;
+      }
+    }
+    else if let Some(video) = bin.by_name("video") {
       info!("Found video element in pipeline, linking...");
       let video_sink = conference.video_sink_element().await?;
       video.link(&video_sink)?;
@@ -388,12 +613,49 @@ async fn main_inner() -> Result<()> {
     else {
       conference.set_muted(MediaType::Video, true).await?;
     }
+
+    if let Some(video_caps) = bin.by_name("video_caps") {
+      info!("Found video_caps element in pipeline, applying sender video constraints to it");
+      conference
+        .set_sender_video_capsfilter(&conference.video_source_name()?, Some(video_caps))
+        .await?;
+    }
+
+    if bandwidth_estimation {
+      if let Some(video_encoder) = bin.by_name("video_encoder") {
+        match video_encoder_bitrate_unit {
+          Some(unit) => {
+            info!(
+              "Found video_encoder element in pipeline, its bitrate will follow the bandwidth \
+               estimate"
+            );
+            conference
+              .set_bandwidth_estimate_encoder(Some(video_encoder), unit)
+              .await?;
+          },
+          None => warn!(
+            "Found video_encoder element in pipeline, but its bitrate will not follow the \
+             bandwidth estimate because --video-encoder-bitrate-unit was not given"
+          ),
+        }
+      }
+    }
   }
-  else {
+  else if !receive_only {
     conference.set_muted(MediaType::Audio, true).await?;
     conference.set_muted(MediaType::Video, true).await?;
   }
 
+  if let Some(bin) = send_desktop_pipeline {
+    let video = bin
+      .by_name("video")
+      .context("no video element in send desktop pipeline")?;
+    conference.add_bin(&bin).await?;
+    let source_name = conference.add_video_source(VideoType::Desktop).await?;
+    info!("Sending desktop video as source {}", source_name);
+    video.link(&conference.video_source_sink_element(&source_name).await?)?;
+  }
+
   if let Some(bin) = recv_pipeline {
     conference.add_bin(&bin).await?;
 
@@ -0,0 +1,1165 @@
+use std::{collections::HashMap, sync::Mutex, thread, time::Duration};
+
+use anyhow::{bail, Context as _, Result};
+use colibri::{ColibriMessage, Constraints};
+use gstreamer::{glib, prelude::*, subclass::prelude::*, GhostPad};
+use http::Uri;
+use lib_gst_meet::{
+  parse_video_codecs, AudioRecvConfig, AudioSendConfig, Authentication, CodecRegistry, Connection,
+  JitsiConference, JitsiConferenceConfig, JitterBufferConfig, MediaType,
+};
+use once_cell::sync::Lazy;
+use tokio::{runtime::Runtime, time::timeout};
+
+static CAT: Lazy<gstreamer::DebugCategory> = Lazy::new(|| {
+  gstreamer::DebugCategory::new(
+    "jitsimeetbin",
+    gstreamer::DebugColorFlags::empty(),
+    Some("Jitsi Meet conference bin"),
+  )
+});
+
+/// lib-gst-meet is async, so the plugin owns a runtime which is shared by all instances of
+/// the element.
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+  tokio::runtime::Builder::new_multi_thread()
+    .enable_all()
+    .thread_name("jitsimeetbin")
+    .build()
+    .expect("failed to start tokio runtime")
+});
+
+#[derive(Debug, Clone)]
+struct Settings {
+  web_socket_url: Option<String>,
+  xmpp_domain: Option<String>,
+  room_name: Option<String>,
+  muc_domain: Option<String>,
+  focus_jid: Option<String>,
+  xmpp_username: Option<String>,
+  xmpp_password: Option<String>,
+  xmpp_jwt: Option<String>,
+  nick: String,
+  region: Option<String>,
+  video_codec: String,
+  h264_profile: Option<String>,
+  decoders: Option<String>,
+  scalability_mode: Option<String>,
+  last_n: i32,
+  recv_audio_encoded: bool,
+  recv_video_encoded: bool,
+  recv_video_scale_width: u16,
+  recv_video_scale_height: u16,
+  recv_video_scale_policy: String,
+  recv_video_format: Option<String>,
+  recv_audio_rate: u32,
+  recv_audio_channels: u32,
+  recv_audio_format: Option<String>,
+  buffer_size: u32,
+  audio_jitterbuffer: Option<String>,
+  video_jitterbuffer: Option<String>,
+  start_bitrate: u32,
+  bandwidth_estimation: bool,
+  stereo: bool,
+  send_stereo: bool,
+  ptime: u32,
+  max_average_bitrate: u32,
+  receive_only: bool,
+  audio_only: bool,
+  tls_insecure: bool,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      web_socket_url: None,
+      xmpp_domain: None,
+      room_name: None,
+      muc_domain: None,
+      focus_jid: None,
+      xmpp_username: None,
+      xmpp_password: None,
+      xmpp_jwt: None,
+      nick: "gst-meet".to_owned(),
+      region: None,
+      video_codec: "vp8".to_owned(),
+      h264_profile: None,
+      decoders: None,
+      scalability_mode: None,
+      last_n: -1,
+      recv_audio_encoded: false,
+      recv_video_encoded: false,
+      recv_video_scale_width: 1280,
+      recv_video_scale_height: 720,
+      recv_video_scale_policy: "stretch".to_owned(),
+      recv_video_format: None,
+      recv_audio_rate: 0,
+      recv_audio_channels: 0,
+      recv_audio_format: None,
+      buffer_size: 200,
+      audio_jitterbuffer: None,
+      video_jitterbuffer: None,
+      start_bitrate: 800,
+      bandwidth_estimation: false,
+      stereo: false,
+      send_stereo: false,
+      ptime: 0,
+      max_average_bitrate: 0,
+      receive_only: false,
+      audio_only: false,
+      tls_insecure: false,
+    }
+  }
+}
+
+#[derive(Default)]
+struct State {
+  conference: Option<JitsiConference>,
+  /// The video codec negotiated for sending, once joined.
+  video_codec: Option<String>,
+  main_loop: Option<glib::MainLoop>,
+  /// The proxysinks behind our request pads, keyed by media type.
+  send_proxysinks: HashMap<MediaType, gstreamer::Element>,
+  /// The proxysrcs in the conference pipeline that feed the conference's sink elements from
+  /// [State::send_proxysinks], once joined.
+  send_proxysrcs: HashMap<MediaType, gstreamer::Element>,
+}
+
+#[derive(Default)]
+pub struct JitsiMeetBin {
+  settings: Mutex<Settings>,
+  state: Mutex<State>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for JitsiMeetBin {
+  type ParentType = gstreamer::Bin;
+  type Type = super::JitsiMeetBin;
+
+  const NAME: &'static str = "GstJitsiMeetBin";
+}
+
+impl ObjectImpl for JitsiMeetBin {
+  fn properties() -> &'static [glib::ParamSpec] {
+    static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+      let defaults = Settings::default();
+      vec![
+        glib::ParamSpecString::builder("web-socket-url")
+          .nick("WebSocket URL")
+          .blurb("The XMPP WebSocket URL, for example wss://your.jitsi.domain/xmpp-websocket")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("xmpp-domain")
+          .nick("XMPP domain")
+          .blurb("If not set, assumed to be the host part of web-socket-url")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("room-name")
+          .nick("Room name")
+          .blurb("The name of the conference room to join")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("muc-domain")
+          .nick("MUC domain")
+          .blurb("If not set, assumed to be conference.<xmpp-domain>")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("focus-jid")
+          .nick("Focus JID")
+          .blurb("If not set, assumed to be focus@auth.<xmpp-domain>/focus")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("xmpp-username")
+          .nick("XMPP username")
+          .blurb("If neither this nor xmpp-jwt is set, anonymous auth is used")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("xmpp-password")
+          .nick("XMPP password")
+          .blurb("Required if xmpp-username is set")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("xmpp-jwt")
+          .nick("XMPP JWT")
+          .blurb("The JWT token for Jitsi JWT authentication")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("nick")
+          .nick("Nick")
+          .blurb("The display name to use in the conference")
+          .default_value(Some(defaults.nick.as_str()))
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("region")
+          .nick("Region")
+          .blurb("The region to signal to the focus")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("video-codec")
+          .nick("Video codec")
+          .blurb(
+            "The codecs to transmit and receive video using, in order of preference and \
+             separated by commas. Any of: av1, vp9, vp8, h264",
+          )
+          .default_value(Some(defaults.video_codec.as_str()))
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("negotiated-video-codec")
+          .nick("Negotiated video codec")
+          .blurb("The video codec that video linked to the video pad must be encoded in")
+          .read_only()
+          .build(),
+        glib::ParamSpecString::builder("h264-profile")
+          .nick("H.264 profile")
+          .blurb(
+            "The H.264 profile that the video encoder produces (e.g. constrained-baseline); the \
+             offered H.264 payload type with this profile is used",
+          )
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("decoders")
+          .nick("Decoders")
+          .blurb(
+            "Decoders to use for codecs, separated by commas, e.g. h264=openh264dec,av1=dav1ddec; \
+             the first installed decoder for each codec is used, falling back to decodebin3",
+          )
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("scalability-mode")
+          .nick("Scalability mode")
+          .blurb(
+            "Send AV1 video as SVC in this scalability mode (L1T2 or L1T3); the encoder must use \
+             the matching temporal layering. VP9 is only supported in L1T1",
+          )
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecInt::builder("last-n")
+          .nick("Last N")
+          .blurb("The maximum number of video streams to receive, or -1 for no limit")
+          .minimum(-1)
+          .default_value(defaults.last_n)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecBoolean::builder("recv-audio-encoded")
+          .nick("Receive audio encoded")
+          .blurb("Output received audio still encoded (depayloaded Opus) instead of decoding it")
+          .default_value(defaults.recv_audio_encoded)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecBoolean::builder("recv-video-encoded")
+          .nick("Receive video encoded")
+          .blurb("Output received video still encoded (depayloaded, in the negotiated codec) instead of decoding and scaling it")
+          .default_value(defaults.recv_video_encoded)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("recv-video-scale-width")
+          .nick("Receive video scale width")
+          .blurb("The width to scale received video to")
+          .maximum(u16::MAX.into())
+          .default_value(defaults.recv_video_scale_width.into())
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("recv-video-scale-height")
+          .nick("Receive video scale height")
+          .blurb("The height to scale received video to, also signalled to the bridge as the maximum height to send")
+          .maximum(u16::MAX.into())
+          .default_value(defaults.recv_video_scale_height.into())
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("recv-video-scale-policy")
+          .nick("Receive video scale policy")
+          .blurb(
+            "How to scale received video: stretch, letterbox (add black borders), fit (scale \
+             down within the size, keeping the aspect ratio) or none",
+          )
+          .default_value(Some(defaults.recv_video_scale_policy.as_str()))
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("recv-video-format")
+          .nick("Receive video format")
+          .blurb("The raw format to convert received video to, e.g. I420, or NULL to keep the decoder's")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("recv-audio-rate")
+          .nick("Receive audio rate")
+          .blurb("The sample rate to convert received audio to, or 0 to keep the decoder's")
+          .default_value(defaults.recv_audio_rate)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("recv-audio-channels")
+          .nick("Receive audio channels")
+          .blurb("The number of channels to convert received audio to, or 0 to keep the decoder's")
+          .default_value(defaults.recv_audio_channels)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("recv-audio-format")
+          .nick("Receive audio format")
+          .blurb("The sample format to convert received audio to, e.g. S16LE, or NULL to keep the decoder's")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("buffer-size")
+          .nick("Buffer size")
+          .blurb("The size of the jitter buffers in milliseconds")
+          .default_value(defaults.buffer_size)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("audio-jitterbuffer")
+          .nick("Audio jitterbuffer")
+          .blurb(
+            "Jitterbuffer settings for received audio as rtpjitterbuffer property=value pairs, \
+             e.g. \"latency=50 mode=synced\", over a latency of buffer-size",
+          )
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecString::builder("video-jitterbuffer")
+          .nick("Video jitterbuffer")
+          .blurb("Jitterbuffer settings for received video, like audio-jitterbuffer")
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("start-bitrate")
+          .nick("Start bitrate")
+          .blurb("The video bitrate in kbps to signal to the bridge at the start of the call")
+          .default_value(defaults.start_bitrate)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecBoolean::builder("bandwidth-estimation")
+          .nick("Bandwidth estimation")
+          .blurb("Estimate the available send bandwidth from transport-cc feedback and pace sending to match (requires rtpgccbwe)")
+          .default_value(defaults.bandwidth_estimation)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecBoolean::builder("stereo")
+          .nick("Stereo")
+          .blurb("Signal support for stereo Opus audio")
+          .default_value(defaults.stereo)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecBoolean::builder("send-stereo")
+          .nick("Send stereo")
+          .blurb("Send stereo Opus audio")
+          .default_value(defaults.send_stereo)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("ptime")
+          .nick("Packet time")
+          .blurb("The duration of audio in each packet in milliseconds to signal and payload audio with, or 0 not to signal it")
+          .default_value(defaults.ptime)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecUInt::builder("max-average-bitrate")
+          .nick("Maximum average bitrate")
+          .blurb("The maximum average bitrate in bps to ask other participants to send audio at, or 0 not to signal it")
+          .default_value(defaults.max_average_bitrate)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecBoolean::builder("receive-only")
+          .nick("Receive only")
+          .blurb("Join without sending any media; the audio and video pads cannot be requested")
+          .default_value(defaults.receive_only)
+          .mutable_ready()
+          .build(),
+        glib::ParamSpecBoolean::builder("audio-only")
+          .nick("Audio only")
+          .blurb("Only negotiate audio; the video pad cannot be requested and no video is received")
+          .default_value(defaults.audio_only)
+          .mutable_ready()
+          .build(),
+        #[cfg(feature = "tls-insecure")]
+        glib::ParamSpecBoolean::builder("tls-insecure")
+          .nick("TLS insecure")
+          .blurb("Disable TLS certificate verification (use with extreme caution)")
+          .default_value(defaults.tls_insecure)
+          .mutable_ready()
+          .build(),
+      ]
+    });
+    PROPERTIES.as_ref()
+  }
+
+  fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+    let mut settings = self.settings.lock().unwrap();
+    match pspec.name() {
+      "web-socket-url" => settings.web_socket_url = value.get().expect("type checked upstream"),
+      "xmpp-domain" => settings.xmpp_domain = value.get().expect("type checked upstream"),
+      "room-name" => settings.room_name = value.get().expect("type checked upstream"),
+      "muc-domain" => settings.muc_domain = value.get().expect("type checked upstream"),
+      "focus-jid" => settings.focus_jid = value.get().expect("type checked upstream"),
+      "xmpp-username" => settings.xmpp_username = value.get().expect("type checked upstream"),
+      "xmpp-password" => settings.xmpp_password = value.get().expect("type checked upstream"),
+      "xmpp-jwt" => settings.xmpp_jwt = value.get().expect("type checked upstream"),
+      "nick" => {
+        settings.nick = value
+          .get::<Option<String>>()
+          .expect("type checked upstream")
+          .unwrap_or_else(|| Settings::default().nick)
+      },
+      "region" => settings.region = value.get().expect("type checked upstream"),
+      "video-codec" => {
+        settings.video_codec = value
+          .get::<Option<String>>()
+          .expect("type checked upstream")
+          .unwrap_or_else(|| Settings::default().video_codec)
+      },
+      "h264-profile" => settings.h264_profile = value.get().expect("type checked upstream"),
+      "decoders" => settings.decoders = value.get().expect("type checked upstream"),
+      "scalability-mode" => settings.scalability_mode = value.get().expect("type checked upstream"),
+      "last-n" => settings.last_n = value.get().expect("type checked upstream"),
+      "recv-audio-encoded" => {
+        settings.recv_audio_encoded = value.get().expect("type checked upstream")
+      },
+      "recv-video-encoded" => {
+        settings.recv_video_encoded = value.get().expect("type checked upstream")
+      },
+      "recv-video-scale-width" => {
+        settings.recv_video_scale_width = value
+          .get::<u32>()
+          .expect("type checked upstream")
+          .try_into()
+          .expect("range checked upstream")
+      },
+      "recv-video-scale-height" => {
+        settings.recv_video_scale_height = value
+          .get::<u32>()
+          .expect("type checked upstream")
+          .try_into()
+          .expect("range checked upstream")
+      },
+      "recv-video-scale-policy" => {
+        settings.recv_video_scale_policy = value
+          .get::<Option<String>>()
+          .expect("type checked upstream")
+          .unwrap_or_else(|| Settings::default().recv_video_scale_policy)
+      },
+      "recv-video-format" => {
+        settings.recv_video_format = value.get().expect("type checked upstream")
+      },
+      "recv-audio-rate" => settings.recv_audio_rate = value.get().expect("type checked upstream"),
+      "recv-audio-channels" => {
+        settings.recv_audio_channels = value.get().expect("type checked upstream")
+      },
+      "recv-audio-format" => {
+        settings.recv_audio_format = value.get().expect("type checked upstream")
+      },
+      "buffer-size" => settings.buffer_size = value.get().expect("type checked upstream"),
+      "audio-jitterbuffer" => {
+        settings.audio_jitterbuffer = value.get().expect("type checked upstream")
+      },
+      "video-jitterbuffer" => {
+        settings.video_jitterbuffer = value.get().expect("type checked upstream")
+      },
+      "start-bitrate" => settings.start_bitrate = value.get().expect("type checked upstream"),
+      "bandwidth-estimation" => {
+        settings.bandwidth_estimation = value.get().expect("type checked upstream")
+      },
+      "stereo" => settings.stereo = value.get().expect("type checked upstream"),
+      "send-stereo" => settings.send_stereo = value.get().expect("type checked upstream"),
+      "ptime" => settings.ptime = value.get().expect("type checked upstream"),
+      "max-average-bitrate" => {
+        settings.max_average_bitrate = value.get().expect("type checked upstream")
+      },
+      "receive-only" => settings.receive_only = value.get().expect("type checked upstream"),
+      "audio-only" => settings.audio_only = value.get().expect("type checked upstream"),
+      "tls-insecure" => settings.tls_insecure = value.get().expect("type checked upstream"),
+      _ => unimplemented!(),
+    }
+  }
+
+  fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+    let settings = self.settings.lock().unwrap();
+    match pspec.name() {
+      "web-socket-url" => settings.web_socket_url.to_value(),
+      "xmpp-domain" => settings.xmpp_domain.to_value(),
+      "room-name" => settings.room_name.to_value(),
+      "muc-domain" => settings.muc_domain.to_value(),
+      "focus-jid" => settings.focus_jid.to_value(),
+      "xmpp-username" => settings.xmpp_username.to_value(),
+      "xmpp-password" => settings.xmpp_password.to_value(),
+      "xmpp-jwt" => settings.xmpp_jwt.to_value(),
+      "nick" => settings.nick.to_value(),
+      "region" => settings.region.to_value(),
+      "video-codec" => settings.video_codec.to_value(),
+      "negotiated-video-codec" => self.state.lock().unwrap().video_codec.to_value(),
+      "h264-profile" => settings.h264_profile.to_value(),
+      "decoders" => settings.decoders.to_value(),
+      "scalability-mode" => settings.scalability_mode.to_value(),
+      "last-n" => settings.last_n.to_value(),
+      "recv-audio-encoded" => settings.recv_audio_encoded.to_value(),
+      "recv-video-encoded" => settings.recv_video_encoded.to_value(),
+      "recv-video-scale-width" => u32::from(settings.recv_video_scale_width).to_value(),
+      "recv-video-scale-height" => u32::from(settings.recv_video_scale_height).to_value(),
+      "recv-video-scale-policy" => settings.recv_video_scale_policy.to_value(),
+      "recv-video-format" => settings.recv_video_format.to_value(),
+      "recv-audio-rate" => settings.recv_audio_rate.to_value(),
+      "recv-audio-channels" => settings.recv_audio_channels.to_value(),
+      "recv-audio-format" => settings.recv_audio_format.to_value(),
+      "buffer-size" => settings.buffer_size.to_value(),
+      "audio-jitterbuffer" => settings.audio_jitterbuffer.to_value(),
+      "video-jitterbuffer" => settings.video_jitterbuffer.to_value(),
+      "start-bitrate" => settings.start_bitrate.to_value(),
+      "bandwidth-estimation" => settings.bandwidth_estimation.to_value(),
+      "stereo" => settings.stereo.to_value(),
+      "send-stereo" => settings.send_stereo.to_value(),
+      "ptime" => settings.ptime.to_value(),
+      "max-average-bitrate" => settings.max_average_bitrate.to_value(),
+      "receive-only" => settings.receive_only.to_value(),
+      "audio-only" => settings.audio_only.to_value(),
+      "tls-insecure" => settings.tls_insecure.to_value(),
+      _ => unimplemented!(),
+    }
+  }
+}
+
+impl GstObjectImpl for JitsiMeetBin {}
+
+impl ElementImpl for JitsiMeetBin {
+  fn metadata() -> Option<&'static gstreamer::subclass::ElementMetadata> {
+    static ELEMENT_METADATA: Lazy<gstreamer::subclass::ElementMetadata> = Lazy::new(|| {
+      gstreamer::subclass::ElementMetadata::new(
+        "Jitsi Meet",
+        "Source/Sink/Network",
+        "Joins a Jitsi Meet conference, sending and receiving audio and video",
+        "Jasper Hugo <jasper@avstack.io>",
+      )
+    });
+    Some(&*ELEMENT_METADATA)
+  }
+
+  fn pad_templates() -> &'static [gstreamer::PadTemplate] {
+    static PAD_TEMPLATES: Lazy<Vec<gstreamer::PadTemplate>> = Lazy::new(|| {
+      let encoded_video = ["video/x-vp8", "video/x-vp9", "video/x-h264", "video/x-av1"];
+      let encoded_video_caps = encoded_video
+        .into_iter()
+        .map(gstreamer::Structure::new_empty)
+        .collect::<gstreamer::Caps>();
+      // Received media is decoded unless recv-audio-encoded or recv-video-encoded is set.
+      let recv_audio_caps = ["audio/x-raw", "audio/x-opus"]
+        .into_iter()
+        .map(gstreamer::Structure::new_empty)
+        .collect::<gstreamer::Caps>();
+      let recv_video_caps = std::iter::once("video/x-raw")
+        .chain(encoded_video)
+        .map(gstreamer::Structure::new_empty)
+        .collect::<gstreamer::Caps>();
+      vec![
+        gstreamer::PadTemplate::new(
+          "audio",
+          gstreamer::PadDirection::Sink,
+          gstreamer::PadPresence::Request,
+          &gstreamer::Caps::builder("audio/x-opus").build(),
+        )
+        .unwrap(),
+        gstreamer::PadTemplate::new(
+          "video",
+          gstreamer::PadDirection::Sink,
+          gstreamer::PadPresence::Request,
+          &encoded_video_caps,
+        )
+        .unwrap(),
+        gstreamer::PadTemplate::new(
+          "audio_%s",
+          gstreamer::PadDirection::Src,
+          gstreamer::PadPresence::Sometimes,
+          &recv_audio_caps,
+        )
+        .unwrap(),
+        gstreamer::PadTemplate::new(
+          "video_%s",
+          gstreamer::PadDirection::Src,
+          gstreamer::PadPresence::Sometimes,
+          &recv_video_caps,
+        )
+        .unwrap(),
+      ]
+    });
+    PAD_TEMPLATES.as_ref()
+  }
+
+  fn change_state(
+    &self,
+    transition: gstreamer::StateChange,
+  ) -> Result<gstreamer::StateChangeSuccess, gstreamer::StateChangeError> {
+    if transition == gstreamer::StateChange::NullToReady {
+      if let Err(e) = RUNTIME.block_on(self.join()) {
+        gstreamer::element_imp_error!(
+          self,
+          gstreamer::ResourceError::OpenReadWrite,
+          ["Failed to join conference: {:?}", e]
+        );
+        return Err(gstreamer::StateChangeError);
+      }
+    }
+
+    let mut success = self.parent_change_state(transition)?;
+
+    match transition {
+      // Remote participants can join at any time (or never), so like a live source we can't
+      // preroll.
+      gstreamer::StateChange::ReadyToPaused | gstreamer::StateChange::PlayingToPaused => {
+        success = gstreamer::StateChangeSuccess::NoPreroll;
+      },
+      gstreamer::StateChange::ReadyToNull => {
+        RUNTIME.block_on(self.leave());
+      },
+      _ => {},
+    }
+
+    Ok(success)
+  }
+
+  fn request_new_pad(
+    &self,
+    templ: &gstreamer::PadTemplate,
+    _name: Option<&str>,
+    _caps: Option<&gstreamer::Caps>,
+  ) -> Option<gstreamer::Pad> {
+    let media_type = match templ.name_template().as_str() {
+      "audio" => MediaType::Audio,
+      "video" => MediaType::Video,
+      _ => return None,
+    };
+    match self.request_send_pad(templ, media_type) {
+      Ok(pad) => Some(pad),
+      Err(e) => {
+        gstreamer::error!(CAT, imp: self, "failed to request pad: {:?}", e);
+        None
+      },
+    }
+  }
+
+  fn release_pad(&self, pad: &gstreamer::Pad) {
+    let media_type = match pad.name().as_str() {
+      "audio" => MediaType::Audio,
+      "video" => MediaType::Video,
+      _ => return,
+    };
+    let (proxysink, proxysrc, conference) = {
+      let mut state = self.state.lock().unwrap();
+      (
+        state.send_proxysinks.remove(&media_type),
+        state.send_proxysrcs.remove(&media_type),
+        state.conference.clone(),
+      )
+    };
+    if let Some(conference) = conference {
+      if let Err(e) = RUNTIME.block_on(conference.set_muted(media_type, true)) {
+        gstreamer::warning!(CAT, imp: self, "failed to mute {:?}: {:?}", media_type, e);
+      }
+      // Unlink the conference's sink element, so that the pad can be requested again.
+      if let Some(proxysrc) = proxysrc {
+        let result = RUNTIME.block_on(async {
+          proxysrc.set_state(gstreamer::State::Null)?;
+          conference.pipeline().await?.remove(&proxysrc)?;
+          Ok::<_, anyhow::Error>(())
+        });
+        if let Err(e) = result {
+          gstreamer::warning!(CAT, imp: self, "failed to remove {:?} proxysrc: {:?}", media_type, e);
+        }
+      }
+    }
+    let obj = self.obj();
+    let _ = pad.set_active(false);
+    let _ = obj.remove_pad(pad);
+    if let Some(proxysink) = proxysink {
+      let _ = proxysink.set_state(gstreamer::State::Null);
+      let _ = obj.remove(&proxysink);
+    }
+  }
+}
+
+impl BinImpl for JitsiMeetBin {}
+
+impl JitsiMeetBin {
+  fn request_send_pad(
+    &self,
+    templ: &gstreamer::PadTemplate,
+    media_type: MediaType,
+  ) -> Result<gstreamer::Pad> {
+    {
+      let settings = self.settings.lock().unwrap();
+      if settings.receive_only {
+        bail!("cannot request send pads when receive-only");
+      }
+      if settings.audio_only && media_type == MediaType::Video {
+        bail!("cannot request a video pad when audio-only");
+      }
+    }
+    let mut state = self.state.lock().unwrap();
+    if state.send_proxysinks.contains_key(&media_type) {
+      bail!("{} pad already requested", templ.name_template());
+    }
+
+    let obj = self.obj();
+    let proxysink = gstreamer::ElementFactory::make("proxysink")
+      .name(format!("{}_proxysink", templ.name_template()))
+      .build()?;
+    obj.add(&proxysink)?;
+    let sink_pad = proxysink
+      .static_pad("sink")
+      .context("proxysink has no sink pad")?;
+    let pad = GhostPad::builder_from_template_with_target(templ, &sink_pad)?
+      .name(templ.name_template())
+      .build();
+    pad.set_active(true)?;
+    obj.add_pad(&pad)?;
+    proxysink.sync_state_with_parent()?;
+    state.send_proxysinks.insert(media_type, proxysink.clone());
+
+    // Pads requested before we go to READY are linked when we join the conference.
+    let conference = state.conference.clone();
+    drop(state);
+    if let Some(conference) = conference {
+      let proxysrc = RUNTIME.block_on(async {
+        let proxysrc = link_send_pad(&conference, media_type, &proxysink).await?;
+        conference.set_muted(media_type, false).await?;
+        Ok::<_, anyhow::Error>(proxysrc)
+      })?;
+      self
+        .state
+        .lock()
+        .unwrap()
+        .send_proxysrcs
+        .insert(media_type, proxysrc);
+    }
+
+    Ok(pad.upcast())
+  }
+
+  async fn join(&self) -> Result<()> {
+    let settings = self.settings.lock().unwrap().clone();
+
+    let room_name = settings
+      .room_name
+      .context("the room-name property must be set")?;
+    let web_socket_url = web_socket_url_with_room(
+      settings
+        .web_socket_url
+        .as_deref()
+        .context("the web-socket-url property must be set")?,
+      &room_name,
+    )?;
+    let xmpp_domain = settings
+      .xmpp_domain
+      .as_deref()
+      .or_else(|| web_socket_url.host())
+      .context("invalid WebSocket URL")?
+      .to_owned();
+
+    let (connection, background) = Connection::new(
+      &web_socket_url.to_string(),
+      &xmpp_domain,
+      match settings.xmpp_username {
+        Some(username) => Authentication::Plain {
+          username,
+          password: settings
+            .xmpp_password
+            .context("if xmpp-username is set, xmpp-password must also be set")?,
+        },
+        None => match settings.xmpp_jwt {
+          Some(token) => Authentication::Jwt { token },
+          None => Authentication::Anonymous,
+        },
+      },
+      &room_name,
+      settings.tls_insecure,
+    )
+    .await
+    .context("failed to build connection")?;
+
+    tokio::spawn(background);
+
+    connection.connect().await?;
+
+    let mut audio_jitterbuffer = JitterBufferConfig::new(MediaType::Audio, settings.buffer_size);
+    if let Some(jitterbuffer_settings) = &settings.audio_jitterbuffer {
+      audio_jitterbuffer
+        .update_from_str(jitterbuffer_settings)
+        .context("invalid audio-jitterbuffer")?;
+    }
+    let mut video_jitterbuffer = JitterBufferConfig::new(MediaType::Video, settings.buffer_size);
+    if let Some(jitterbuffer_settings) = &settings.video_jitterbuffer {
+      video_jitterbuffer
+        .update_from_str(jitterbuffer_settings)
+        .context("invalid video-jitterbuffer")?;
+    }
+
+    let mut codec_registry = CodecRegistry::default();
+    for decoder in settings
+      .decoders
+      .iter()
+      .flat_map(|decoders| decoders.split(','))
+    {
+      codec_registry.add_decoder(decoder)?;
+    }
+
+    let config = JitsiConferenceConfig {
+      muc: format!(
+        "{}@{}",
+        room_name,
+        settings
+          .muc_domain
+          .unwrap_or_else(|| format!("conference.{}", xmpp_domain)),
+      )
+      .parse()?,
+      focus: settings
+        .focus_jid
+        .unwrap_or_else(|| format!("focus@auth.{}/focus", xmpp_domain))
+        .parse()?,
+      nick: settings.nick,
+      region: settings.region,
+      video_codecs: parse_video_codecs(&settings.video_codec),
+      h264_profile: settings.h264_profile,
+      codec_registry,
+      extra_muc_features: vec![],
+      receive_only: settings.receive_only,
+      audio_only: settings.audio_only,
+      simulcast_layers: 1,
+      scalability_mode: settings
+        .scalability_mode
+        .map(|mode| mode.parse())
+        .transpose()?,
+      bandwidth_estimation: settings.bandwidth_estimation,
+      start_bitrate: settings.start_bitrate,
+      stereo: settings.stereo,
+      audio_send: AudioSendConfig {
+        stereo: settings.send_stereo,
+        ptime: Some(settings.ptime).filter(|ptime| *ptime > 0),
+      },
+      audio_recv: AudioRecvConfig {
+        rate: Some(settings.recv_audio_rate).filter(|rate| *rate > 0),
+        channels: Some(settings.recv_audio_channels).filter(|channels| *channels > 0),
+        format: settings
+          .recv_audio_format
+          .as_deref()
+          .map(AudioRecvConfig::parse_format)
+          .transpose()?,
+        max_average_bitrate: Some(settings.max_average_bitrate).filter(|bitrate| *bitrate > 0),
+      },
+      recv_audio_encoded: settings.recv_audio_encoded,
+      recv_video_encoded: settings.recv_video_encoded,
+      recv_video_scale_width: settings.recv_video_scale_width,
+      recv_video_scale_height: settings.recv_video_scale_height,
+      recv_video_scale_policy: settings.recv_video_scale_policy.parse()?,
+      recv_video_format: settings.recv_video_format,
+      audio_jitterbuffer,
+      video_jitterbuffer,
+      #[cfg(feature = "log-rtp")]
+      log_rtp: false,
+      #[cfg(feature = "log-rtp")]
+      log_rtcp: false,
+    };
+
+    // The conference pipeline's bus is watched from this context.
+    let main_context = glib::MainContext::new();
+    let main_loop = glib::MainLoop::new(Some(&main_context), false);
+    thread::Builder::new()
+      .name("jitsimeetbin-glib".to_owned())
+      .spawn({
+        let main_loop = main_loop.clone();
+        move || main_loop.run()
+      })?;
+
+    let conference = match JitsiConference::join(connection, main_context, config).await {
+      Ok(conference) => conference,
+      Err(e) => {
+        main_loop.quit();
+        return Err(e.context("failed to join conference"));
+      },
+    };
+
+    // If anything fails after joining, leave again, so that the conference is not left joined
+    // with nothing in our state to reach it.
+    let setup = async {
+      if !settings.audio_only {
+        conference
+          .send_colibri_message(ColibriMessage::ReceiverVideoConstraints {
+            last_n: Some(settings.last_n),
+            selected_endpoints: None,
+            on_stage_endpoints: None,
+            default_constraints: Some(Constraints {
+              max_height: Some(settings.recv_video_scale_height.into()),
+              ideal_height: None,
+            }),
+            constraints: None,
+          })
+          .await?;
+      }
+
+      if !settings.receive_only {
+        let send_proxysinks = self.state.lock().unwrap().send_proxysinks.clone();
+        for media_type in [MediaType::Audio, MediaType::Video] {
+          if let Some(proxysink) = send_proxysinks.get(&media_type) {
+            let proxysrc = link_send_pad(&conference, media_type, proxysink).await?;
+            self
+              .state
+              .lock()
+              .unwrap()
+              .send_proxysrcs
+              .insert(media_type, proxysrc);
+          }
+          else {
+            conference.set_muted(media_type, true).await?;
+          }
+        }
+      }
+
+      // In audio-only mode no video is received, so no video pads are created that would never
+      // carry any data.
+      let media_types: &'static [&str] = if settings.audio_only {
+        &["audio"]
+      }
+      else {
+        &["audio", "video"]
+      };
+      let element = self.obj().downgrade();
+      conference
+        .on_participant(move |conference, participant| {
+          let element = element.clone();
+          Box::pin(async move {
+            let Some(element) = element.upgrade()
+            else {
+              return Ok(());
+            };
+            let participant_id = participant.muc_jid.resource_str().to_owned();
+            gstreamer::info!(CAT, obj: element, "new participant: {:?}", participant);
+
+            // lib-gst-meet links the participant's decoded streams to the "audio" and "video"
+            // pads of a bin with this name, if one exists in the conference pipeline.
+            let bin = gstreamer::Bin::builder()
+              .name(format!("participant_{}", participant_id))
+              .build();
+            for &media in media_types {
+              let proxysink = gstreamer::ElementFactory::make("proxysink").build()?;
+              bin.add(&proxysink)?;
+              let sink_pad = proxysink
+                .static_pad("sink")
+                .context("proxysink has no sink pad")?;
+              bin.add_pad(
+                &GhostPad::builder_with_target(&sink_pad)?
+                  .name(media)
+                  .build(),
+              )?;
+              element
+                .imp()
+                .add_participant_src_pad(media, &participant_id, &proxysink)?;
+            }
+            conference.add_bin(&bin).await?;
+
+            Ok(())
+          })
+        })
+        .await;
+
+      let element = self.obj().downgrade();
+      conference
+        .on_participant_left(move |_conference, participant| {
+          let element = element.clone();
+          Box::pin(async move {
+            if let Some(element) = element.upgrade() {
+              gstreamer::info!(CAT, obj: element, "participant left: {:?}", participant);
+              element
+                .imp()
+                .remove_participant_src_pads(participant.muc_jid.resource_str())?;
+            }
+            Ok(())
+          })
+        })
+        .await;
+
+      conference
+        .set_pipeline_state(gstreamer::State::Playing)
+        .await?;
+      Ok::<_, anyhow::Error>(())
+    };
+    if let Err(e) = setup.await {
+      if let Err(leave_error) = conference.leave().await {
+        gstreamer::warning!(CAT, imp: self, "error leaving conference: {:?}", leave_error);
+      }
+      main_loop.quit();
+      return Err(e);
+    }
+
+    let video_codec = conference.video_codec().await.ok();
+
+    {
+      let mut state = self.state.lock().unwrap();
+      state.conference = Some(conference);
+      state.video_codec = video_codec;
+      state.main_loop = Some(main_loop);
+    }
+    self.obj().notify("negotiated-video-codec");
+
+    Ok(())
+  }
+
+  async fn leave(&self) {
+    let (conference, main_loop) = {
+      let mut state = self.state.lock().unwrap();
+      state.video_codec = None;
+      state.send_proxysrcs.clear();
+      (state.conference.take(), state.main_loop.take())
+    };
+
+    if let Some(conference) = conference {
+      match timeout(Duration::from_secs(10), conference.leave()).await {
+        Ok(Ok(_)) => {},
+        Ok(Err(e)) => gstreamer::warning!(CAT, imp: self, "error leaving conference: {:?}", e),
+        Err(_) => gstreamer::warning!(CAT, imp: self, "timed out leaving conference"),
+      }
+    }
+
+    if let Some(main_loop) = main_loop {
+      main_loop.quit();
+    }
+
+    let obj = self.obj();
+    for pad in obj.src_pads() {
+      let _ = pad.set_active(false);
+      let _ = obj.remove_pad(&pad);
+      if let Some(proxysrc) = obj.by_name(&pad.name()) {
+        let _ = proxysrc.set_state(gstreamer::State::Null);
+        let _ = obj.remove(&proxysrc);
+      }
+    }
+  }
+
+  fn add_participant_src_pad(
+    &self,
+    media: &str,
+    participant_id: &str,
+    proxysink: &gstreamer::Element,
+  ) -> Result<()> {
+    let obj = self.obj();
+    let name = format!("{}_{}", media, participant_id);
+    let proxysrc = gstreamer::ElementFactory::make("proxysrc")
+      .name(name.as_str())
+      .property("proxysink", proxysink)
+      .build()?;
+    obj.add(&proxysrc)?;
+    let templ = obj
+      .pad_template(&format!("{}_%s", media))
+      .context("missing pad template")?;
+    let src_pad = proxysrc
+      .static_pad("src")
+      .context("proxysrc has no src pad")?;
+    let pad = GhostPad::builder_from_template_with_target(&templ, &src_pad)?
+      .name(name)
+      .build();
+    pad.set_active(true)?;
+    obj.add_pad(&pad)?;
+    proxysrc.sync_state_with_parent()?;
+    Ok(())
+  }
+
+  fn remove_participant_src_pads(&self, participant_id: &str) -> Result<()> {
+    let obj = self.obj();
+    for media in ["audio", "video"] {
+      let name = format!("{}_{}", media, participant_id);
+      if let Some(pad) = obj.static_pad(&name) {
+        pad.push_event(gstreamer::event::Eos::new());
+        pad.set_active(false)?;
+        obj.remove_pad(&pad)?;
+      }
+      if let Some(proxysrc) = obj.by_name(&name) {
+        proxysrc.set_state(gstreamer::State::Null)?;
+        obj.remove(&proxysrc)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Link the proxysink behind one of our request pads to the corresponding sink element in the
+/// conference pipeline, via a proxysrc, which is returned.
+async fn link_send_pad(
+  conference: &JitsiConference,
+  media_type: MediaType,
+  proxysink: &gstreamer::Element,
+) -> Result<gstreamer::Element> {
+  let sink_element = match media_type {
+    MediaType::Audio => conference.audio_sink_element().await?,
+    MediaType::Video => conference.video_sink_element().await?,
+  };
+  let proxysrc = gstreamer::ElementFactory::make("proxysrc")
+    .property("proxysink", proxysink)
+    .build()?;
+  conference.pipeline().await?.add(&proxysrc)?;
+  proxysrc.link(&sink_element)?;
+  proxysrc.sync_state_with_parent()?;
+  Ok(proxysrc)
+}
+
+/// Add the room name to the WebSocket URL's query string, as the gst-meet tool does.
+fn web_socket_url_with_room(web_socket_url: &str, room_name: &str) -> Result<Uri> {
+  let mut parts = web_socket_url
+    .parse::<Uri>()
+    .context("invalid WebSocket URL")?
+    .into_parts();
+  parts.path_and_query = parts
+    .path_and_query
+    .map(|path_and_query| {
+      let mut qs: HashMap<String, String> = path_and_query
+        .query()
+        .map(serde_urlencoded::from_str)
+        .transpose()?
+        .unwrap_or_default();
+      qs.entry("room".to_owned())
+        .or_insert_with(|| room_name.to_owned());
+      Ok::<_, anyhow::Error>(
+        format!(
+          "{}?{}",
+          path_and_query.path(),
+          serde_urlencoded::to_string(&qs)?,
+        )
+        .parse()?,
+      )
+    })
+    .transpose()?;
+  Ok(Uri::from_parts(parts)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn query(uri: &Uri) -> Result<HashMap<String, String>> {
+    Ok(serde_urlencoded::from_str(uri.query().unwrap_or_default())?)
+  }
+
+  #[test]
+  fn adds_room_to_web_socket_url() -> Result<()> {
+    let uri = web_socket_url_with_room("wss://meet.example.com/xmpp-websocket", "test")?;
+    assert_eq!(
+      uri.to_string(),
+      "wss://meet.example.com/xmpp-websocket?room=test"
+    );
+
+    let uri = web_socket_url_with_room("wss://meet.example.com/xmpp-websocket", "a room&more")?;
+    assert_eq!(query(&uri)?["room"], "a room&more");
+    Ok(())
+  }
+
+  #[test]
+  fn keeps_web_socket_url_query() -> Result<()> {
+    let uri = web_socket_url_with_room(
+      "wss://meet.example.com:8443/xmpp-websocket?token=abc&room=other",
+      "test",
+    )?;
+    assert_eq!(uri.scheme_str(), Some("wss"));
+    assert_eq!(uri.host(), Some("meet.example.com"));
+    assert_eq!(uri.port_u16(), Some(8443));
+    assert_eq!(uri.path(), "/xmpp-websocket");
+    assert_eq!(
+      query(&uri)?,
+      HashMap::from([
+        ("token".to_owned(), "abc".to_owned()),
+        ("room".to_owned(), "other".to_owned()),
+      ])
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_invalid_web_socket_url() {
+    assert!(web_socket_url_with_room("wss://meet example.com/", "test").is_err());
+  }
+}
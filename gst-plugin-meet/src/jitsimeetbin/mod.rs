@@ -0,0 +1,23 @@
+use gstreamer::{glib, prelude::StaticType as _};
+
+mod imp;
+
+glib::wrapper! {
+  /// A bin which joins a Jitsi Meet conference when it goes to READY.
+  ///
+  /// Encoded audio (Opus) and video (in the configured codec) linked to the `audio` and
+  /// `video` request pads is sent to the conference. Each remote participant's decoded
+  /// audio and video is exposed on `audio_<participant_id>` and `video_<participant_id>`
+  /// sometimes pads.
+  pub struct JitsiMeetBin(ObjectSubclass<imp::JitsiMeetBin>)
+    @extends gstreamer::Bin, gstreamer::Element, gstreamer::Object;
+}
+
+pub fn register(plugin: &gstreamer::Plugin) -> Result<(), glib::BoolError> {
+  gstreamer::Element::register(
+    Some(plugin),
+    "jitsimeetbin",
+    gstreamer::Rank::NONE,
+    JitsiMeetBin::static_type(),
+  )
+}
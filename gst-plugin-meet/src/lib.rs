@@ -0,0 +1,29 @@
+//! A GStreamer plugin providing the `jitsimeetbin` element, which joins a Jitsi Meet
+//! conference using lib-gst-meet.
+//!
+//! ```text
+//! gst-launch-1.0 jitsimeetbin name=meet \
+//!     web-socket-url=wss://your.jitsi.domain/xmpp-websocket room-name=roomname \
+//!   audiotestsrc is-live=true ! audioconvert ! audioresample ! opusenc ! meet.audio \
+//!   videotestsrc is-live=true ! vp8enc deadline=1 ! meet.video
+//! ```
+
+use gstreamer::glib;
+
+mod jitsimeetbin;
+
+fn plugin_init(plugin: &gstreamer::Plugin) -> Result<(), glib::BoolError> {
+  jitsimeetbin::register(plugin)
+}
+
+gstreamer::plugin_define!(
+  jitsimeet,
+  env!("CARGO_PKG_DESCRIPTION"),
+  plugin_init,
+  concat!(env!("CARGO_PKG_VERSION"), "-", env!("COMMIT_ID")),
+  "MIT/X11",
+  env!("CARGO_PKG_NAME"),
+  env!("CARGO_PKG_NAME"),
+  env!("CARGO_PKG_REPOSITORY"),
+  env!("BUILD_REL_DATE")
+);
@@ -0,0 +1,101 @@
+//! Tests of the jitsimeetbin element against a mock deployment: no media flows.
+
+use std::sync::{mpsc, Arc};
+
+use anyhow::{Context, Result};
+use gst_meet_test_support::{
+  MockDeployment, MockDeploymentConfig, MockParticipant, ServerEvent, TIMEOUT,
+};
+use gstreamer::prelude::{ElementExt as _, ElementExtManual as _, GstObjectExt as _};
+
+const ROOM: &str = "test";
+
+#[test]
+fn joins_conference() -> Result<()> {
+  gstreamer::init()?;
+  for factory in ["proxysink", "proxysrc"] {
+    if gstreamer::ElementFactory::find(factory).is_none() {
+      eprintln!(
+        "skipping: {} (from gst-plugins-bad) is not installed",
+        factory
+      );
+      return Ok(());
+    }
+  }
+  gstjitsimeet::plugin_register_static()?;
+
+  // The element blocks on its own runtime while changing state and handling pads, so the mock
+  // deployment runs on a separate runtime and the element is driven from outside of it.
+  let runtime = tokio::runtime::Runtime::new()?;
+  let alice = MockParticipant::new("alice", Some(1111), Some(2222));
+  let deployment = Arc::new(
+    runtime.block_on(MockDeployment::start(MockDeploymentConfig {
+      participants: vec![alice.clone()],
+      ..Default::default()
+    }))?,
+  );
+
+  let bin = gstreamer::ElementFactory::make("jitsimeetbin")
+    .property("web-socket-url", deployment.web_socket_url())
+    .property("xmpp-domain", deployment.xmpp_domain())
+    .property("room-name", ROOM)
+    .build()?;
+  let (pad_tx, pad_rx) = mpsc::channel();
+  bin.connect_pad_added(move |_bin, pad| {
+    let _ = pad_tx.send(pad.name().to_string());
+  });
+
+  // A send pad requested before joining is linked once the element has joined.
+  let audio_pad = bin
+    .request_pad_simple("audio")
+    .context("failed to request audio pad")?;
+  assert_eq!(audio_pad.name(), "audio");
+
+  let session_accept = runtime.spawn({
+    let deployment = deployment.clone();
+    async move { deployment.accept_session().await }
+  });
+  bin.set_state(gstreamer::State::Ready)?;
+  runtime.block_on(session_accept)??;
+  bin.set_state(gstreamer::State::Playing)?;
+  assert_eq!(bin.current_state(), gstreamer::State::Playing);
+
+  let mut participant_pads = vec![];
+  while participant_pads.len() < 2 {
+    let name = pad_rx.recv_timeout(TIMEOUT)?;
+    if name.ends_with(&format!("_{}", alice.id)) {
+      participant_pads.push(name);
+    }
+  }
+  participant_pads.sort();
+  assert_eq!(
+    participant_pads,
+    [format!("audio_{}", alice.id), format!("video_{}", alice.id)]
+  );
+
+  // Releasing the send pad mutes us.
+  bin.release_request_pad(&audio_pad);
+  assert!(bin.static_pad("audio").is_none());
+  runtime.block_on(deployment.xmpp.wait_for(|event| {
+    match event {
+      ServerEvent::Presence(presence)
+        if presence
+          .children()
+          .any(|child| child.name() == "audiomuted" && child.text() == "true") =>
+      {
+        Some(())
+      },
+      _ => None,
+    }
+  }))?;
+
+  // The pad can be requested again while joined.
+  let audio_pad = bin
+    .request_pad_simple("audio")
+    .context("failed to request audio pad again")?;
+  bin.release_request_pad(&audio_pad);
+
+  bin.set_state(gstreamer::State::Null)?;
+  assert!(bin.src_pads().is_empty());
+  Ok(())
+}
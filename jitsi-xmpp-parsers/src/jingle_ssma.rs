@@ -1,5 +1,5 @@
 use minidom::{Element, NSChoice::Any};
-use xmpp_parsers::{jingle_ssma::Semantics, ns::JINGLE_SSMA};
+use xmpp_parsers::ns::JINGLE_SSMA;
 
 use crate::ns::JITSI_MEET;
 
@@ -80,6 +80,32 @@ generate_element!(
   ]
 );
 
+generate_attribute!(
+  /// From RFC5888, the list of allowed semantics, plus SIM which Jitsi Meet uses for simulcast.
+  Semantics, "semantics", {
+    /// Lip Synchronization, defined in RFC5888.
+    Ls => "LS",
+
+    /// Flow Identification, defined in RFC5888.
+    Fid => "FID",
+
+    /// Single Reservation Flow, defined in RFC3524.
+    Srf => "SRF",
+
+    /// Alternative Network Address Types, defined in RFC4091.
+    Anat => "ANAT",
+
+    /// Forward Error Correction, defined in RFC4756.
+    Fec => "FEC",
+
+    /// Decoding Dependency, defined in RFC5583.
+    Ddp => "DDP",
+
+    /// Simulcast layers of the same source, lowest resolution first.
+    Sim => "SIM",
+  }
+);
+
 generate_element!(
   /// Element grouping multiple ssrc.
   Group, "ssrc-group", JINGLE_SSMA,
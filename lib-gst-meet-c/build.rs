@@ -0,0 +1,16 @@
+use std::{env, path::PathBuf};
+
+fn main() {
+  println!("cargo:rerun-if-changed=src/lib.rs");
+  println!("cargo:rerun-if-changed=cbindgen.toml");
+  println!("cargo:rerun-if-env-changed=GSTMEET_UPDATE_HEADER");
+
+  let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+  let bindings = cbindgen::generate(&crate_dir).expect("failed to generate C bindings");
+  bindings.write_to_file(PathBuf::from(env::var("OUT_DIR").unwrap()).join("gstmeet.h"));
+  // The checked-in header is only rewritten on request, so that building never modifies the
+  // source tree.
+  if env::var_os("GSTMEET_UPDATE_HEADER").is_some() {
+    bindings.write_to_file(format!("{}/include/gstmeet.h", crate_dir));
+  }
+}
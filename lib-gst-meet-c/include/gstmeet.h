@@ -10,68 +10,374 @@
 #include <glib/glib.h>
 #include <gst/gst.h>
 
-typedef struct JitsiConnection JitsiConnection;
-typedef struct JitsiConference JitsiConference;
+// The version of [ConferenceConfig] described by this header.
+//
+// Set `ConferenceConfig.version` to this value (or call `gstmeet_conference_config_init`,
+// which does so). Fields added in later versions are only read if the version passed in
+// is new enough, so applications built against an older header keep working.
+#define GSTMEET_CONFERENCE_CONFIG_VERSION 1
+
+// Broad categories of failure, returned by `gstmeet_last_error`.
+typedef enum GstmeetError {
+  // The last call succeeded.
+  GSTMEET_ERROR_OK = 0,
+  // An argument was NULL, malformed (for example an unparseable JID or JSON document), or
+  // a `ConferenceConfig` had an unsupported version.
+  GSTMEET_ERROR_INVALID_ARGUMENT = 1,
+  // Connecting to the XMPP server failed.
+  GSTMEET_ERROR_CONNECTION = 2,
+  // Joining or leaving the conference failed.
+  GSTMEET_ERROR_CONFERENCE = 3,
+  // The operation needs an established Jingle session (or Colibri channel) and there isn't one.
+  GSTMEET_ERROR_NOT_CONNECTED = 4,
+  // Any other failure, for example a GStreamer state change error.
+  GSTMEET_ERROR_FAILED = 5,
+} GstmeetError;
+
+typedef enum MediaType {
+  VIDEO,
+  AUDIO,
+} MediaType;
+
+typedef enum PacketDirection {
+  RECV,
+  SEND,
+} PacketDirection;
+
+typedef enum BitrateUnit {
+  BITS_PER_SECOND,
+  KILOBITS_PER_SECOND,
+} BitrateUnit;
+
+typedef struct KeyframeRequestStats {
+  // Keyframe requests passed on to the send pipeline as upstream GstForceKeyUnit events.
+  uint64_t forwarded;
+  // Keyframe requests dropped because one for the same SSRC had been forwarded just before.
+  uint64_t dropped;
+} KeyframeRequestStats;
 
 typedef struct Context Context;
 
+typedef struct JitsiConference JitsiConference;
+
+typedef struct JitsiConnection JitsiConnection;
+
 typedef struct ConferenceConfig {
+  uint32_t version;
   const char *muc;
   const char *focus;
   const char *nick;
   const char *region;
+  // Video codecs in order of preference, separated by commas, e.g. "vp9,vp8".
   const char *video_codec;
+  // A NULL-terminated array of strings, or NULL.
+  const char *const *extra_muc_features;
+  uint32_t start_bitrate;
+  bool stereo;
+  uint16_t recv_video_scale_width;
+  uint16_t recv_video_scale_height;
+  uint32_t buffer_size;
+  // Ignored unless the library was built with the `log-rtp` feature.
+  bool log_rtp;
+  // Ignored unless the library was built with the `log-rtp` feature.
+  bool log_rtcp;
+  // Join without sending any media.
+  bool receive_only;
+  // Only negotiate audio.
+  bool audio_only;
+  // The number of simulcast layers to send video in, from 1 to 3. With more than one, link each
+  // layer's encoder to the video sink element's `sink_<layer>` pad.
+  uint8_t simulcast_layers;
+  // Send AV1 as SVC in this scalability mode ("L1T2" or "L1T3"), or NULL to send without SVC. Only
+  // single spatial layer modes are supported, and VP9 only in "L1T1".
+  const char *scalability_mode;
+  // Estimate the available send bandwidth from transport-cc feedback.
+  bool bandwidth_estimation;
+  // Send stereo audio.
+  bool send_stereo;
+  // The duration of audio in each packet in milliseconds, or 0 not to signal it.
+  uint32_t ptime;
+  // The maximum average bitrate in bits per second to ask other participants to send audio at, or 0
+  // not to signal it.
+  uint32_t max_average_bitrate;
+  // The H.264 profile that the video encoder produces, e.g. "constrained-baseline", or NULL to use
+  // the first H.264 payload type offered.
+  const char *h264_profile;
+  // A NULL-terminated array of decoders to use for codecs, e.g. "h264=openh264dec", or NULL to use
+  // the defaults.
+  const char *const *decoders;
+  // Pass received audio to the recv pipeline still encoded, as depayloaded Opus.
+  bool recv_audio_encoded;
+  // Pass received video to the recv pipeline still encoded, without decoding or scaling it.
+  bool recv_video_encoded;
+  // How to scale received video: "stretch", "letterbox", "fit" or "none", or NULL to stretch.
+  const char *recv_video_scale_policy;
+  // The raw format to convert received video to, e.g. "I420", or NULL to keep the decoder's.
+  const char *recv_video_format;
+  // The sample rate to convert received audio to, or 0 to keep the decoder's.
+  uint32_t recv_audio_rate;
+  // The number of channels to convert received audio to, or 0 to keep the decoder's.
+  uint32_t recv_audio_channels;
+  // The sample format to convert received audio to, e.g. "S16LE", or NULL to keep the decoder's.
+  const char *recv_audio_format;
+  // Jitterbuffer settings for received audio as rtpjitterbuffer `property=value` pairs, e.g.
+  // "latency=50 mode=synced", over a latency of `buffer_size`, or NULL for the defaults.
+  const char *audio_jitterbuffer;
+  // Jitterbuffer settings for received video, like `audio_jitterbuffer`.
+  const char *video_jitterbuffer;
 } ConferenceConfig;
 
+// A remote participant. The strings are only valid for the duration of the callback
+// they are passed to.
 typedef struct Participant {
   const char *jid;
   const char *muc_jid;
   const char *nick;
 } Participant;
 
-typedef enum {VIDEO, AUDIO} MediaType;
+// Returns the error code of the last fallible `gstmeet_*` call made on this thread, or
+// `GSTMEET_ERROR_OK` if it succeeded.
+enum GstmeetError gstmeet_last_error(void);
+
+// Returns a human-readable description of the last error on this thread, or NULL if the last
+// fallible call succeeded.
+//
+// The string is owned by the library and is valid until the next `gstmeet_*` call on this
+// thread.
+const char *gstmeet_last_error_message(void);
+
+// Report a failure from within a callback registered with one of the
+// `gstmeet_conference_on_*` functions. Must be called on the thread that invoked the callback,
+// before the callback returns.
+//
+// lib-gst-meet logs the message and treats the callback as having failed (for example, a
+// failing `on_participant` callback does not produce a pipeline graph dump).
+void gstmeet_set_callback_error(const char *message);
 
 struct Context *gstmeet_init(void);
 
-void gstmeet_init_tracing(const char *level);
+bool gstmeet_init_tracing(const char *level);
 
 void gstmeet_deinit(struct Context *context);
 
 JitsiConnection *gstmeet_connection_new(struct Context *context,
                                         const char *websocket_url,
                                         const char *xmpp_domain,
+                                        const char *room_name,
                                         bool tls_insecure);
 
 void gstmeet_connection_free(JitsiConnection *connection);
 
 bool gstmeet_connection_connect(struct Context *context, JitsiConnection *connection);
 
+// Fill in `config` with the default values used by the gst-meet tool, and set its version.
+//
+// The JIDs, nick and codec are set to NULL and must be filled in by the caller.
+void gstmeet_conference_config_init(struct ConferenceConfig *config);
+
 JitsiConference *gstmeet_connection_join_conference(struct Context *context,
                                                     JitsiConnection *connection,
                                                     GMainContext *glib_main_context,
                                                     const struct ConferenceConfig *config);
 
-bool gstmeet_conference_connected(struct Context *context, JitsiConference *conference);
-
 bool gstmeet_conference_leave(struct Context *context, JitsiConference *conference);
 
 bool gstmeet_conference_set_muted(struct Context *context,
                                   JitsiConference *conference,
-                                  MediaType media_type,
+                                  enum MediaType media_type,
                                   bool muted);
 
 GstPipeline *gstmeet_conference_pipeline(struct Context *context, JitsiConference *conference);
 
+// The video codec negotiated for sending, which the send pipeline should encode video in.
+// Free the result with `g_free`.
+char *gstmeet_conference_video_codec(struct Context *context, JitsiConference *conference);
+
+// Start sending video in another negotiated codec. The callback registered with
+// `gstmeet_conference_on_video_codec_changed` is called after the switch.
+bool gstmeet_conference_set_video_codec(struct Context *context,
+                                        JitsiConference *conference,
+                                        const char *codec);
+
+// Ask a remote participant for a keyframe, for one of their video SSRCs or for all of them if
+// `ssrc` is 0.
+bool gstmeet_conference_request_keyframe(struct Context *context,
+                                         JitsiConference *conference,
+                                         const char *participant_id,
+                                         uint32_t ssrc);
+
+// How many keyframe requests for the video we send have been received from the bridge, and
+// how many of them were passed on to the send pipeline's encoder. Both are 0 if there is no
+// Jingle session.
+struct KeyframeRequestStats gstmeet_conference_keyframe_request_stats(struct Context *context,
+                                                                      JitsiConference *conference);
+
+// Change the jitterbuffer settings for a media type, as rtpjitterbuffer `property=value` pairs
+// over the current settings, e.g. "latency=100". Streams that are already being received are
+// changed too.
+bool gstmeet_conference_set_jitterbuffer(struct Context *context,
+                                         JitsiConference *conference,
+                                         enum MediaType media_type,
+                                         const char *settings);
+
+// Set how a remote participant's video is scaled ("stretch", "letterbox", "fit" or "none"),
+// or go back to the configured policy with NULL. Video that is already being received is
+// rescaled.
+bool gstmeet_conference_set_participant_video_scale_policy(struct Context *context,
+                                                           JitsiConference *conference,
+                                                           const char *participant_id,
+                                                           const char *policy);
+
+// The most recent estimate of the available send bandwidth in bits per second, or 0 if
+// bandwidth estimation is not enabled.
+uint32_t gstmeet_conference_bandwidth_estimate(struct Context *context,
+                                               JitsiConference *conference);
+
+// Set the bitrate of an encoder in the send pipeline to the send bandwidth estimate whenever
+// it changes (or, if `encoder` is NULL, stop doing so). `unit` is the unit of the encoder's
+// bitrate property.
+bool gstmeet_conference_set_bandwidth_estimate_encoder(struct Context *context,
+                                                       JitsiConference *conference,
+                                                       GstElement *encoder,
+                                                       enum BitrateUnit unit);
+
 GstElement *gstmeet_conference_audio_sink_element(struct Context *context,
                                                   JitsiConference *conference);
 
 GstElement *gstmeet_conference_video_sink_element(struct Context *context,
                                                   JitsiConference *conference);
 
+// Add another outgoing video source of the given video type ("camera" or "desktop"). Returns
+// the source name, which should be freed with `g_free`.
+char *gstmeet_conference_add_video_source(struct Context *context,
+                                          JitsiConference *conference,
+                                          const char *video_type);
+
+// The element to link encoded video for a source added with
+// `gstmeet_conference_add_video_source` to.
+GstElement *gstmeet_conference_video_source_sink_element(struct Context *context,
+                                                         JitsiConference *conference,
+                                                         const char *source_name);
+
+// Stop sending a video source added with `gstmeet_conference_add_video_source`.
+bool gstmeet_conference_remove_video_source(struct Context *context,
+                                            JitsiConference *conference,
+                                            const char *source_name);
+
+// Set (or, if `sink` is NULL, unset) the element in the recv pipeline that a sink pad will be
+// requested from for each remote participant's audio.
+void gstmeet_conference_set_remote_participant_audio_sink_element(struct Context *context,
+                                                                  JitsiConference *conference,
+                                                                  GstElement *sink);
+
+// Set (or, if `sink` is NULL, unset) the element in the recv pipeline that a sink pad will be
+// requested from for each remote participant's video.
+void gstmeet_conference_set_remote_participant_video_sink_element(struct Context *context,
+                                                                  JitsiConference *conference,
+                                                                  GstElement *sink);
+
+// The name of the video source negotiated when joining. Free the result with `g_free`.
+char *gstmeet_conference_video_source_name(JitsiConference *conference);
+
+// Write the maximum height that the bridge has asked us to send a video source at to
+// `height`, or -1 if it has not sent a constraint for the source. Returns false if an argument
+// is NULL, leaving `height` untouched.
+bool gstmeet_conference_sender_video_constraint(struct Context *context,
+                                                JitsiConference *conference,
+                                                const char *source_name,
+                                                int32_t *height);
+
+// Apply the bridge's sender video constraints for a video source to a capsfilter in the send
+// pipeline (or, if `capsfilter` is NULL, stop doing so).
+bool gstmeet_conference_set_sender_video_capsfilter(struct Context *context,
+                                                    JitsiConference *conference,
+                                                    const char *source_name,
+                                                    GstElement *capsfilter);
+
+void gstmeet_conference_set_send_resolution(struct Context *context,
+                                            JitsiConference *conference,
+                                            int32_t height);
+
+// Send a Colibri message, serialised as JSON, to the bridge.
+bool gstmeet_conference_send_colibri_message(struct Context *context,
+                                             JitsiConference *conference,
+                                             const char *json);
+
+// Send a JSON message to all participants in the MUC.
+bool gstmeet_conference_send_json_message(struct Context *context,
+                                          JitsiConference *conference,
+                                          const char *json);
+
+// Register a callback for new remote participants. The callback can report a failure with
+// `gstmeet_set_callback_error`.
 void gstmeet_conference_on_participant(struct Context *context,
                                        JitsiConference *conference,
-                                       GstBin *(*f)(struct Participant, void *),
-                                       void *callback_context);
+                                       GstBin *(*f)(JitsiConference*, struct Participant, void*),
+                                       void *ctx);
+
+// Register a callback for remote participants leaving. The callback can report a failure with
+// `gstmeet_set_callback_error`.
+void gstmeet_conference_on_participant_left(struct Context *context,
+                                            JitsiConference *conference,
+                                            void (*f)(JitsiConference*, struct Participant, void*),
+                                            void *ctx);
+
+// Register a callback for Colibri messages received from the bridge. The message is passed
+// to the callback serialised as JSON, and is only valid for the duration of the callback.
+// The callback can report a failure with `gstmeet_set_callback_error`.
+void gstmeet_conference_on_colibri_message(struct Context *context,
+                                           JitsiConference *conference,
+                                           void (*f)(JitsiConference*, const char*, void*),
+                                           void *ctx);
+
+// Register a callback for changes of the send bandwidth estimate, in bits per second. The
+// callback can report a failure with `gstmeet_set_callback_error`.
+void gstmeet_conference_on_bandwidth_estimate(struct Context *context,
+                                              JitsiConference *conference,
+                                              void (*f)(JitsiConference*, uint32_t, void*),
+                                              void *ctx);
+
+// Register a callback for the audio levels of received audio streams, read from the
+// `ssrc-audio-level` RTP header extension: the sending participant's ID (NULL if unknown), the
+// SSRC, the level in dBov from -127 to 0, and whether voice activity was detected. The
+// participant ID is only valid for the duration of the callback, which can report a failure
+// with `gstmeet_set_callback_error`.
+void gstmeet_conference_on_audio_level(struct Context *context,
+                                       JitsiConference *conference,
+                                       void (*f)(JitsiConference*, const char*, uint32_t, int8_t, bool, void*),
+                                       void *ctx);
+
+// Register a callback for every RTP packet received from or sent to the bridge, unencrypted.
+// It is called on GStreamer's streaming threads and must not block. The buffer is read-only
+// (it is still on its way through the pipeline, so it must not be modified or unreffed) and is
+// only valid for the duration of the callback.
+void gstmeet_conference_on_rtp(JitsiConference *conference,
+                               void (*f)(enum PacketDirection, const GstBuffer*, void*),
+                               void *ctx);
+
+// Register a callback for every compound RTCP packet received from or sent to the bridge, like
+// `gstmeet_conference_on_rtp`.
+void gstmeet_conference_on_rtcp(JitsiConference *conference,
+                                void (*f)(enum PacketDirection, const GstBuffer*, void*),
+                                void *ctx);
+
+// Register a callback for sender video constraints from the bridge, which is passed a video
+// source name and the maximum height that anyone is viewing it at. The source name is only
+// valid for the duration of the callback. The callback can report a failure with
+// `gstmeet_set_callback_error`.
+void gstmeet_conference_on_sender_video_constraint(struct Context *context,
+                                                   JitsiConference *conference,
+                                                   void (*f)(JitsiConference*, const char*, int32_t, void*),
+                                                   void *ctx);
+
+// Register a callback for changes of the codec that video is sent in. The callback should
+// replace the send pipeline's encoder with one producing the new codec, linked to the element
+// returned by `gstmeet_conference_video_sink_element`. The codec name is only valid for the
+// duration of the callback. The callback can report a failure with `gstmeet_set_callback_error`.
+void gstmeet_conference_on_video_codec_changed(struct Context *context,
+                                               JitsiConference *conference,
+                                               void (*f)(JitsiConference*, const char*, void*),
+                                               void *ctx);
 
 bool gstmeet_conference_set_pipeline_state(struct Context *context,
                                            JitsiConference *conference,
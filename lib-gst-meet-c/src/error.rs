@@ -0,0 +1,149 @@
+use std::{
+  cell::RefCell,
+  ffi::{CStr, CString},
+  os::raw::c_char,
+  ptr,
+};
+
+use anyhow::{anyhow, Result};
+use tracing::debug;
+
+/// Broad categories of failure, returned by `gstmeet_last_error`.
+///
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GstmeetError {
+  /// The last call succeeded.
+  Ok = 0,
+  /// An argument was NULL, malformed (for example an unparseable JID or JSON document), or
+  /// a `ConferenceConfig` had an unsupported version.
+  InvalidArgument = 1,
+  /// Connecting to the XMPP server failed.
+  Connection = 2,
+  /// Joining or leaving the conference failed.
+  Conference = 3,
+  /// The operation needs an established Jingle session (or Colibri channel) and there isn't one.
+  NotConnected = 4,
+  /// Any other failure, for example a GStreamer state change error.
+  Failed = 5,
+}
+
+struct LastError {
+  code: GstmeetError,
+  message: CString,
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<LastError>> = RefCell::new(None);
+  static CALLBACK_ERROR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+pub(crate) fn clear_last_error() {
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+}
+
+pub(crate) fn set_last_error(code: GstmeetError, e: anyhow::Error) {
+  debug!("lib-gst-meet-c: {:?}", e);
+  let message = CString::new(format!("{:#}", e).replace('\0', ""))
+    .expect("NUL bytes were removed from the message");
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(LastError { code, message }));
+}
+
+/// Must be called immediately before invoking a callback registered from C.
+pub(crate) fn clear_callback_error() {
+  CALLBACK_ERROR.with(|callback_error| *callback_error.borrow_mut() = None);
+}
+
+/// Must be called immediately after a callback registered from C returns, on the same thread.
+///
+/// Converts a failure reported by the callback with `gstmeet_set_callback_error` into an error,
+/// which lib-gst-meet will log and handle in the same way as a failing Rust callback.
+pub(crate) fn take_callback_error() -> Result<()> {
+  match CALLBACK_ERROR.with(|callback_error| callback_error.borrow_mut().take()) {
+    Some(message) => Err(anyhow!("callback failed: {}", message)),
+    None => Ok(()),
+  }
+}
+
+pub(crate) trait ResultExt<T> {
+  fn ok_or_set_error(self, code: GstmeetError) -> Option<T>;
+
+  fn ok_raw_or_set_error(self, code: GstmeetError) -> *mut T
+  where
+    Self: Sized,
+  {
+    self
+      .ok_or_set_error(code)
+      .map(|o| Box::into_raw(Box::new(o)))
+      .unwrap_or(ptr::null_mut())
+  }
+
+  fn is_ok_or_set_error(self, code: GstmeetError) -> bool
+  where
+    Self: Sized,
+  {
+    self.ok_or_set_error(code).is_some()
+  }
+}
+
+impl<T> ResultExt<T> for Result<T> {
+  fn ok_or_set_error(self, code: GstmeetError) -> Option<T> {
+    match self {
+      Ok(o) => {
+        clear_last_error();
+        Some(o)
+      },
+      Err(e) => {
+        set_last_error(code, e);
+        None
+      },
+    }
+  }
+}
+
+/// Returns the error code of the last fallible `gstmeet_*` call made on this thread, or
+/// `GSTMEET_ERROR_OK` if it succeeded.
+#[no_mangle]
+pub extern "C" fn gstmeet_last_error() -> GstmeetError {
+  LAST_ERROR.with(|last_error| {
+    last_error
+      .borrow()
+      .as_ref()
+      .map(|last_error| last_error.code)
+      .unwrap_or(GstmeetError::Ok)
+  })
+}
+
+/// Returns a human-readable description of the last error on this thread, or NULL if the last
+/// fallible call succeeded.
+///
+/// The string is owned by the library and is valid until the next `gstmeet_*` call on this
+/// thread.
+#[no_mangle]
+pub extern "C" fn gstmeet_last_error_message() -> *const c_char {
+  LAST_ERROR.with(|last_error| {
+    last_error
+      .borrow()
+      .as_ref()
+      .map(|last_error| last_error.message.as_ptr())
+      .unwrap_or_else(ptr::null)
+  })
+}
+
+/// Report a failure from within a callback registered with one of the
+/// `gstmeet_conference_on_*` functions. Must be called on the thread that invoked the callback,
+/// before the callback returns.
+///
+/// lib-gst-meet logs the message and treats the callback as having failed (for example, a
+/// failing `on_participant` callback does not produce a pipeline graph dump).
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_set_callback_error(message: *const c_char) {
+  let message = if message.is_null() {
+    "unknown error".to_owned()
+  }
+  else {
+    CStr::from_ptr(message).to_string_lossy().to_string()
+  };
+  CALLBACK_ERROR.with(|callback_error| *callback_error.borrow_mut() = Some(message));
+}
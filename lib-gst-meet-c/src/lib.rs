@@ -8,28 +8,108 @@ use std::{
   },
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context as _, Result};
+use colibri::{ColibriMessage, VideoType};
 use glib::{
   ffi::GMainContext,
-  translate::{from_glib, from_glib_full, ToGlibPtr},
+  translate::{from_glib, from_glib_full, from_glib_none, ToGlibPtr},
+};
+pub use lib_gst_meet::{
+  init_tracing, Authentication, BitrateUnit, Connection, JitsiConference, KeyframeRequestStats,
+  MediaType, PacketDirection,
+};
+use lib_gst_meet::{
+  parse_video_codecs, AudioRecvConfig, AudioSendConfig, CodecRegistry, JitsiConferenceConfig,
+  JitterBufferConfig,
 };
-use lib_gst_meet::JitsiConferenceConfig;
-pub use lib_gst_meet::{init_tracing, Authentication, Connection, JitsiConference, MediaType};
 use tokio::runtime::Runtime;
 
+use crate::error::{clear_callback_error, take_callback_error, ResultExt as _};
+pub use crate::error::{
+  gstmeet_last_error, gstmeet_last_error_message, gstmeet_set_callback_error, GstmeetError,
+};
+
+mod error;
+
+/// The version of [ConferenceConfig] described by this header.
+///
+/// Set `ConferenceConfig.version` to this value (or call `gstmeet_conference_config_init`,
+/// which does so). Fields added in later versions are only read if the version passed in
+/// is new enough, so applications built against an older header keep working.
+pub const GSTMEET_CONFERENCE_CONFIG_VERSION: u32 = 1;
+
 pub struct Context {
   runtime: Runtime,
 }
 
 #[repr(C)]
 pub struct ConferenceConfig {
+  pub version: u32,
   pub muc: *const c_char,
   pub focus: *const c_char,
   pub nick: *const c_char,
   pub region: *const c_char,
+  /// Video codecs in order of preference, separated by commas, e.g. "vp9,vp8".
   pub video_codec: *const c_char,
+  /// A NULL-terminated array of strings, or NULL.
+  pub extra_muc_features: *const *const c_char,
+  pub start_bitrate: u32,
+  pub stereo: bool,
+  pub recv_video_scale_width: u16,
+  pub recv_video_scale_height: u16,
+  pub buffer_size: u32,
+  /// Ignored unless the library was built with the `log-rtp` feature.
+  pub log_rtp: bool,
+  /// Ignored unless the library was built with the `log-rtp` feature.
+  pub log_rtcp: bool,
+  /// Join without sending any media.
+  pub receive_only: bool,
+  /// Only negotiate audio.
+  pub audio_only: bool,
+  /// The number of simulcast layers to send video in, from 1 to 3. With more than one, link each
+  /// layer's encoder to the video sink element's `sink_<layer>` pad.
+  pub simulcast_layers: u8,
+  /// Send AV1 as SVC in this scalability mode ("L1T2" or "L1T3"), or NULL to send without SVC. Only
+  /// single spatial layer modes are supported, and VP9 only in "L1T1".
+  pub scalability_mode: *const c_char,
+  /// Estimate the available send bandwidth from transport-cc feedback.
+  pub bandwidth_estimation: bool,
+  /// Send stereo audio.
+  pub send_stereo: bool,
+  /// The duration of audio in each packet in milliseconds, or 0 not to signal it.
+  pub ptime: u32,
+  /// The maximum average bitrate in bits per second to ask other participants to send audio at, or
+  /// 0 not to signal it.
+  pub max_average_bitrate: u32,
+  /// The H.264 profile that the video encoder produces, e.g. "constrained-baseline", or NULL to use
+  /// the first H.264 payload type offered.
+  pub h264_profile: *const c_char,
+  /// A NULL-terminated array of decoders to use for codecs, e.g. "h264=openh264dec", or NULL to use
+  /// the defaults.
+  pub decoders: *const *const c_char,
+  /// Pass received audio to the recv pipeline still encoded, as depayloaded Opus.
+  pub recv_audio_encoded: bool,
+  /// Pass received video to the recv pipeline still encoded, without decoding or scaling it.
+  pub recv_video_encoded: bool,
+  /// How to scale received video: "stretch", "letterbox", "fit" or "none", or NULL to stretch.
+  pub recv_video_scale_policy: *const c_char,
+  /// The raw format to convert received video to, e.g. "I420", or NULL to keep the decoder's.
+  pub recv_video_format: *const c_char,
+  /// The sample rate to convert received audio to, or 0 to keep the decoder's.
+  pub recv_audio_rate: u32,
+  /// The number of channels to convert received audio to, or 0 to keep the decoder's.
+  pub recv_audio_channels: u32,
+  /// The sample format to convert received audio to, e.g. "S16LE", or NULL to keep the decoder's.
+  pub recv_audio_format: *const c_char,
+  /// Jitterbuffer settings for received audio as rtpjitterbuffer `property=value` pairs, e.g.
+  /// "latency=50 mode=synced", over a latency of `buffer_size`, or NULL for the defaults.
+  pub audio_jitterbuffer: *const c_char,
+  /// Jitterbuffer settings for received video, like `audio_jitterbuffer`.
+  pub video_jitterbuffer: *const c_char,
 }
 
+/// A remote participant. The strings are only valid for the duration of the callback
+/// they are passed to.
 #[repr(C)]
 pub struct Participant {
   pub jid: *const c_char,
@@ -37,20 +117,66 @@ pub struct Participant {
   pub nick: *const c_char,
 }
 
-trait ResultExt<T> {
-  fn ok_raw_or_log(self) -> *mut T;
+struct OwnedParticipant {
+  jid: Option<CString>,
+  muc_jid: CString,
+  nick: Option<CString>,
 }
 
-impl<T> ResultExt<T> for Result<T> {
-  fn ok_raw_or_log(self) -> *mut T {
-    match self {
-      Ok(o) => Box::into_raw(Box::new(o)),
-      Err(e) => {
-        eprintln!("lib-gst-meet: {:?}", e);
-        ptr::null_mut()
-      },
+impl OwnedParticipant {
+  fn new(participant: lib_gst_meet::Participant) -> Result<Self> {
+    Ok(Self {
+      jid: participant
+        .jid
+        .map(|jid| CString::new(jid.to_string()))
+        .transpose()?,
+      muc_jid: CString::new(participant.muc_jid.to_string())?,
+      nick: participant.nick.map(CString::new).transpose()?,
+    })
+  }
+
+  fn as_c(&self) -> Participant {
+    Participant {
+      jid: self
+        .jid
+        .as_ref()
+        .map(|s| s.as_ptr())
+        .unwrap_or_else(ptr::null),
+      muc_jid: self.muc_jid.as_ptr(),
+      nick: self
+        .nick
+        .as_ref()
+        .map(|s| s.as_ptr())
+        .unwrap_or_else(ptr::null),
+    }
+  }
+}
+
+unsafe fn required_string(s: *const c_char, name: &str) -> Result<String> {
+  if s.is_null() {
+    bail!("{} must not be NULL", name);
+  }
+  Ok(CStr::from_ptr(s).to_string_lossy().to_string())
+}
+
+unsafe fn optional_string(s: *const c_char) -> Option<String> {
+  if s.is_null() {
+    None
+  }
+  else {
+    Some(CStr::from_ptr(s).to_string_lossy().to_string())
+  }
+}
+
+unsafe fn string_array(mut array: *const *const c_char) -> Vec<String> {
+  let mut strings = vec![];
+  if !array.is_null() {
+    while !(*array).is_null() {
+      strings.push(CStr::from_ptr(*array).to_string_lossy().to_string());
+      array = array.add(1);
     }
   }
+  strings
 }
 
 #[no_mangle]
@@ -58,21 +184,26 @@ pub extern "C" fn gstmeet_init() -> *mut Context {
   Runtime::new()
     .map(|runtime| Context { runtime })
     .map_err(|e| e.into())
-    .ok_raw_or_log()
+    .ok_raw_or_set_error(GstmeetError::Failed)
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn gstmeet_init_tracing(level: *const c_char) {
-  let level = CStr::from_ptr(level)
-    .to_string_lossy()
-    .parse()
-    .expect("invalid tracing level");
-  init_tracing(level);
+pub unsafe extern "C" fn gstmeet_init_tracing(level: *const c_char) -> bool {
+  required_string(level, "level")
+    .and_then(|level| {
+      level
+        .parse()
+        .map_err(|_| anyhow!("invalid tracing level: {}", level))
+    })
+    .map(init_tracing)
+    .is_ok_or_set_error(GstmeetError::InvalidArgument)
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn gstmeet_deinit(context: *mut Context) {
-  let _ = Box::from_raw(context);
+  if !context.is_null() {
+    let _ = Box::from_raw(context);
+  }
 }
 
 #[no_mangle]
@@ -83,28 +214,39 @@ pub unsafe extern "C" fn gstmeet_connection_new(
   room_name: *const c_char,
   tls_insecure: bool,
 ) -> *mut Connection {
-  let websocket_url = CStr::from_ptr(websocket_url);
-  let xmpp_domain = CStr::from_ptr(xmpp_domain);
-  let room_name = CStr::from_ptr(room_name);
+  let args = || {
+    Ok((
+      required_string(websocket_url, "websocket_url")?,
+      required_string(xmpp_domain, "xmpp_domain")?,
+      required_string(room_name, "room_name")?,
+    ))
+  };
+  let (websocket_url, xmpp_domain, room_name) =
+    match args().ok_or_set_error(GstmeetError::InvalidArgument) {
+      Some(args) => args,
+      None => return ptr::null_mut(),
+    };
   (*context)
     .runtime
     .block_on(Connection::new(
-      &websocket_url.to_string_lossy(),
-      &xmpp_domain.to_string_lossy(),
+      &websocket_url,
+      &xmpp_domain,
       Authentication::Anonymous,
-      &room_name.to_string_lossy(),
+      &room_name,
       tls_insecure,
     ))
     .map(|(connection, background)| {
       (*context).runtime.spawn(background);
       connection
     })
-    .ok_raw_or_log()
+    .ok_raw_or_set_error(GstmeetError::Connection)
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn gstmeet_connection_free(connection: *mut Connection) {
-  let _ = Box::from_raw(connection);
+  if !connection.is_null() {
+    let _ = Box::from_raw(connection);
+  }
 }
 
 #[no_mangle]
@@ -115,64 +257,167 @@ pub unsafe extern "C" fn gstmeet_connection_connect(
   (*context)
     .runtime
     .block_on((*connection).connect())
-    .map_err(|e| eprintln!("lib-gst-meet: {:?}", e))
-    .is_ok()
+    .is_ok_or_set_error(GstmeetError::Connection)
 }
 
+/// Fill in `config` with the default values used by the gst-meet tool, and set its version.
+///
+/// The JIDs, nick and codec are set to NULL and must be filled in by the caller.
 #[no_mangle]
-pub unsafe extern "C" fn gstmeet_connection_join_conference(
-  context: *mut Context,
-  connection: *mut Connection,
-  glib_main_context: *mut GMainContext,
-  config: *const ConferenceConfig,
-) -> *mut JitsiConference {
-  let muc = match CStr::from_ptr((*config).muc).to_string_lossy().parse() {
-    Ok(jid) => jid,
-    Err(e) => {
-      eprintln!("lib-gst-meet: invalid MUC JID: {:?}", e);
-      return ptr::null_mut();
-    },
-  };
-  let focus = match CStr::from_ptr((*config).focus).to_string_lossy().parse() {
-    Ok(jid) => jid,
-    Err(e) => {
-      eprintln!("lib-gst-meet: invalid focus JID: {:?}", e);
-      return ptr::null_mut();
-    },
-  };
-  let region = if (*config).region.is_null() {
-    None
-  }
-  else {
-    Some(
-      CStr::from_ptr((*config).region)
-        .to_string_lossy()
-        .to_string(),
-    )
-  };
-  let config = JitsiConferenceConfig {
-    muc,
-    focus,
-    nick: CStr::from_ptr((*config).nick).to_string_lossy().to_string(),
-    region,
-    video_codec: CStr::from_ptr((*config).video_codec)
-      .to_string_lossy()
-      .to_string(),
-    extra_muc_features: vec![],
-
-    // TODO
+pub unsafe extern "C" fn gstmeet_conference_config_init(config: *mut ConferenceConfig) {
+  config.write(ConferenceConfig {
+    version: GSTMEET_CONFERENCE_CONFIG_VERSION,
+    muc: ptr::null(),
+    focus: ptr::null(),
+    nick: ptr::null(),
+    region: ptr::null(),
+    video_codec: ptr::null(),
+    extra_muc_features: ptr::null(),
     start_bitrate: 800,
     stereo: false,
-
     recv_video_scale_width: 1280,
     recv_video_scale_height: 720,
-
     buffer_size: 200,
+    log_rtp: false,
+    log_rtcp: false,
+    receive_only: false,
+    audio_only: false,
+    simulcast_layers: 1,
+    scalability_mode: ptr::null(),
+    bandwidth_estimation: false,
+    send_stereo: false,
+    ptime: 0,
+    max_average_bitrate: 0,
+    h264_profile: ptr::null(),
+    decoders: ptr::null(),
+    recv_audio_encoded: false,
+    recv_video_encoded: false,
+    recv_video_scale_policy: ptr::null(),
+    recv_video_format: ptr::null(),
+    recv_audio_rate: 0,
+    recv_audio_channels: 0,
+    recv_audio_format: ptr::null(),
+    audio_jitterbuffer: ptr::null(),
+    video_jitterbuffer: ptr::null(),
+  });
+}
+
+fn jitterbuffer_config(
+  media_type: MediaType,
+  latency: u32,
+  settings: Option<String>,
+) -> Result<JitterBufferConfig> {
+  let mut config = JitterBufferConfig::new(media_type, latency);
+  if let Some(settings) = settings {
+    config
+      .update_from_str(&settings)
+      .with_context(|| format!("invalid {:?} jitterbuffer settings", media_type))?;
+  }
+  Ok(config)
+}
+
+unsafe fn conference_config_from_c(
+  config: *const ConferenceConfig,
+) -> Result<JitsiConferenceConfig> {
+  if config.is_null() {
+    bail!("config must not be NULL");
+  }
+  // Fields are read one at a time, never through a reference to the whole struct, so that
+  // fields added in later versions can be read only after checking that the caller's struct has
+  // them.
+  macro_rules! field {
+    ($name:ident) => {
+      ptr::addr_of!((*config).$name).read()
+    };
+  }
+  let version = field!(version);
+  if version == 0 || version > GSTMEET_CONFERENCE_CONFIG_VERSION {
+    bail!("unsupported ConferenceConfig version: {}", version);
+  }
+  let buffer_size = field!(buffer_size);
+  Ok(JitsiConferenceConfig {
+    muc: required_string(field!(muc), "muc")?
+      .parse()
+      .context("invalid MUC JID")?,
+    focus: required_string(field!(focus), "focus")?
+      .parse()
+      .context("invalid focus JID")?,
+    nick: required_string(field!(nick), "nick")?,
+    region: optional_string(field!(region)),
+    video_codecs: parse_video_codecs(&required_string(field!(video_codec), "video_codec")?),
+    h264_profile: optional_string(field!(h264_profile)),
+    codec_registry: {
+      let mut codec_registry = CodecRegistry::default();
+      for decoder in string_array(field!(decoders)) {
+        codec_registry.add_decoder(&decoder)?;
+      }
+      codec_registry
+    },
+    extra_muc_features: string_array(field!(extra_muc_features)),
+    receive_only: field!(receive_only),
+    audio_only: field!(audio_only),
+    simulcast_layers: field!(simulcast_layers),
+    scalability_mode: optional_string(field!(scalability_mode))
+      .map(|mode| mode.parse())
+      .transpose()
+      .context("invalid scalability mode")?,
+    bandwidth_estimation: field!(bandwidth_estimation),
+
+    start_bitrate: field!(start_bitrate),
+    stereo: field!(stereo),
+    audio_send: AudioSendConfig {
+      stereo: field!(send_stereo),
+      ptime: Some(field!(ptime)).filter(|ptime| *ptime > 0),
+    },
+    audio_recv: AudioRecvConfig {
+      rate: Some(field!(recv_audio_rate)).filter(|rate| *rate > 0),
+      channels: Some(field!(recv_audio_channels)).filter(|channels| *channels > 0),
+      format: optional_string(field!(recv_audio_format))
+        .as_deref()
+        .map(AudioRecvConfig::parse_format)
+        .transpose()?,
+      max_average_bitrate: Some(field!(max_average_bitrate)).filter(|bitrate| *bitrate > 0),
+    },
+
+    recv_audio_encoded: field!(recv_audio_encoded),
+    recv_video_encoded: field!(recv_video_encoded),
+    recv_video_scale_width: field!(recv_video_scale_width),
+    recv_video_scale_height: field!(recv_video_scale_height),
+    recv_video_scale_policy: optional_string(field!(recv_video_scale_policy))
+      .map(|policy| policy.parse())
+      .transpose()?
+      .unwrap_or_default(),
+    recv_video_format: optional_string(field!(recv_video_format)),
+
+    audio_jitterbuffer: jitterbuffer_config(
+      MediaType::Audio,
+      buffer_size,
+      optional_string(field!(audio_jitterbuffer)),
+    )?,
+    video_jitterbuffer: jitterbuffer_config(
+      MediaType::Video,
+      buffer_size,
+      optional_string(field!(video_jitterbuffer)),
+    )?,
 
     #[cfg(feature = "log-rtp")]
-    log_rtp: false,
+    log_rtp: field!(log_rtp),
     #[cfg(feature = "log-rtp")]
-    log_rtcp: false,
+    log_rtcp: field!(log_rtcp),
+  })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_connection_join_conference(
+  context: *mut Context,
+  connection: *mut Connection,
+  glib_main_context: *mut GMainContext,
+  config: *const ConferenceConfig,
+) -> *mut JitsiConference {
+  let config = match conference_config_from_c(config).ok_or_set_error(GstmeetError::InvalidArgument)
+  {
+    Some(config) => config,
+    None => return ptr::null_mut(),
   };
   (*context)
     .runtime
@@ -181,7 +426,7 @@ pub unsafe extern "C" fn gstmeet_connection_join_conference(
       from_glib_full(glib_main_context),
       config,
     ))
-    .ok_raw_or_log()
+    .ok_raw_or_set_error(GstmeetError::Conference)
 }
 
 #[no_mangle]
@@ -192,8 +437,7 @@ pub unsafe extern "C" fn gstmeet_conference_leave(
   (*context)
     .runtime
     .block_on(Box::from_raw(conference).leave())
-    .map_err(|e| eprintln!("lib-gst-meet: {:?}", e))
-    .is_ok()
+    .is_ok_or_set_error(GstmeetError::Conference)
 }
 
 #[no_mangle]
@@ -206,8 +450,7 @@ pub unsafe extern "C" fn gstmeet_conference_set_muted(
   (*context)
     .runtime
     .block_on((*conference).set_muted(media_type, muted))
-    .map_err(|e| eprintln!("lib-gst-meet: {:?}", e))
-    .is_ok()
+    .is_ok_or_set_error(GstmeetError::Failed)
 }
 
 #[no_mangle]
@@ -218,11 +461,169 @@ pub unsafe extern "C" fn gstmeet_conference_pipeline(
   (*context)
     .runtime
     .block_on((*conference).pipeline())
+    .ok_or_set_error(GstmeetError::NotConnected)
     .map(|pipeline| pipeline.to_glib_full())
-    .map_err(|e| eprintln!("lib-gst-meet: {:?}", e))
     .unwrap_or(ptr::null_mut())
 }
 
+/// The video codec negotiated for sending, which the send pipeline should encode video in.
+/// Free the result with `g_free`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_video_codec(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+) -> *mut c_char {
+  (*context)
+    .runtime
+    .block_on((*conference).video_codec())
+    .ok_or_set_error(GstmeetError::NotConnected)
+    .map(|codec| codec.to_glib_full())
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Start sending video in another negotiated codec. The callback registered with
+/// `gstmeet_conference_on_video_codec_changed` is called after the switch.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_video_codec(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  codec: *const c_char,
+) -> bool {
+  match required_string(codec, "codec").ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some(codec) => (*context)
+      .runtime
+      .block_on((*conference).set_video_codec(&codec))
+      .is_ok_or_set_error(GstmeetError::Failed),
+    None => false,
+  }
+}
+
+/// Ask a remote participant for a keyframe, for one of their video SSRCs or for all of them if
+/// `ssrc` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_request_keyframe(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  participant_id: *const c_char,
+  ssrc: u32,
+) -> bool {
+  match required_string(participant_id, "participant_id")
+    .ok_or_set_error(GstmeetError::InvalidArgument)
+  {
+    Some(participant_id) => (*context)
+      .runtime
+      .block_on(
+        (*conference).request_keyframe(&participant_id, Some(ssrc).filter(|ssrc| *ssrc != 0)),
+      )
+      .is_ok_or_set_error(GstmeetError::Failed),
+    None => false,
+  }
+}
+
+/// How many keyframe requests for the video we send have been received from the bridge, and
+/// how many of them were passed on to the send pipeline's encoder. Both are 0 if there is no
+/// Jingle session.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_keyframe_request_stats(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+) -> KeyframeRequestStats {
+  (*context)
+    .runtime
+    .block_on((*conference).keyframe_request_stats())
+    .ok_or_set_error(GstmeetError::NotConnected)
+    .unwrap_or_default()
+}
+
+/// Change the jitterbuffer settings for a media type, as rtpjitterbuffer `property=value` pairs
+/// over the current settings, e.g. "latency=100". Streams that are already being received are
+/// changed too.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_jitterbuffer(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  media_type: MediaType,
+  settings: *const c_char,
+) -> bool {
+  let settings =
+    match required_string(settings, "settings").ok_or_set_error(GstmeetError::InvalidArgument) {
+      Some(settings) => settings,
+      None => return false,
+    };
+  (*context).runtime.block_on(async {
+    let mut config = (*conference).jitterbuffer_config(media_type).await;
+    if config
+      .update_from_str(&settings)
+      .ok_or_set_error(GstmeetError::InvalidArgument)
+      .is_none()
+    {
+      return false;
+    }
+    (*conference)
+      .set_jitterbuffer_config(media_type, config)
+      .await;
+    true
+  })
+}
+
+/// Set how a remote participant's video is scaled ("stretch", "letterbox", "fit" or "none"),
+/// or go back to the configured policy with NULL. Video that is already being received is
+/// rescaled.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_participant_video_scale_policy(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  participant_id: *const c_char,
+  policy: *const c_char,
+) -> bool {
+  let args = required_string(participant_id, "participant_id").and_then(|participant_id| {
+    Ok((
+      participant_id,
+      optional_string(policy)
+        .map(|policy| policy.parse())
+        .transpose()?,
+    ))
+  });
+  match args.ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some((participant_id, policy)) => {
+      (*context)
+        .runtime
+        .block_on((*conference).set_participant_video_scale_policy(&participant_id, policy));
+      true
+    },
+    None => false,
+  }
+}
+
+/// The most recent estimate of the available send bandwidth in bits per second, or 0 if
+/// bandwidth estimation is not enabled.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_bandwidth_estimate(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+) -> u32 {
+  (*context)
+    .runtime
+    .block_on((*conference).bandwidth_estimate())
+    .unwrap_or_default()
+}
+
+/// Set the bitrate of an encoder in the send pipeline to the send bandwidth estimate whenever
+/// it changes (or, if `encoder` is NULL, stop doing so). `unit` is the unit of the encoder's
+/// bitrate property.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_bandwidth_estimate_encoder(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  encoder: *mut gstreamer::ffi::GstElement,
+  unit: BitrateUnit,
+) -> bool {
+  (*context)
+    .runtime
+    .block_on((*conference).set_bandwidth_estimate_encoder(from_glib_none(encoder), unit))
+    .is_ok_or_set_error(GstmeetError::Failed)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn gstmeet_conference_audio_sink_element(
   context: *mut Context,
@@ -231,8 +632,8 @@ pub unsafe extern "C" fn gstmeet_conference_audio_sink_element(
   (*context)
     .runtime
     .block_on((*conference).audio_sink_element())
-    .map(|pipeline| pipeline.to_glib_full())
-    .map_err(|e| eprintln!("lib-gst-meet: {:?}", e))
+    .ok_or_set_error(GstmeetError::NotConnected)
+    .map(|element| element.to_glib_full())
     .unwrap_or(ptr::null_mut())
 }
 
@@ -244,11 +645,206 @@ pub unsafe extern "C" fn gstmeet_conference_video_sink_element(
   (*context)
     .runtime
     .block_on((*conference).video_sink_element())
-    .map(|pipeline| pipeline.to_glib_full())
-    .map_err(|e| eprintln!("lib-gst-meet: {:?}", e))
+    .ok_or_set_error(GstmeetError::NotConnected)
+    .map(|element| element.to_glib_full())
     .unwrap_or(ptr::null_mut())
 }
 
+/// Add another outgoing video source of the given video type ("camera" or "desktop"). Returns
+/// the source name, which should be freed with `g_free`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_add_video_source(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  video_type: *const c_char,
+) -> *mut c_char {
+  let video_type = required_string(video_type, "video_type").and_then(|video_type| {
+    serde_json::from_value::<VideoType>(serde_json::Value::String(video_type))
+      .context("invalid video type")
+  });
+  match video_type.ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some(video_type) => (*context)
+      .runtime
+      .block_on((*conference).add_video_source(video_type))
+      .ok_or_set_error(GstmeetError::Failed)
+      .map(|source_name| source_name.to_glib_full())
+      .unwrap_or(ptr::null_mut()),
+    None => ptr::null_mut(),
+  }
+}
+
+/// The element to link encoded video for a source added with
+/// `gstmeet_conference_add_video_source` to.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_video_source_sink_element(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  source_name: *const c_char,
+) -> *mut gstreamer::ffi::GstElement {
+  match required_string(source_name, "source_name").ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some(source_name) => (*context)
+      .runtime
+      .block_on((*conference).video_source_sink_element(&source_name))
+      .ok_or_set_error(GstmeetError::NotConnected)
+      .map(|element| element.to_glib_full())
+      .unwrap_or(ptr::null_mut()),
+    None => ptr::null_mut(),
+  }
+}
+
+/// Stop sending a video source added with `gstmeet_conference_add_video_source`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_remove_video_source(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  source_name: *const c_char,
+) -> bool {
+  match required_string(source_name, "source_name").ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some(source_name) => (*context)
+      .runtime
+      .block_on((*conference).remove_video_source(&source_name))
+      .is_ok_or_set_error(GstmeetError::Failed),
+    None => false,
+  }
+}
+
+/// Set (or, if `sink` is NULL, unset) the element in the recv pipeline that a sink pad will be
+/// requested from for each remote participant's audio.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_remote_participant_audio_sink_element(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  sink: *mut gstreamer::ffi::GstElement,
+) {
+  (*context)
+    .runtime
+    .block_on((*conference).set_remote_participant_audio_sink_element(from_glib_none(sink)));
+}
+
+/// Set (or, if `sink` is NULL, unset) the element in the recv pipeline that a sink pad will be
+/// requested from for each remote participant's video.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_remote_participant_video_sink_element(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  sink: *mut gstreamer::ffi::GstElement,
+) {
+  (*context)
+    .runtime
+    .block_on((*conference).set_remote_participant_video_sink_element(from_glib_none(sink)));
+}
+
+/// The name of the video source negotiated when joining. Free the result with `g_free`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_video_source_name(
+  conference: *mut JitsiConference,
+) -> *mut c_char {
+  (*conference)
+    .video_source_name()
+    .ok_or_set_error(GstmeetError::Failed)
+    .map(|source_name| source_name.to_glib_full())
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Write the maximum height that the bridge has asked us to send a video source at to
+/// `height`, or -1 if it has not sent a constraint for the source. Returns false if an argument
+/// is NULL, leaving `height` untouched.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_sender_video_constraint(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  source_name: *const c_char,
+  height: *mut i32,
+) -> bool {
+  let source_name = match required_string(source_name, "source_name")
+    .and_then(|source_name| {
+      if height.is_null() {
+        bail!("height must not be NULL");
+      }
+      Ok(source_name)
+    })
+    .ok_or_set_error(GstmeetError::InvalidArgument)
+  {
+    Some(source_name) => source_name,
+    None => return false,
+  };
+  height.write(
+    (*context)
+      .runtime
+      .block_on((*conference).sender_video_constraint(&source_name))
+      .unwrap_or(-1),
+  );
+  true
+}
+
+/// Apply the bridge's sender video constraints for a video source to a capsfilter in the send
+/// pipeline (or, if `capsfilter` is NULL, stop doing so).
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_sender_video_capsfilter(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  source_name: *const c_char,
+  capsfilter: *mut gstreamer::ffi::GstElement,
+) -> bool {
+  match required_string(source_name, "source_name").ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some(source_name) => (*context)
+      .runtime
+      .block_on((*conference).set_sender_video_capsfilter(&source_name, from_glib_none(capsfilter)))
+      .is_ok_or_set_error(GstmeetError::Failed),
+    None => false,
+  }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_set_send_resolution(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  height: i32,
+) {
+  (*context)
+    .runtime
+    .block_on((*conference).set_send_resolution(height));
+}
+
+/// Send a Colibri message, serialised as JSON, to the bridge.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_send_colibri_message(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  json: *const c_char,
+) -> bool {
+  let message = required_string(json, "json").and_then(|json| {
+    serde_json::from_str::<ColibriMessage>(&json).context("invalid Colibri message")
+  });
+  match message.ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some(message) => (*context)
+      .runtime
+      .block_on((*conference).send_colibri_message(message))
+      .is_ok_or_set_error(GstmeetError::NotConnected),
+    None => false,
+  }
+}
+
+/// Send a JSON message to all participants in the MUC.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_send_json_message(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  json: *const c_char,
+) -> bool {
+  let payload = required_string(json, "json")
+    .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).context("invalid JSON"));
+  match payload.ok_or_set_error(GstmeetError::InvalidArgument) {
+    Some(payload) => (*context)
+      .runtime
+      .block_on((*conference).send_json_message(&payload))
+      .is_ok_or_set_error(GstmeetError::Failed),
+    None => false,
+  }
+}
+
+/// Register a callback for new remote participants. The callback can report a failure with
+/// `gstmeet_set_callback_error`.
 #[no_mangle]
 pub unsafe extern "C" fn gstmeet_conference_on_participant(
   context: *mut Context,
@@ -265,27 +861,219 @@ pub unsafe extern "C" fn gstmeet_conference_on_participant(
     (*conference).on_participant(move |conference, participant| {
       let ctx = ctx.clone();
       Box::pin(async move {
-        let participant = Participant {
-          jid: participant
-            .jid
-            .map(
-              |jid| Ok::<_, anyhow::Error>(CString::new(jid.to_string())?.into_raw() as *const _),
-            )
-            .transpose()?
-            .unwrap_or_else(ptr::null),
-          muc_jid: CString::new(participant.muc_jid.to_string())?.into_raw() as *const _,
-          nick: participant
-            .nick
-            .map(|nick| Ok::<_, anyhow::Error>(CString::new(nick)?.into_raw() as *const _))
-            .transpose()?
-            .unwrap_or_else(ptr::null),
-        };
+        let participant = OwnedParticipant::new(participant)?;
+        clear_callback_error();
         f(
           Box::into_raw(Box::new(conference)),
-          participant,
+          participant.as_c(),
           ctx.load(Ordering::Relaxed),
         );
-        Ok(())
+        take_callback_error()
+      })
+    }),
+  );
+}
+
+/// Register a callback for remote participants leaving. The callback can report a failure with
+/// `gstmeet_set_callback_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_participant_left(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(*mut JitsiConference, Participant, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = Arc::new(AtomicPtr::new(ctx));
+  (*context).runtime.block_on(
+    (*conference).on_participant_left(move |conference, participant| {
+      let ctx = ctx.clone();
+      Box::pin(async move {
+        let participant = OwnedParticipant::new(participant)?;
+        clear_callback_error();
+        f(
+          Box::into_raw(Box::new(conference)),
+          participant.as_c(),
+          ctx.load(Ordering::Relaxed),
+        );
+        take_callback_error()
+      })
+    }),
+  );
+}
+
+/// Register a callback for Colibri messages received from the bridge. The message is passed
+/// to the callback serialised as JSON, and is only valid for the duration of the callback.
+/// The callback can report a failure with `gstmeet_set_callback_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_colibri_message(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(*mut JitsiConference, *const c_char, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = Arc::new(AtomicPtr::new(ctx));
+  (*context).runtime.block_on(
+    (*conference).on_colibri_message(move |conference, message| {
+      let ctx = ctx.clone();
+      Box::pin(async move {
+        let json = CString::new(serde_json::to_string(&message)?)?;
+        clear_callback_error();
+        f(
+          Box::into_raw(Box::new(conference)),
+          json.as_ptr(),
+          ctx.load(Ordering::Relaxed),
+        );
+        take_callback_error()
+      })
+    }),
+  );
+}
+
+/// Register a callback for changes of the send bandwidth estimate, in bits per second. The
+/// callback can report a failure with `gstmeet_set_callback_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_bandwidth_estimate(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(*mut JitsiConference, u32, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = Arc::new(AtomicPtr::new(ctx));
+  (*context).runtime.block_on(
+    (*conference).on_bandwidth_estimate(move |conference, bitrate| {
+      let ctx = ctx.clone();
+      Box::pin(async move {
+        clear_callback_error();
+        f(
+          Box::into_raw(Box::new(conference)),
+          bitrate,
+          ctx.load(Ordering::Relaxed),
+        );
+        take_callback_error()
+      })
+    }),
+  );
+}
+
+/// Register a callback for the audio levels of received audio streams, read from the
+/// `ssrc-audio-level` RTP header extension: the sending participant's ID (NULL if unknown), the
+/// SSRC, the level in dBov from -127 to 0, and whether voice activity was detected. The
+/// participant ID is only valid for the duration of the callback, which can report a failure
+/// with `gstmeet_set_callback_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_audio_level(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(*mut JitsiConference, *const c_char, u32, i8, bool, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = Arc::new(AtomicPtr::new(ctx));
+  (*context).runtime.block_on(
+    (*conference).on_audio_level(move |conference, audio_level| {
+      let ctx = ctx.clone();
+      Box::pin(async move {
+        let participant_id = audio_level.participant_id.map(CString::new).transpose()?;
+        clear_callback_error();
+        f(
+          Box::into_raw(Box::new(conference)),
+          participant_id
+            .as_ref()
+            .map_or(ptr::null(), |participant_id| participant_id.as_ptr()),
+          audio_level.ssrc,
+          audio_level.level_dbov,
+          audio_level.vad,
+          ctx.load(Ordering::Relaxed),
+        );
+        take_callback_error()
+      })
+    }),
+  );
+}
+
+/// Register a callback for every RTP packet received from or sent to the bridge, unencrypted.
+/// It is called on GStreamer's streaming threads and must not block. The buffer is read-only
+/// (it is still on its way through the pipeline, so it must not be modified or unreffed) and is
+/// only valid for the duration of the callback.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_rtp(
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(PacketDirection, *const gstreamer::ffi::GstBuffer, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = AtomicPtr::new(ctx);
+  (*conference)
+    .on_rtp(move |direction, buffer| f(direction, buffer.as_ptr(), ctx.load(Ordering::Relaxed)));
+}
+
+/// Register a callback for every compound RTCP packet received from or sent to the bridge, like
+/// `gstmeet_conference_on_rtp`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_rtcp(
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(PacketDirection, *const gstreamer::ffi::GstBuffer, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = AtomicPtr::new(ctx);
+  (*conference)
+    .on_rtcp(move |direction, buffer| f(direction, buffer.as_ptr(), ctx.load(Ordering::Relaxed)));
+}
+
+/// Register a callback for sender video constraints from the bridge, which is passed a video
+/// source name and the maximum height that anyone is viewing it at. The source name is only
+/// valid for the duration of the callback. The callback can report a failure with
+/// `gstmeet_set_callback_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_sender_video_constraint(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(*mut JitsiConference, *const c_char, i32, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = Arc::new(AtomicPtr::new(ctx));
+  (*context)
+    .runtime
+    .block_on((*conference).on_sender_video_constraint(
+      move |conference, source_name, max_height| {
+        let ctx = ctx.clone();
+        Box::pin(async move {
+          let source_name = CString::new(source_name)?;
+          clear_callback_error();
+          f(
+            Box::into_raw(Box::new(conference)),
+            source_name.as_ptr(),
+            max_height,
+            ctx.load(Ordering::Relaxed),
+          );
+          take_callback_error()
+        })
+      },
+    ));
+}
+
+/// Register a callback for changes of the codec that video is sent in. The callback should
+/// replace the send pipeline's encoder with one producing the new codec, linked to the element
+/// returned by `gstmeet_conference_video_sink_element`. The codec name is only valid for the
+/// duration of the callback. The callback can report a failure with `gstmeet_set_callback_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gstmeet_conference_on_video_codec_changed(
+  context: *mut Context,
+  conference: *mut JitsiConference,
+  f: unsafe extern "C" fn(*mut JitsiConference, *const c_char, *mut c_void),
+  ctx: *mut c_void,
+) {
+  let ctx = Arc::new(AtomicPtr::new(ctx));
+  (*context).runtime.block_on(
+    (*conference).on_video_codec_changed(move |conference, codec| {
+      let ctx = ctx.clone();
+      Box::pin(async move {
+        let codec = CString::new(codec)?;
+        clear_callback_error();
+        f(
+          Box::into_raw(Box::new(conference)),
+          codec.as_ptr(),
+          ctx.load(Ordering::Relaxed),
+        );
+        take_callback_error()
       })
     }),
   );
@@ -300,6 +1088,151 @@ pub unsafe extern "C" fn gstmeet_conference_set_pipeline_state(
   (*context)
     .runtime
     .block_on((*conference).set_pipeline_state(from_glib(state)))
-    .map_err(|e| eprintln!("lib-gst-meet: {:?}", e))
-    .is_ok()
+    .is_ok_or_set_error(GstmeetError::NotConnected)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{mem::MaybeUninit, thread};
+
+  use super::*;
+  use crate::error::clear_last_error;
+
+  fn last_error_message() -> Option<String> {
+    let message = gstmeet_last_error_message();
+    (!message.is_null()).then(|| {
+      unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned()
+    })
+  }
+
+  fn assert_last_error(code: GstmeetError, message: &str) {
+    assert_eq!(gstmeet_last_error(), code);
+    let last_message = last_error_message().expect("no error message");
+    assert!(
+      last_message.contains(message),
+      "{:?} does not contain {:?}",
+      last_message,
+      message
+    );
+  }
+
+  fn config() -> ConferenceConfig {
+    let mut config = MaybeUninit::uninit();
+    unsafe {
+      gstmeet_conference_config_init(config.as_mut_ptr());
+      config.assume_init()
+    }
+  }
+
+  #[test]
+  fn reports_and_clears_last_error() {
+    clear_last_error();
+    assert_eq!(gstmeet_last_error(), GstmeetError::Ok);
+    assert!(gstmeet_last_error_message().is_null());
+
+    assert!(!unsafe { gstmeet_init_tracing(ptr::null()) });
+    assert_last_error(GstmeetError::InvalidArgument, "level must not be NULL");
+
+    // The error is per thread.
+    thread::spawn(|| assert_eq!(gstmeet_last_error(), GstmeetError::Ok))
+      .join()
+      .unwrap();
+
+    let context = gstmeet_init();
+    assert!(!context.is_null());
+    assert_eq!(gstmeet_last_error(), GstmeetError::Ok);
+    assert!(gstmeet_last_error_message().is_null());
+
+    let url = CString::new("wss://meet.example.com/xmpp-websocket").unwrap();
+    let connection =
+      unsafe { gstmeet_connection_new(context, url.as_ptr(), ptr::null(), ptr::null(), false) };
+    assert!(connection.is_null());
+    assert_last_error(
+      GstmeetError::InvalidArgument,
+      "xmpp_domain must not be NULL",
+    );
+
+    let source_name = CString::new("endpoint-v0").unwrap();
+    assert!(!unsafe {
+      gstmeet_conference_sender_video_constraint(
+        context,
+        ptr::null_mut(),
+        source_name.as_ptr(),
+        ptr::null_mut(),
+      )
+    });
+    assert_last_error(GstmeetError::InvalidArgument, "height must not be NULL");
+
+    unsafe { gstmeet_deinit(context) };
+  }
+
+  #[test]
+  fn rejects_invalid_conference_configs() {
+    // The config is checked before the context and connection are used.
+    fn join(config: *const ConferenceConfig) -> *mut JitsiConference {
+      unsafe {
+        gstmeet_connection_join_conference(
+          ptr::null_mut(),
+          ptr::null_mut(),
+          ptr::null_mut(),
+          config,
+        )
+      }
+    }
+
+    assert!(join(ptr::null()).is_null());
+    assert_last_error(GstmeetError::InvalidArgument, "config must not be NULL");
+
+    let mut config = config();
+    assert!(join(&config).is_null());
+    assert_last_error(GstmeetError::InvalidArgument, "muc must not be NULL");
+
+    let muc = CString::new("test@conference.meet.example.com").unwrap();
+    let focus = CString::new("focus@auth.meet.example.com/focus").unwrap();
+    let nick = CString::new("gst-meet").unwrap();
+    let video_codec = CString::new("vp8").unwrap();
+    let scalability_mode = CString::new("L3T3").unwrap();
+    config.muc = muc.as_ptr();
+    config.focus = focus.as_ptr();
+    config.nick = nick.as_ptr();
+    config.video_codec = video_codec.as_ptr();
+    config.scalability_mode = scalability_mode.as_ptr();
+    assert!(join(&config).is_null());
+    assert_last_error(GstmeetError::InvalidArgument, "invalid scalability mode");
+
+    for version in [0, GSTMEET_CONFERENCE_CONFIG_VERSION + 1] {
+      config.version = version;
+      assert!(join(&config).is_null());
+      assert_last_error(
+        GstmeetError::InvalidArgument,
+        "unsupported ConferenceConfig version",
+      );
+    }
+  }
+
+  #[test]
+  fn passes_callback_errors_to_rust() {
+    clear_callback_error();
+    assert!(take_callback_error().is_ok());
+
+    let message = CString::new("something broke").unwrap();
+    unsafe { gstmeet_set_callback_error(message.as_ptr()) };
+    let e = take_callback_error().unwrap_err();
+    assert_eq!(e.to_string(), "callback failed: something broke");
+    // Taking the error clears it.
+    assert!(take_callback_error().is_ok());
+
+    unsafe { gstmeet_set_callback_error(ptr::null()) };
+    assert_eq!(
+      take_callback_error().unwrap_err().to_string(),
+      "callback failed: unknown error"
+    );
+
+    // An error left over from an earlier callback is cleared before the next one is invoked.
+    unsafe { gstmeet_set_callback_error(message.as_ptr()) };
+    clear_callback_error();
+    assert!(take_callback_error().is_ok());
+  }
 }
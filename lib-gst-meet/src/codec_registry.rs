@@ -0,0 +1,312 @@
+//! Choosing the elements that payload and decode each codec, so that applications can use
+//! other implementations than the defaults.
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+use glib::{object::ObjectExt as _, prelude::Cast as _};
+use gstreamer::prelude::{
+  ElementExt as _, GhostPadExt as _, GstBinExtManual as _, GstValueExt as _,
+  PluginFeatureExtManual as _,
+};
+use tracing::{debug, warn};
+
+/// An element factory and the properties to set on the elements it creates. Parsed from
+/// `factory property=value ...`, e.g. `dav1ddec max-threads=4`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementConfig {
+  pub factory: String,
+  pub properties: Vec<(String, String)>,
+}
+
+impl ElementConfig {
+  pub fn new(factory: impl Into<String>) -> Self {
+    Self {
+      factory: factory.into(),
+      properties: vec![],
+    }
+  }
+
+  fn is_available(&self) -> bool {
+    gstreamer::ElementFactory::find(&self.factory).is_some()
+  }
+
+  /// Check that the factory's elements have each property and that each value can be
+  /// deserialized, so that creating them later cannot fail. Factories that are not installed
+  /// are not checked, since they are never used.
+  pub fn validate(&self) -> Result<()> {
+    gstreamer::init()?;
+    let Some(factory) = gstreamer::ElementFactory::find(&self.factory)
+    else {
+      return Ok(());
+    };
+    let element_type = factory
+      .load()
+      .with_context(|| format!("failed to load {}", self.factory))?
+      .element_type();
+    let class = glib::object::ObjectClass::from_type(element_type)
+      .with_context(|| format!("{} is not an object type", self.factory))?;
+    for (name, value) in &self.properties {
+      self.property_value(class.find_property(name), name, value)?;
+    }
+    Ok(())
+  }
+
+  fn property_value(
+    &self,
+    pspec: Option<glib::ParamSpec>,
+    name: &str,
+    value: &str,
+  ) -> Result<glib::Value> {
+    let pspec = pspec.with_context(|| format!("{} has no property {}", self.factory, name))?;
+    if !pspec.flags().contains(glib::ParamFlags::WRITABLE) {
+      bail!("{} property {} is not writable", self.factory, name);
+    }
+    glib::Value::deserialize_with_pspec(value, &pspec).map_err(|_| {
+      anyhow!(
+        "invalid value for {} property {}: {}",
+        self.factory,
+        name,
+        value
+      )
+    })
+  }
+
+  fn make(&self) -> Result<gstreamer::Element> {
+    let element = gstreamer::ElementFactory::make(&self.factory)
+      .build()
+      .with_context(|| format!("failed to create {}", self.factory))?;
+    for (name, value) in &self.properties {
+      let value = self.property_value(element.find_property(name), name, value)?;
+      element.set_property_from_value(name, &value);
+    }
+    Ok(element)
+  }
+}
+
+impl FromStr for ElementConfig {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let mut parts = s.split_whitespace();
+    let factory = parts
+      .next()
+      .context("empty element description")?
+      .to_owned();
+    let properties = parts
+      .map(|part| {
+        part
+          .split_once('=')
+          .map(|(name, value)| (name.to_owned(), value.to_owned()))
+          .ok_or_else(|| anyhow!("expected property=value: {}", part))
+      })
+      .collect::<Result<_>>()?;
+    Ok(Self {
+      factory,
+      properties,
+    })
+  }
+}
+
+impl fmt::Display for ElementConfig {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.factory)?;
+    for (name, value) in &self.properties {
+      write!(f, " {}={}", name, value)?;
+    }
+    Ok(())
+  }
+}
+
+/// Which elements payload and decode each codec, keyed by lowercase codec name (`opus`, `h264`,
+/// `vp8`, `vp9` or `av1`). Codecs that are not listed use the defaults, such as `avdec_h264` and
+/// `rtph264pay`.
+#[derive(Debug, Clone, Default)]
+pub struct CodecRegistry {
+  /// Decoders to try, in order of preference. The first one that is installed is used, and if
+  /// none is, one is autoplugged with `decodebin3`.
+  pub decoders: HashMap<String, Vec<ElementConfig>>,
+  /// Payloaders to send with.
+  pub payloaders: HashMap<String, ElementConfig>,
+  /// Ranks to give element factories when joining, which affect what `decodebin3` autoplugs.
+  pub ranks: Vec<(String, gstreamer::Rank)>,
+}
+
+impl CodecRegistry {
+  /// Add a decoder from `codec=factory property=value ...`, e.g. `h264=openh264dec`, after any
+  /// decoders already added for the codec. If the decoder is installed, its properties are
+  /// checked straight away (see [ElementConfig::validate]).
+  pub fn add_decoder(&mut self, spec: &str) -> Result<()> {
+    let (codec, decoder) = spec
+      .split_once('=')
+      .with_context(|| format!("expected codec=decoder: {}", spec))?;
+    let decoder: ElementConfig = decoder.parse()?;
+    decoder.validate()?;
+    self
+      .decoders
+      .entry(codec.trim().to_lowercase())
+      .or_default()
+      .push(decoder);
+    Ok(())
+  }
+
+  pub(crate) fn apply_ranks(&self) {
+    for (name, rank) in &self.ranks {
+      match gstreamer::ElementFactory::find(name) {
+        Some(factory) => factory.set_rank(*rank),
+        None => warn!("cannot set rank of {}: not installed", name),
+      }
+    }
+  }
+
+  pub(crate) fn make_decoder(&self, codec: &str, default: &str) -> Result<gstreamer::Element> {
+    let default = [ElementConfig::new(default)];
+    let candidates = self
+      .decoders
+      .get(codec)
+      .map(Vec::as_slice)
+      .unwrap_or(&default);
+    match candidates.iter().find(|decoder| decoder.is_available()) {
+      Some(decoder) => {
+        debug!("decoding {} with {}", codec, decoder);
+        decoder.make()
+      },
+      None => {
+        debug!("no {} decoder installed, autoplugging one", codec);
+        autoplug_decoder()
+      },
+    }
+  }
+
+  pub(crate) fn make_payloader(&self, codec: &str, default: &str) -> Result<gstreamer::Element> {
+    match self.payloaders.get(codec) {
+      Some(payloader) => payloader.make(),
+      None => Ok(gstreamer::ElementFactory::make(default).build()?),
+    }
+  }
+}
+
+/// A `decodebin3` in a bin with an always src pad, so that it can be linked like a decoder.
+fn autoplug_decoder() -> Result<gstreamer::Element> {
+  let bin = gstreamer::Bin::new();
+  let decodebin = gstreamer::ElementFactory::make("decodebin3").build()?;
+  bin.add(&decodebin)?;
+  bin.add_pad(
+    &gstreamer::GhostPad::builder_with_target(
+      &decodebin
+        .static_pad("sink")
+        .context("decodebin3 has no sink pad")?,
+    )?
+    .name("sink")
+    .build(),
+  )?;
+  let src_pad = gstreamer::GhostPad::builder(gstreamer::PadDirection::Src)
+    .name("src")
+    .build();
+  bin.add_pad(&src_pad)?;
+  decodebin.connect_pad_added(move |_, pad| {
+    if let Err(e) = src_pad.set_target(Some(pad)) {
+      warn!("failed to link autoplugged decoder: {:?}", e);
+    }
+  });
+  Ok(bin.upcast())
+}
+
+#[cfg(test)]
+mod tests {
+  use glib::{object::ObjectExt as _, prelude::Cast as _};
+  use gstreamer::prelude::{GstBinExt as _, GstObjectExt as _};
+
+  use super::*;
+
+  fn factory_name(element: &gstreamer::Element) -> Option<String> {
+    element.factory().map(|factory| factory.name().to_string())
+  }
+
+  #[test]
+  fn parses_element_config() -> Result<()> {
+    let config: ElementConfig = "dav1ddec max-threads=4 n-frames=2".parse()?;
+    assert_eq!(config.factory, "dav1ddec");
+    assert_eq!(
+      config.properties,
+      [
+        ("max-threads".to_owned(), "4".to_owned()),
+        ("n-frames".to_owned(), "2".to_owned())
+      ]
+    );
+    assert_eq!(config.to_string(), "dav1ddec max-threads=4 n-frames=2");
+    assert_eq!(config.to_string().parse::<ElementConfig>()?, config);
+    assert_eq!(
+      "  vp8dec  ".parse::<ElementConfig>()?,
+      ElementConfig::new("vp8dec")
+    );
+
+    assert!("".parse::<ElementConfig>().is_err());
+    assert!("vp8dec threads".parse::<ElementConfig>().is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_invalid_decoders() -> Result<()> {
+    let mut registry = CodecRegistry::default();
+    assert!(registry.add_decoder("vp8dec").is_err());
+    assert!(registry.add_decoder("vp8=").is_err());
+    assert!(registry.add_decoder("vp8=vp8dec threads").is_err());
+    assert!(registry
+      .add_decoder("vp8=vp8dec no-such-property=1")
+      .is_err());
+    assert!(registry.add_decoder("vp8=vp8dec threads=abc").is_err());
+    assert!(registry.decoders.is_empty());
+
+    // Decoders that are not installed cannot be checked, but are never used either.
+    registry.add_decoder("VP8 = no-such-decoder threads=abc")?;
+    registry.add_decoder("vp8=vp8dec threads=2")?;
+    assert_eq!(
+      registry.decoders["vp8"],
+      [
+        "no-such-decoder threads=abc".parse::<ElementConfig>()?,
+        "vp8dec threads=2".parse::<ElementConfig>()?
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn uses_first_installed_decoder() -> Result<()> {
+    let mut registry = CodecRegistry::default();
+    registry.add_decoder("vp8=no-such-decoder")?;
+    registry.add_decoder("vp8=vp8dec threads=2")?;
+    registry.add_decoder("vp8=avdec_vp8")?;
+    let decoder = registry.make_decoder("vp8", "no-such-default")?;
+    assert_eq!(factory_name(&decoder).as_deref(), Some("vp8dec"));
+    assert_eq!(decoder.property::<u32>("threads"), 2);
+
+    // Codecs without configured decoders use the default.
+    let decoder = registry.make_decoder("opus", "opusdec")?;
+    assert_eq!(factory_name(&decoder).as_deref(), Some("opusdec"));
+    Ok(())
+  }
+
+  #[test]
+  fn autoplugs_when_no_decoder_is_installed() -> Result<()> {
+    let mut registry = CodecRegistry::default();
+    registry.add_decoder("vp8=no-such-decoder")?;
+    for decoder in [
+      registry.make_decoder("vp8", "vp8dec")?,
+      registry.make_decoder("vp9", "no-such-default")?,
+    ] {
+      let bin = decoder
+        .downcast::<gstreamer::Bin>()
+        .map_err(|_| anyhow!("autoplugged decoder is not a bin"))?;
+      assert_eq!(
+        bin
+          .children()
+          .iter()
+          .filter_map(factory_name)
+          .collect::<Vec<_>>(),
+        ["decodebin3"]
+      );
+    }
+    Ok(())
+  }
+}
@@ -1,14 +1,22 @@
 use std::{
-  collections::HashMap, convert::TryFrom, fmt, future::Future, pin::Pin, sync::Arc, time::Duration,
+  collections::HashMap,
+  convert::TryFrom,
+  fmt,
+  future::Future,
+  pin::Pin,
+  str::FromStr,
+  sync::{Arc, RwLock},
+  time::Duration,
 };
 
 use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
-use colibri::{ColibriMessage, JsonMessage};
+use colibri::{ColibriMessage, JsonMessage, VideoType};
 use futures::stream::StreamExt;
-use glib::object::ObjectExt as _;
+use glib::{object::ObjectExt as _, value::ToValue as _};
 use gstreamer::prelude::{
-  ElementExt as _, ElementExtManual as _, GstBinExt as _, GstBinExtManual as _,
+  ElementExt as _, ElementExtManual as _, GObjectExtManualGst as _, GstBinExt as _,
+  GstBinExtManual as _, GstObjectExt as _,
 };
 use jid::{BareJid, FullJid, Jid};
 use jitsi_xmpp_parsers::jingle::{Action, Jingle};
@@ -38,7 +46,9 @@ use xmpp_parsers::{
 };
 
 use crate::{
+  codec_registry::CodecRegistry,
   colibri::ColibriChannel,
+  dependency_descriptor::ScalabilityMode,
   jingle::JingleSession,
   source::MediaType,
   stanza_filter::StanzaFilter,
@@ -77,22 +87,96 @@ enum JitsiConferenceState {
   Idle,
 }
 
+/// How we send Opus audio, signalled in the session-accept.
+#[derive(Debug, Clone, Default)]
+pub struct AudioSendConfig {
+  /// Send two-channel audio, signalled as `sprop-stereo`. The Opus encoder in the send pipeline
+  /// is made to produce stereo.
+  pub stereo: bool,
+  /// The duration of audio in each packet in milliseconds, signalled as `ptime`. The payloader's
+  /// `min-ptime` and `max-ptime` are set to it, and the encoder's frame size should match.
+  pub ptime: Option<u32>,
+}
+
+/// Parse a comma-separated video codec preference list such as `vp9,vp8` into
+/// [JitsiConferenceConfig::video_codecs].
+pub fn parse_video_codecs(codecs: &str) -> Vec<String> {
+  codecs
+    .split(',')
+    .map(|codec| codec.trim().to_lowercase())
+    .filter(|codec| !codec.is_empty())
+    .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct JitsiConferenceConfig {
   pub muc: BareJid,
   pub focus: Jid,
   pub nick: String,
   pub region: Option<String>,
-  pub video_codec: String,
+  /// Video codecs we support, in order of preference. The first one offered by the focus is
+  /// used for sending (see [JitsiConference::video_codec]), and the whole list is advertised in
+  /// presence. See [parse_video_codecs].
+  pub video_codecs: Vec<String>,
+  /// The H.264 profile that our encoder produces, as named in GStreamer caps (e.g.
+  /// `constrained-baseline` or `high`). Of the H.264 payload types offered by the focus, the one
+  /// with this profile is used, or the first one if `None`. Either way, a capsfilter in front of
+  /// the payloader makes the encoder produce the profile of the chosen payload type.
+  pub h264_profile: Option<String>,
+  /// The elements to payload and decode each codec with.
+  pub codec_registry: CodecRegistry,
   pub extra_muc_features: Vec<String>,
 
+  /// Only receive media: no payloaders or send SSRCs are created, and the session-accept and
+  /// presence signal that we will not send.
+  pub receive_only: bool,
+  /// Only negotiate audio: the video content is not accepted, no video elements are created,
+  /// and the bridge is asked not to forward any video.
+  pub audio_only: bool,
+  /// The number of simulcast layers to send video in, from 1 (no simulcast) to 3. With more than
+  /// one, the video sink element has a sink pad per layer, `sink_0` (lowest resolution) to
+  /// `sink_<N-1>`, so that the send pipeline can feed an encoder at each resolution.
+  pub simulcast_layers: u8,
+  /// Send AV1 as SVC in this scalability mode, with the dependency descriptor header extension
+  /// so that the bridge can drop temporal layers per receiver. The encoder must be configured
+  /// with the matching layering, which is read from its OBU extension headers. Only single
+  /// spatial layer modes are supported. Sending VP9 fails in any mode but L1T1, since vp9enc
+  /// does not expose the layer of each frame. Ignored for other codecs, or if the bridge does
+  /// not offer the extension.
+  pub scalability_mode: Option<ScalabilityMode>,
+  /// Estimate the available send bandwidth from the bridge's transport-cc feedback, pacing what
+  /// is sent to match, starting from `start_bitrate`. Requires the `rtpgccbwe` element from
+  /// gst-plugins-rs. See [JitsiConference::on_bandwidth_estimate].
+  pub bandwidth_estimation: bool,
+
   pub start_bitrate: u32,
+  /// Ask for stereo audio, from the focus and (as `stereo`) in the session-accept. Received
+  /// audio is decoded in stereo.
   pub stereo: bool,
-
+  pub audio_send: AudioSendConfig,
+  pub audio_recv: AudioRecvConfig,
+
+  /// Hand received audio to the recv pipeline still encoded, as depayloaded Opus, instead of
+  /// decoding it.
+  pub recv_audio_encoded: bool,
+  /// Hand received video to the recv pipeline still encoded, as depayloaded (and, for H.264, VP9
+  /// and AV1, parsed) video in the negotiated codec, instead of decoding and scaling it.
+  pub recv_video_encoded: bool,
   pub recv_video_scale_width: u16,
   pub recv_video_scale_height: u16,
-
-  pub buffer_size: u32,
+  /// How received video is scaled, unless overridden for a participant with
+  /// [JitsiConference::set_participant_video_scale_policy].
+  pub recv_video_scale_policy: VideoScalePolicy,
+  /// The raw format that received video is converted to, as named in GStreamer caps (e.g.
+  /// `I420`), or `None` for whatever the decoder produces.
+  pub recv_video_format: Option<String>,
+
+  /// Jitterbuffer settings for received audio, which can be changed later with
+  /// [JitsiConference::set_jitterbuffer_config].
+  pub audio_jitterbuffer: JitterBufferConfig,
+  /// Jitterbuffer settings for received video, which can be changed later with
+  /// [JitsiConference::set_jitterbuffer_config].
+  pub video_jitterbuffer: JitterBufferConfig,
 
   #[cfg(feature = "log-rtp")]
   pub log_rtp: bool,
@@ -100,6 +184,249 @@ pub struct JitsiConferenceConfig {
   pub log_rtcp: bool,
 }
 
+/// How we receive audio: the raw format that it is converted to before it is passed to the recv
+/// pipeline (format fields left as `None` are whatever the decoder produces), and the bitrate we
+/// ask for.
+#[derive(Debug, Clone, Default)]
+pub struct AudioRecvConfig {
+  /// The sample rate, e.g. 16000.
+  pub rate: Option<u32>,
+  pub channels: Option<u32>,
+  /// The sample format. Use [AudioRecvConfig::parse_format] to parse it from the name used in
+  /// GStreamer caps.
+  pub format: Option<gstreamer_audio::AudioFormat>,
+  /// The maximum average bitrate in bits per second that we want to receive audio at, signalled
+  /// to other participants as `maxaveragebitrate` in the session-accept.
+  pub max_average_bitrate: Option<u32>,
+}
+
+impl AudioRecvConfig {
+  /// Parse a raw sample format as named in GStreamer caps, e.g. `S16LE`.
+  pub fn parse_format(format: &str) -> Result<gstreamer_audio::AudioFormat> {
+    match gstreamer_audio::AudioFormat::from_string(format) {
+      gstreamer_audio::AudioFormat::Unknown | gstreamer_audio::AudioFormat::Encoded => {
+        bail!("unknown raw audio format: {}", format)
+      },
+      format => Ok(format),
+    }
+  }
+
+  pub(crate) fn caps(&self) -> Option<gstreamer::Caps> {
+    if self.rate.is_none() && self.channels.is_none() && self.format.is_none() {
+      return None;
+    }
+    let mut caps = gstreamer::Caps::builder("audio/x-raw");
+    if let Some(rate) = self.rate {
+      caps = caps.field("rate", rate as i32);
+    }
+    if let Some(channels) = self.channels {
+      caps = caps.field("channels", channels as i32);
+    }
+    if let Some(format) = self.format {
+      caps = caps.field("format", format.to_str());
+    }
+    Some(caps.build())
+  }
+}
+
+/// Settings for the jitterbuffers of received streams of one media type. The fields are named
+/// after `rtpjitterbuffer` properties, which is also how [JitterBufferConfig::update_from_str]
+/// names them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitterBufferConfig {
+  /// The latency in milliseconds.
+  pub latency: u32,
+  /// The jitterbuffer mode: `none`, `slave`, `buffer` or `synced`.
+  pub mode: String,
+  /// Drop packets that arrive too late to be played within the latency.
+  pub drop_on_latency: bool,
+  /// Ask for lost packets to be retransmitted. Only used for video, which is the only media with
+  /// RTX.
+  pub do_retransmission: bool,
+  /// The number of consecutive packets to wait for before starting to push, or 0 to wait for
+  /// the latency.
+  pub faststart_min_packets: u32,
+  /// How far back in time (in milliseconds) a packet can be and still be accepted as reordered
+  /// rather than as a restarted sequence.
+  pub max_misorder_time: u32,
+}
+
+impl JitterBufferConfig {
+  /// The default settings for a media type with the given latency: late packets are dropped,
+  /// and lost video packets are retransmitted.
+  pub fn new(media_type: MediaType, latency: u32) -> Self {
+    Self {
+      latency,
+      mode: "slave".to_owned(),
+      drop_on_latency: true,
+      do_retransmission: media_type == MediaType::Video,
+      faststart_min_packets: 0,
+      max_misorder_time: 2000,
+    }
+  }
+
+  /// Change settings from `property=value ...`, e.g. `latency=100 mode=synced`.
+  pub fn update_from_str(&mut self, s: &str) -> Result<()> {
+    for part in s.split(|c: char| c == ',' || c.is_whitespace()) {
+      if part.is_empty() {
+        continue;
+      }
+      let (name, value) = part
+        .split_once('=')
+        .with_context(|| format!("expected property=value: {}", part))?;
+      let context = || format!("invalid {}: {}", name, value);
+      match name {
+        "latency" => self.latency = value.parse().with_context(context)?,
+        "mode" => {
+          if !["none", "slave", "buffer", "synced"].contains(&value) {
+            bail!(context());
+          }
+          self.mode = value.to_owned();
+        },
+        "drop-on-latency" => self.drop_on_latency = value.parse().with_context(context)?,
+        "do-retransmission" => self.do_retransmission = value.parse().with_context(context)?,
+        "faststart-min-packets" => {
+          self.faststart_min_packets = value.parse().with_context(context)?
+        },
+        "max-misorder-time" => self.max_misorder_time = value.parse().with_context(context)?,
+        _ => bail!("unknown jitterbuffer setting: {}", name),
+      }
+    }
+    Ok(())
+  }
+
+  pub(crate) fn apply(&self, rtpjitterbuffer: &gstreamer::Element, allow_retransmission: bool) {
+    rtpjitterbuffer.set_property("latency", self.latency);
+    rtpjitterbuffer.set_property_from_str("mode", &self.mode);
+    rtpjitterbuffer.set_property("drop-on-latency", self.drop_on_latency);
+    rtpjitterbuffer.set_property(
+      "do-retransmission",
+      self.do_retransmission && allow_retransmission,
+    );
+    rtpjitterbuffer.set_property("faststart-min-packets", self.faststart_min_packets);
+    rtpjitterbuffer.set_property("max-misorder-time", self.max_misorder_time);
+  }
+}
+
+/// How received video is scaled to `recv_video_scale_width`×`recv_video_scale_height` before
+/// it is passed to the recv pipeline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoScalePolicy {
+  /// Scale to exactly the configured size, changing the aspect ratio if it differs.
+  #[default]
+  Stretch,
+  /// Scale to exactly the configured size, adding black borders to keep the aspect ratio.
+  Letterbox,
+  /// Scale down, keeping the aspect ratio with square pixels, to fit within the configured size.
+  /// The output size follows the sender's resolution.
+  Fit,
+  /// Pass video through at the sender's resolution.
+  None,
+}
+
+impl VideoScalePolicy {
+  pub(crate) fn apply(
+    &self,
+    videoscale: &gstreamer::Element,
+    capsfilter: &gstreamer::Element,
+    width: u16,
+    height: u16,
+  ) {
+    videoscale.set_property("add-borders", *self == VideoScalePolicy::Letterbox);
+    let caps = match self {
+      VideoScalePolicy::Stretch => format!("video/x-raw, width={}, height={}", width, height),
+      VideoScalePolicy::Letterbox => format!(
+        "video/x-raw, width={}, height={}, pixel-aspect-ratio=1/1",
+        width, height
+      ),
+      VideoScalePolicy::Fit => format!(
+        "video/x-raw, width=[1, {}], height=[1, {}], pixel-aspect-ratio=1/1",
+        width, height
+      ),
+      VideoScalePolicy::None => "video/x-raw".to_owned(),
+    };
+    capsfilter.set_property_from_str("caps", &caps);
+  }
+}
+
+impl FromStr for VideoScalePolicy {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "stretch" => Ok(VideoScalePolicy::Stretch),
+      "letterbox" => Ok(VideoScalePolicy::Letterbox),
+      "fit" => Ok(VideoScalePolicy::Fit),
+      "none" => Ok(VideoScalePolicy::None),
+      _ => bail!("unknown video scale policy: {}", s),
+    }
+  }
+}
+
+/// The unit of an encoder's bitrate property, which differs between encoders: for example bits
+/// per second for `vp8enc`, `vp9enc`, `openh264enc` and `rav1enc`, and kilobits per second for
+/// `x264enc`, `x265enc` and `av1enc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum BitrateUnit {
+  BitsPerSecond,
+  KilobitsPerSecond,
+}
+
+impl FromStr for BitrateUnit {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "bps" => Ok(BitrateUnit::BitsPerSecond),
+      "kbps" => Ok(BitrateUnit::KilobitsPerSecond),
+      _ => bail!("unknown bitrate unit: {} (supported: bps, kbps)", s),
+    }
+  }
+}
+
+/// Whether an RTP or RTCP packet was received from the bridge or is being sent to it.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[repr(C)]
+pub enum PacketDirection {
+  Recv,
+  Send,
+}
+
+/// The audio level of a received audio stream, as measured by its sender and signalled in the
+/// `ssrc-audio-level` RTP header extension (RFC 6464). See [JitsiConference::on_audio_level].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioLevel {
+  /// The remote participant sending the stream, or `None` if it is not known.
+  pub participant_id: Option<String>,
+  pub ssrc: u32,
+  /// The level in dBov, from -127 (silence) to 0 (the loudest possible signal).
+  pub level_dbov: i8,
+  /// Whether the sender detected voice activity.
+  pub vad: bool,
+}
+
+/// Counts of the keyframe requests (RTCP PLI and FIR) received from the bridge for our video
+/// SSRCs. See [JitsiConference::keyframe_request_stats].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct KeyframeRequestStats {
+  /// Requests passed on to the send pipeline as upstream `GstForceKeyUnit` events.
+  pub forwarded: u64,
+  /// Requests dropped because one for the same SSRC had been forwarded just before.
+  pub dropped: u64,
+}
+
+pub(crate) type PacketHandler = Arc<dyn Fn(PacketDirection, &gstreamer::Buffer) + Send + Sync>;
+
+/// Handlers for raw RTP and RTCP packets. They are called on GStreamer's streaming threads, so
+/// they are kept out of [JitsiConferenceInner] and its async lock.
+#[derive(Default)]
+pub(crate) struct PacketHandlers {
+  pub(crate) rtp: RwLock<Option<PacketHandler>>,
+  pub(crate) rtcp: RwLock<Option<PacketHandler>>,
+}
+
 #[derive(Clone)]
 pub struct JitsiConference {
   pub(crate) glib_main_context: glib::MainContext,
@@ -109,6 +436,7 @@ pub struct JitsiConference {
   pub(crate) external_services: Vec<xmpp::extdisco::Service>,
   pub(crate) jingle_session: Arc<Mutex<Option<JingleSession>>>,
   pub(crate) inner: Arc<Mutex<JitsiConferenceInner>>,
+  pub(crate) packet_handlers: Arc<PacketHandlers>,
   pub(crate) tls_insecure: bool,
 }
 
@@ -141,6 +469,38 @@ pub(crate) struct JitsiConferenceInner {
     Option<Arc<dyn (Fn(JitsiConference, Participant) -> BoxedResultFuture) + Send + Sync>>,
   on_colibri_message:
     Option<Arc<dyn (Fn(JitsiConference, ColibriMessage) -> BoxedResultFuture) + Send + Sync>>,
+  on_video_codec_changed:
+    Option<Arc<dyn (Fn(JitsiConference, String) -> BoxedResultFuture) + Send + Sync>>,
+  on_sender_video_constraint:
+    Option<Arc<dyn (Fn(JitsiConference, String, i32) -> BoxedResultFuture) + Send + Sync>>,
+  on_bandwidth_estimate:
+    Option<Arc<dyn (Fn(JitsiConference, u32) -> BoxedResultFuture) + Send + Sync>>,
+  on_audio_level:
+    Option<Arc<dyn (Fn(JitsiConference, AudioLevel) -> BoxedResultFuture) + Send + Sync>>,
+  /// The most recent send bandwidth estimate, in bits per second.
+  bandwidth_estimate: Option<u32>,
+  /// An encoder whose bitrate follows the send bandwidth estimate, and the unit of its bitrate
+  /// property.
+  bandwidth_estimate_encoder: Option<(gstreamer::Element, BitrateUnit)>,
+  /// The maximum height that the bridge has asked us to send each video source at.
+  sender_video_constraints: HashMap<String, i32>,
+  /// Capsfilters that sender video constraints are applied to, by source name, along with the
+  /// caps they were designated with.
+  sender_video_capsfilters: HashMap<String, (gstreamer::Element, gstreamer::Caps)>,
+  /// The current jitterbuffer settings for each media type.
+  jitterbuffer_configs: HashMap<MediaType, JitterBufferConfig>,
+  /// The jitterbuffers of received streams, with their media type and whether retransmission
+  /// can be requested for them.
+  jitterbuffers: Vec<(MediaType, bool, glib::WeakRef<gstreamer::Element>)>,
+  /// Video scale policies that override the configured one, by participant ID.
+  participant_video_scale_policies: HashMap<String, VideoScalePolicy>,
+  /// The videoscale and capsfilter elements that each remote participant's video is scaled
+  /// with, by participant ID.
+  video_scalers: HashMap<String, Vec<(gstreamer::Element, gstreamer::Element)>>,
+  /// The video codecs that each remote participant advertises in its presence.
+  participant_video_codecs: HashMap<jid::ResourcePart, Vec<String>>,
+  /// Our sources, as signalled in the `SourceInfo` presence element.
+  source_info: serde_json::Map<String, serde_json::Value>,
   presence: Vec<xmpp_parsers::Element>,
   state: JitsiConferenceState,
   send_resolution: Option<i32>,
@@ -162,6 +522,13 @@ impl JitsiConference {
     glib_main_context: glib::MainContext,
     config: JitsiConferenceConfig,
   ) -> Result<Self> {
+    if !(1..=3).contains(&config.simulcast_layers) {
+      bail!(
+        "simulcast layers must be between 1 and 3, not {}",
+        config.simulcast_layers
+      );
+    }
+
     let conference_stanza = xmpp::jitsi::Conference {
       machine_uid: Uuid::new_v4().to_string(),
       room: config.muc.to_string(),
@@ -182,6 +549,21 @@ impl JitsiConference {
 
     let endpoint_id = endpoint_id_for_jid(&jid)?;
 
+    let mut source_info = serde_json::Map::new();
+    if !config.receive_only {
+      source_info.insert(
+        format!("{endpoint_id}-a0"),
+        serde_json::json!({"muted": false}),
+      );
+      if !config.audio_only {
+        let mut video_source_info = serde_json::json!({"muted": false});
+        if let Some(scalability_mode) = config.scalability_mode {
+          video_source_info["scalabilityMode"] = scalability_mode.to_string().into();
+        }
+        source_info.insert(format!("{endpoint_id}-v0"), video_source_info);
+      }
+    }
+
     let ecaps2_hash = ecaps2::hash_ecaps2(&ecaps2::compute_disco(&DISCO_INFO)?, Algo::Sha_256)?;
     let mut presence = vec![
       Muc::new().into(),
@@ -191,26 +573,26 @@ impl JitsiConference {
         .append("gst-meet")
         .build(),
       xmpp_parsers::Element::builder("jitsi_participant_codecType", ns::DEFAULT_NS)
-        .append(config.video_codec.as_str())
+        .append(
+          config
+            .video_codecs
+            .first()
+            .map(String::as_str)
+            .unwrap_or_default(),
+        )
         .build(),
       xmpp_parsers::Element::builder("jitsi_participant_codecList", ns::DEFAULT_NS)
-        .append(config.video_codec.as_str())
+        .append(config.video_codecs.join(","))
         .build(),
       // TODO: mute state should be based on whether there is a corresponding element in the send pipeline
       xmpp_parsers::Element::builder("audiomuted", ns::DEFAULT_NS)
-        .append("false")
+        .append(config.receive_only.to_string())
         .build(),
       xmpp_parsers::Element::builder("videomuted", ns::DEFAULT_NS)
-        .append("false")
+        .append((config.receive_only || config.audio_only).to_string())
         .build(),
       xmpp_parsers::Element::builder("SourceInfo", ns::DEFAULT_NS)
-        .append(
-          serde_json::to_string(&serde_json::json!({
-            format!("{endpoint_id}-a0"): {"muted": false},
-            format!("{endpoint_id}-v0"): {"muted": false},
-          }))?
-          .as_str(),
-        )
+        .append(serde_json::to_string(&source_info)?.as_str())
         .build(),
       xmpp_parsers::Element::builder("nick", "http://jabber.org/protocol/nick")
         .append(config.nick.as_str())
@@ -251,9 +633,27 @@ impl JitsiConference {
         on_participant: None,
         on_participant_left: None,
         on_colibri_message: None,
+        on_video_codec_changed: None,
+        on_sender_video_constraint: None,
+        on_bandwidth_estimate: None,
+        on_audio_level: None,
+        bandwidth_estimate: None,
+        bandwidth_estimate_encoder: None,
+        sender_video_constraints: HashMap::new(),
+        sender_video_capsfilters: HashMap::new(),
+        jitterbuffer_configs: hashmap! {
+          MediaType::Audio => config.audio_jitterbuffer.clone(),
+          MediaType::Video => config.video_jitterbuffer.clone(),
+        },
+        jitterbuffers: vec![],
+        participant_video_scale_policies: HashMap::new(),
+        video_scalers: HashMap::new(),
+        participant_video_codecs: HashMap::new(),
+        source_info,
         send_resolution: None,
         connected_tx: Some(tx),
       })),
+      packet_handlers: Default::default(),
       tls_insecure: xmpp_connection.tls_insecure,
     };
 
@@ -315,6 +715,12 @@ impl JitsiConference {
 
   #[tracing::instrument(level = "debug", err)]
   pub async fn set_muted(&self, media_type: MediaType, muted: bool) -> Result<()> {
+    if self.config.receive_only && !muted {
+      bail!("cannot unmute in a receive-only conference");
+    }
+    if self.config.audio_only && media_type == MediaType::Video && !muted {
+      bail!("cannot unmute video in an audio-only conference");
+    }
     let mut locked_inner = self.inner.lock().await;
     let element = xmpp_parsers::Element::builder(
       media_type.jitsi_muted_presence_element_name(),
@@ -375,7 +781,133 @@ impl JitsiConference {
     self.inner.lock().await.video_sink = sink;
   }
 
+  /// The video codec negotiated for sending: the first of [JitsiConferenceConfig::video_codecs]
+  /// that the focus offered. The send pipeline should encode video in this codec.
+  pub async fn video_codec(&self) -> Result<String> {
+    self
+      .jingle_session
+      .lock()
+      .await
+      .as_ref()
+      .context("not connected (no jingle session)")?
+      .video_codec()
+      .context("audio-only conference has no video codec")
+  }
+
+  /// Start sending video in `codec`, which must be one of the video codecs negotiated with the
+  /// focus. This replaces the video sink element, so the send pipeline's encoder must be swapped
+  /// for one producing the new codec: do that from the [JitsiConference::on_video_codec_changed]
+  /// callback, which is called after the switch.
+  #[tracing::instrument(level = "debug", err)]
+  pub async fn set_video_codec(&self, codec: &str) -> Result<()> {
+    if self.config.receive_only || self.config.audio_only {
+      bail!("cannot set the video codec in a receive-only or audio-only conference");
+    }
+    let codec = codec.to_lowercase();
+    let changed = self
+      .jingle_session
+      .lock()
+      .await
+      .as_mut()
+      .context("not connected (no jingle session)")?
+      .set_video_codec(&codec)?;
+    if !changed {
+      return Ok(());
+    }
+    info!("Sending video as {}", codec);
+    self.advertise_video_codec(&codec).await?;
+
+    let on_video_codec_changed = self.inner.lock().await.on_video_codec_changed.clone();
+    if let Some(f) = on_video_codec_changed {
+      if let Err(e) = f(self.clone(), codec).await {
+        warn!("on_video_codec_changed failed: {:?}", e);
+      }
+    }
+    Ok(())
+  }
+
+  /// Advertise the codec that we send video in, in the `jitsi_participant_codecType` presence
+  /// element.
+  async fn advertise_video_codec(&self, codec: &str) -> Result<()> {
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner
+      .presence
+      .retain(|el| el.name() != "jitsi_participant_codecType");
+    locked_inner.presence.push(
+      xmpp_parsers::Element::builder("jitsi_participant_codecType", ns::DEFAULT_NS)
+        .append(codec)
+        .build(),
+    );
+    self.send_presence(&locked_inner.presence).await
+  }
+
+  /// Switch to the most preferred negotiated video codec that every remote participant
+  /// advertises support for, if it is not the current one. This only happens once an
+  /// [JitsiConference::on_video_codec_changed] callback is registered, since otherwise nothing
+  /// would swap the encoder.
+  async fn update_video_codec(&self) -> Result<()> {
+    if self.config.receive_only || self.config.audio_only {
+      return Ok(());
+    }
+    let Some((negotiated, current)) = self
+      .jingle_session
+      .lock()
+      .await
+      .as_ref()
+      .map(|session| (session.video_codecs(), session.video_codec()))
+    else {
+      return Ok(());
+    };
+    let participant_video_codecs: Vec<_> = {
+      let locked_inner = self.inner.lock().await;
+      if locked_inner.on_video_codec_changed.is_none() {
+        return Ok(());
+      }
+      locked_inner
+        .participant_video_codecs
+        .values()
+        .cloned()
+        .collect()
+    };
+    match negotiated.iter().find(|codec| {
+      participant_video_codecs
+        .iter()
+        .all(|codecs| codecs.contains(codec))
+    }) {
+      Some(codec) if Some(codec) != current.as_ref() => self.set_video_codec(codec).await,
+      Some(_) => Ok(()),
+      None => {
+        warn!("no negotiated video codec is supported by every participant");
+        Ok(())
+      },
+    }
+  }
+
   pub async fn audio_sink_element(&self) -> Result<gstreamer::Element> {
+    self
+      .jingle_session
+      .lock()
+      .await
+      .as_ref()
+      .context("not connected (no jingle session)")?
+      .audio_sink_element()
+      .context("receive-only conference has no audio sink element")
+  }
+
+  pub async fn video_sink_element(&self) -> Result<gstreamer::Element> {
+    self
+      .jingle_session
+      .lock()
+      .await
+      .as_ref()
+      .context("not connected (no jingle session)")?
+      .video_sink_element()
+      .context("receive-only or audio-only conference has no video sink element")
+  }
+
+  /// The SSRCs that a remote participant (identified by the resource part of their MUC JID) is
+  /// sending, with the media type of each, as signalled by the focus.
+  pub async fn remote_ssrcs(&self, participant_id: &str) -> Result<Vec<(u32, MediaType)>> {
     Ok(
       self
         .jingle_session
@@ -383,11 +915,52 @@ impl JitsiConference {
         .await
         .as_ref()
         .context("not connected (no jingle session)")?
-        .audio_sink_element(),
+        .remote_ssrc_map
+        .values()
+        .filter(|source| source.participant_id.as_deref() == Some(participant_id))
+        .map(|source| (source.ssrc, source.media_type))
+        .collect(),
     )
   }
 
-  pub async fn video_sink_element(&self) -> Result<gstreamer::Element> {
+  /// Ask a remote participant (identified by the resource part of their MUC JID) for a keyframe
+  /// with an RTCP PLI, for one of their video SSRCs or for all of them if `ssrc` is `None`. Use
+  /// this when starting to record a stream or after recovering from an error downstream; a
+  /// keyframe is already requested whenever a stream is linked to the recv pipeline.
+  #[tracing::instrument(level = "debug", err)]
+  pub async fn request_keyframe(&self, participant_id: &str, ssrc: Option<u32>) -> Result<()> {
+    let jingle_session = self.jingle_session.lock().await;
+    let jingle_session = jingle_session
+      .as_ref()
+      .context("not connected (no jingle session)")?;
+    let ssrcs: Vec<_> = jingle_session
+      .remote_ssrc_map
+      .values()
+      .filter(|source| {
+        source.media_type == MediaType::Video
+          && source.participant_id.as_deref() == Some(participant_id)
+          && ssrc.map_or(true, |ssrc| source.ssrc == ssrc)
+      })
+      .map(|source| source.ssrc)
+      .collect();
+    if ssrcs.is_empty() {
+      bail!("{} has no such video source", participant_id);
+    }
+    for ssrc in ssrcs {
+      if !jingle_session.request_keyframe(ssrc)? {
+        debug!(
+          "not requesting keyframe for ssrc {}: not received yet",
+          ssrc
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// How many keyframe requests for the video we send have been received from the bridge, and
+  /// how many of them were passed on to the send pipeline's encoder. Requests for the same SSRC
+  /// are forwarded at most twice a second; the rest are dropped.
+  pub async fn keyframe_request_stats(&self) -> Result<KeyframeRequestStats> {
     Ok(
       self
         .jingle_session
@@ -395,10 +968,301 @@ impl JitsiConference {
         .await
         .as_ref()
         .context("not connected (no jingle session)")?
-        .video_sink_element(),
+        .keyframe_request_stats(),
     )
   }
 
+  /// Add another outgoing video source, for example a screenshare alongside the camera. It is
+  /// sent in the current video codec without simulcast, and signalled to the focus with a
+  /// Jingle source-add. Returns the source name, which identifies it to
+  /// [JitsiConference::video_source_sink_element] and [JitsiConference::remove_video_source].
+  #[tracing::instrument(level = "debug", err)]
+  pub async fn add_video_source(&self, video_type: VideoType) -> Result<String> {
+    if self.config.receive_only || self.config.audio_only {
+      bail!("cannot add a video source in a receive-only or audio-only conference");
+    }
+    let endpoint_id = self.endpoint_id()?;
+    let video_type_value = serde_json::to_value(&video_type)?;
+    let mut locked_session = self.jingle_session.lock().await;
+    let jingle_session = locked_session
+      .as_mut()
+      .context("not connected (no jingle session)")?;
+    let (source_name, source_add) =
+      jingle_session.add_video_source(endpoint_id, video_type.clone())?;
+    let colibri_channel = jingle_session.colibri_channel.clone();
+    drop(locked_session);
+
+    let iq = Iq::from_set(generate_id(), source_add)
+      .with_to(Jid::Full(self.focus_jid_in_muc()?))
+      .with_from(Jid::Full(self.jid.clone()));
+    self.xmpp_tx.send(iq.into()).await?;
+
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner.source_info.insert(
+      source_name.clone(),
+      serde_json::json!({"muted": false, "videoType": video_type_value}),
+    );
+    self.update_source_info_presence(&mut locked_inner).await?;
+    drop(locked_inner);
+
+    // If the Colibri channel is not connected yet, this is sent when it connects.
+    if let Some(colibri_channel) = colibri_channel {
+      colibri_channel
+        .send(ColibriMessage::SourceVideoTypeMessage {
+          source_name: source_name.clone(),
+          video_type,
+        })
+        .await?;
+    }
+
+    Ok(source_name)
+  }
+
+  /// The element that encoded video for a source added with [JitsiConference::add_video_source]
+  /// should be linked to.
+  pub async fn video_source_sink_element(&self, source_name: &str) -> Result<gstreamer::Element> {
+    self
+      .jingle_session
+      .lock()
+      .await
+      .as_ref()
+      .context("not connected (no jingle session)")?
+      .video_source_sink_element(source_name)
+      .with_context(|| format!("no such video source: {}", source_name))
+  }
+
+  /// Stop sending a video source added with [JitsiConference::add_video_source], and signal its
+  /// removal to the focus with a Jingle source-remove. The application should unlink and remove
+  /// its encoder first.
+  #[tracing::instrument(level = "debug", err)]
+  pub async fn remove_video_source(&self, source_name: &str) -> Result<()> {
+    let source_remove = self
+      .jingle_session
+      .lock()
+      .await
+      .as_mut()
+      .context("not connected (no jingle session)")?
+      .remove_video_source(source_name)?;
+
+    let iq = Iq::from_set(generate_id(), source_remove)
+      .with_to(Jid::Full(self.focus_jid_in_muc()?))
+      .with_from(Jid::Full(self.jid.clone()));
+    self.xmpp_tx.send(iq.into()).await?;
+
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner.source_info.remove(source_name);
+    self.update_source_info_presence(&mut locked_inner).await
+  }
+
+  async fn update_source_info_presence(&self, inner: &mut JitsiConferenceInner) -> Result<()> {
+    inner.presence.retain(|el| el.name() != "SourceInfo");
+    inner.presence.push(
+      xmpp_parsers::Element::builder("SourceInfo", ns::DEFAULT_NS)
+        .append(serde_json::to_string(&inner.source_info)?.as_str())
+        .build(),
+    );
+    self.send_presence(&inner.presence).await
+  }
+
+  /// The name of the video source negotiated in the session-accept. Sources added with
+  /// [JitsiConference::add_video_source] are named by that method.
+  pub fn video_source_name(&self) -> Result<String> {
+    Ok(format!("{}-v0", self.endpoint_id()?))
+  }
+
+  /// The maximum height that the bridge has asked us to send a video source at, which is the
+  /// highest resolution that anyone is currently viewing it at. `None` until the bridge sends a
+  /// constraint for the source.
+  pub async fn sender_video_constraint(&self, source_name: &str) -> Option<i32> {
+    self
+      .inner
+      .lock()
+      .await
+      .sender_video_constraints
+      .get(source_name)
+      .copied()
+  }
+
+  /// Apply the bridge's sender video constraints for a video source to a capsfilter in the send
+  /// pipeline, which should follow a `videoscale` ahead of the encoder. The height in the
+  /// capsfilter's current caps is treated as the maximum: whenever the bridge lowers the
+  /// constraint, the height (and a fixed width, keeping the aspect ratio) is lowered to match,
+  /// and restored when it rises again. For the negotiated source, the send resolution reported
+  /// in stats (see [JitsiConference::set_send_resolution]) is updated too. A constraint of zero
+  /// (nobody is viewing) leaves the caps alone. Pass `None` to stop.
+  #[tracing::instrument(level = "debug", err)]
+  pub async fn set_sender_video_capsfilter(
+    &self,
+    source_name: &str,
+    capsfilter: Option<gstreamer::Element>,
+  ) -> Result<()> {
+    let mut locked_inner = self.inner.lock().await;
+    match capsfilter {
+      Some(capsfilter) => {
+        let caps = capsfilter
+          .property::<Option<gstreamer::Caps>>("caps")
+          .unwrap_or_else(gstreamer::Caps::new_any);
+        locked_inner
+          .sender_video_capsfilters
+          .insert(source_name.to_owned(), (capsfilter, caps));
+      },
+      None => {
+        if let Some((capsfilter, caps)) = locked_inner.sender_video_capsfilters.remove(source_name)
+        {
+          capsfilter.set_property("caps", caps);
+        }
+        return Ok(());
+      },
+    }
+    if let Some(max_height) = locked_inner
+      .sender_video_constraints
+      .get(source_name)
+      .copied()
+    {
+      self.apply_sender_video_constraint(&mut locked_inner, source_name, max_height)?;
+    }
+    Ok(())
+  }
+
+  /// Record a sender video constraint from the bridge, apply it to the source's capsfilter if
+  /// there is one, and notify the application.
+  async fn sender_video_constraint_changed(&self, source_name: String, max_height: i32) {
+    debug!(
+      "bridge asks us to send {} at up to {}p",
+      source_name, max_height
+    );
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner
+      .sender_video_constraints
+      .insert(source_name.clone(), max_height);
+    if let Err(e) = self.apply_sender_video_constraint(&mut locked_inner, &source_name, max_height)
+    {
+      warn!("failed to apply sender video constraint: {:?}", e);
+    }
+    if let Some(f) = locked_inner.on_sender_video_constraint.as_ref().cloned() {
+      drop(locked_inner);
+      if let Err(e) = f(self.clone(), source_name, max_height).await {
+        warn!("on_sender_video_constraint failed: {:?}", e);
+      }
+    }
+  }
+
+  fn apply_sender_video_constraint(
+    &self,
+    inner: &mut JitsiConferenceInner,
+    source_name: &str,
+    max_height: i32,
+  ) -> Result<()> {
+    if max_height <= 0 {
+      return Ok(());
+    }
+    let Some((capsfilter, max_caps)) = inner.sender_video_capsfilters.get(source_name)
+    else {
+      return Ok(());
+    };
+    let caps = if max_caps.is_any() || max_caps.is_empty() {
+      gstreamer::Caps::builder("video/x-raw")
+        .field("height", max_height)
+        .build()
+    }
+    else {
+      let mut caps = max_caps.clone();
+      for structure in caps.make_mut().iter_mut() {
+        let height = structure.get::<i32>("height").ok();
+        if height.map_or(true, |height| height > max_height) {
+          if let (Ok(width), Some(height)) = (structure.get::<i32>("width"), height) {
+            // Keep the aspect ratio, with an even width as most encoders require.
+            let width = (width as i64 * max_height as i64 / height as i64) as i32 & !1;
+            structure.set("width", width);
+          }
+          structure.set("height", max_height);
+        }
+      }
+      caps
+    };
+    debug!("setting {} send caps to {}", source_name, caps);
+    let height = caps
+      .structure(0)
+      .and_then(|structure| structure.get::<i32>("height").ok());
+    capsfilter.set_property("caps", caps);
+    if source_name == self.video_source_name()? {
+      inner.send_resolution = height;
+    }
+    Ok(())
+  }
+
+  /// The most recent estimate of the available send bandwidth, in bits per second, or `None` if
+  /// bandwidth estimation is not enabled (see [JitsiConferenceConfig::bandwidth_estimation]).
+  pub async fn bandwidth_estimate(&self) -> Option<u32> {
+    self.inner.lock().await.bandwidth_estimate
+  }
+
+  /// Set the bitrate of an encoder in the send pipeline to the send bandwidth estimate whenever
+  /// it changes (or, if `encoder` is `None`, stop doing so). The encoder's `target-bitrate` or
+  /// `bitrate` property is used, which is in `unit`. With several encoders (for simulcast, or
+  /// extra video sources), use [JitsiConference::on_bandwidth_estimate] to divide the bandwidth
+  /// between them instead.
+  #[tracing::instrument(level = "debug", err)]
+  pub async fn set_bandwidth_estimate_encoder(
+    &self,
+    encoder: Option<gstreamer::Element>,
+    unit: BitrateUnit,
+  ) -> Result<()> {
+    let encoder = encoder.map(|encoder| (encoder, unit));
+    let mut locked_inner = self.inner.lock().await;
+    if let (Some((encoder, unit)), Some(bitrate)) = (&encoder, locked_inner.bandwidth_estimate) {
+      set_encoder_bitrate(encoder, *unit, bitrate)?;
+    }
+    locked_inner.bandwidth_estimate_encoder = encoder;
+    Ok(())
+  }
+
+  /// Record a new send bandwidth estimate, apply it to the designated encoder if there is one,
+  /// and notify the application.
+  pub(crate) async fn bandwidth_estimate_changed(&self, bitrate: u32) {
+    trace!("send bandwidth estimate: {} bps", bitrate);
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner.bandwidth_estimate = Some(bitrate);
+    if let Some((encoder, unit)) = &locked_inner.bandwidth_estimate_encoder {
+      if let Err(e) = set_encoder_bitrate(encoder, *unit, bitrate) {
+        warn!("failed to set encoder bitrate: {:?}", e);
+      }
+    }
+    if let Some(f) = locked_inner.on_bandwidth_estimate.as_ref().cloned() {
+      drop(locked_inner);
+      if let Err(e) = f(self.clone(), bitrate).await {
+        warn!("on_bandwidth_estimate failed: {:?}", e);
+      }
+    }
+  }
+
+  /// Notify the application of a new audio level read from the `ssrc-audio-level` header
+  /// extension of a received audio stream. `level` is the extension's single byte: the V bit
+  /// followed by the level in -dBov.
+  pub(crate) async fn audio_level_changed(&self, ssrc: u32, level: u8) {
+    let Some(f) = self.inner.lock().await.on_audio_level.as_ref().cloned()
+    else {
+      return;
+    };
+    let participant_id = self
+      .jingle_session
+      .lock()
+      .await
+      .as_ref()
+      .and_then(|jingle_session| jingle_session.remote_ssrc_map.get(&ssrc))
+      .and_then(|source| source.participant_id.clone());
+    let audio_level = AudioLevel {
+      participant_id,
+      ssrc,
+      level_dbov: -((level & 0x7f) as i8),
+      vad: level & 0x80 != 0,
+    };
+    trace!("audio level: {:?}", audio_level);
+    if let Err(e) = f(self.clone(), audio_level).await {
+      warn!("on_audio_level failed: {:?}", e);
+    }
+  }
+
   /// Set the max resolution that we are currently sending.
   ///
   /// Setting this is required for browser clients in the same conference to display
@@ -467,6 +1331,125 @@ impl JitsiConference {
     Ok(())
   }
 
+  /// The current jitterbuffer settings for a media type.
+  pub async fn jitterbuffer_config(&self, media_type: MediaType) -> JitterBufferConfig {
+    self.inner.lock().await.jitterbuffer_configs[&media_type].clone()
+  }
+
+  /// Change the jitterbuffer settings for a media type, both for streams that are already being
+  /// received and for new ones.
+  pub async fn set_jitterbuffer_config(&self, media_type: MediaType, config: JitterBufferConfig) {
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner
+      .jitterbuffers
+      .retain(|(_, _, rtpjitterbuffer)| rtpjitterbuffer.upgrade().is_some());
+    for (_, allow_retransmission, rtpjitterbuffer) in locked_inner
+      .jitterbuffers
+      .iter()
+      .filter(|(jitterbuffer_media_type, ..)| *jitterbuffer_media_type == media_type)
+    {
+      if let Some(rtpjitterbuffer) = rtpjitterbuffer.upgrade() {
+        config.apply(&rtpjitterbuffer, *allow_retransmission);
+      }
+    }
+    locked_inner.jitterbuffer_configs.insert(media_type, config);
+  }
+
+  /// Apply the jitterbuffer settings for a media type to a new jitterbuffer, and keep it so that
+  /// later changes apply too.
+  pub(crate) async fn add_jitterbuffer(
+    &self,
+    media_type: MediaType,
+    allow_retransmission: bool,
+    rtpjitterbuffer: &gstreamer::Element,
+  ) {
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner.jitterbuffer_configs[&media_type].apply(rtpjitterbuffer, allow_retransmission);
+    locked_inner
+      .jitterbuffers
+      .retain(|(_, _, rtpjitterbuffer)| rtpjitterbuffer.upgrade().is_some());
+    locked_inner.jitterbuffers.push((
+      media_type,
+      allow_retransmission,
+      rtpjitterbuffer.downgrade(),
+    ));
+  }
+
+  /// Set how a remote participant's video is scaled, overriding
+  /// [JitsiConferenceConfig::recv_video_scale_policy], or go back to the configured policy with
+  /// `None`. Video that is already being received is rescaled.
+  pub async fn set_participant_video_scale_policy(
+    &self,
+    participant_id: &str,
+    policy: Option<VideoScalePolicy>,
+  ) {
+    let mut locked_inner = self.inner.lock().await;
+    match policy {
+      Some(policy) => locked_inner
+        .participant_video_scale_policies
+        .insert(participant_id.to_owned(), policy),
+      None => locked_inner
+        .participant_video_scale_policies
+        .remove(participant_id),
+    };
+    let policy = policy.unwrap_or(self.config.recv_video_scale_policy);
+    for (videoscale, capsfilter) in locked_inner
+      .video_scalers
+      .get(participant_id)
+      .into_iter()
+      .flatten()
+    {
+      policy.apply(
+        videoscale,
+        capsfilter,
+        self.config.recv_video_scale_width,
+        self.config.recv_video_scale_height,
+      );
+    }
+  }
+
+  /// Apply a remote participant's video scale policy to the elements that one of their video
+  /// streams is scaled with, and keep them so that later policy changes apply too.
+  pub(crate) async fn add_video_scaler(
+    &self,
+    participant_id: &str,
+    videoscale: gstreamer::Element,
+    capsfilter: gstreamer::Element,
+  ) {
+    let mut locked_inner = self.inner.lock().await;
+    locked_inner
+      .participant_video_scale_policies
+      .get(participant_id)
+      .copied()
+      .unwrap_or(self.config.recv_video_scale_policy)
+      .apply(
+        &videoscale,
+        &capsfilter,
+        self.config.recv_video_scale_width,
+        self.config.recv_video_scale_height,
+      );
+    locked_inner
+      .video_scalers
+      .entry(participant_id.to_owned())
+      .or_default()
+      .push((videoscale, capsfilter));
+  }
+
+  /// Register a handler for every RTP packet received from the bridge (after SRTP decryption,
+  /// before depayloading) or sent to it (before SRTP encryption). The SSRC, payload type and so
+  /// on can be read with `gstreamer_rtp::RTPBuffer`, and [JitsiConference::remote_ssrcs] tells
+  /// which SSRCs belong to which participant. The handler is called on GStreamer's streaming
+  /// threads, so it must not block. Registering another handler replaces this one.
+  pub fn on_rtp(&self, f: impl Fn(PacketDirection, &gstreamer::Buffer) + Send + Sync + 'static) {
+    *self.packet_handlers.rtp.write().unwrap() = Some(Arc::new(f));
+  }
+
+  /// Register a handler for every compound RTCP packet received from or sent to the bridge, like
+  /// [JitsiConference::on_rtp].
+  pub fn on_rtcp(&self, f: impl Fn(PacketDirection, &gstreamer::Buffer) + Send + Sync + 'static) {
+    *self.packet_handlers.rtcp.write().unwrap() = Some(Arc::new(f));
+  }
+
   #[tracing::instrument(level = "trace", skip(f))]
   pub async fn on_participant(
     &self,
@@ -511,6 +1494,56 @@ impl JitsiConference {
   ) {
     self.inner.lock().await.on_colibri_message = Some(Arc::new(f));
   }
+
+  /// Register a callback for sender video constraints from the bridge: the name of one of our
+  /// video sources and the maximum height that anyone is currently viewing it at. Use this to
+  /// adapt the encoder when [JitsiConference::set_sender_video_capsfilter] is not enough.
+  #[tracing::instrument(level = "trace", skip(f))]
+  pub async fn on_sender_video_constraint(
+    &self,
+    f: impl (Fn(JitsiConference, String, i32) -> BoxedResultFuture) + Send + Sync + 'static,
+  ) {
+    self.inner.lock().await.on_sender_video_constraint = Some(Arc::new(f));
+  }
+
+  /// Register a callback for changes of the send bandwidth estimate, in bits per second. Use this
+  /// to adapt the send pipeline when [JitsiConference::set_bandwidth_estimate_encoder] is not
+  /// enough.
+  #[tracing::instrument(level = "trace", skip(f))]
+  pub async fn on_bandwidth_estimate(
+    &self,
+    f: impl (Fn(JitsiConference, u32) -> BoxedResultFuture) + Send + Sync + 'static,
+  ) {
+    self.inner.lock().await.on_bandwidth_estimate = Some(Arc::new(f));
+  }
+
+  /// Register a callback for the audio levels of received audio streams, read from the
+  /// `ssrc-audio-level` RTP header extension without decoding the audio. It is called when a
+  /// stream's voice activity flag changes, and otherwise when its level changes, at most every
+  /// 100ms for each stream. Use this for speaking indicators, voice-activated layouts or silence
+  /// detection.
+  #[tracing::instrument(level = "trace", skip(f))]
+  pub async fn on_audio_level(
+    &self,
+    f: impl (Fn(JitsiConference, AudioLevel) -> BoxedResultFuture) + Send + Sync + 'static,
+  ) {
+    self.inner.lock().await.on_audio_level = Some(Arc::new(f));
+  }
+
+  /// Register a callback for changes of the codec that video is sent in, whether requested with
+  /// [JitsiConference::set_video_codec] or made automatically because a remote participant
+  /// cannot decode the current one. The callback should replace the send pipeline's encoder with
+  /// one producing the new codec, linked to the new [JitsiConference::video_sink_element].
+  #[tracing::instrument(level = "trace", skip(f))]
+  pub async fn on_video_codec_changed(
+    &self,
+    f: impl (Fn(JitsiConference, String) -> BoxedResultFuture) + Send + Sync + 'static,
+  ) {
+    self.inner.lock().await.on_video_codec_changed = Some(Arc::new(f));
+    if let Err(e) = self.update_video_codec().await {
+      warn!("failed to update video codec: {:?}", e);
+    }
+  }
 }
 
 #[async_trait]
@@ -635,8 +1668,17 @@ impl StanzaFilter for JitsiConference {
                         .with_from(Jid::Full(self.jid.clone()));
                       self.xmpp_tx.send(result_iq.into()).await?;
 
-                      *self.jingle_session.lock().await =
-                        Some(JingleSession::initiate(self, jingle).await?);
+                      let jingle_session = JingleSession::initiate(self, jingle).await?;
+                      // The codec we send may not be the first one we prefer, if that was not
+                      // offered.
+                      let video_codec = jingle_session.video_codec();
+                      *self.jingle_session.lock().await = Some(jingle_session);
+                      if let Some(video_codec) = video_codec {
+                        self.advertise_video_codec(&video_codec).await?;
+                      }
+                      if let Err(e) = self.update_video_codec().await {
+                        warn!("failed to update video codec: {:?}", e);
+                      }
                     }
                     else {
                       debug!("Ignored Jingle session-initiate from {}", from_jid);
@@ -685,15 +1727,40 @@ impl StanzaFilter for JitsiConference {
 
                     let my_endpoint_id = self.endpoint_id()?.to_owned();
 
-                    info!("Sending source video type message");
-                    if let Err(e) = colibri_channel
-                      .send(ColibriMessage::SourceVideoTypeMessage {
-                        source_name: format!("{my_endpoint_id}-v0"),
-                        video_type: colibri::VideoType::Camera,
-                      })
-                      .await
-                    {
-                      warn!("Failed to send source video type message: {e:?}");
+                    if self.config.audio_only {
+                      info!("Audio-only: asking the bridge not to forward video");
+                      if let Err(e) = colibri_channel
+                        .send(ColibriMessage::ReceiverVideoConstraints {
+                          last_n: Some(0),
+                          selected_endpoints: None,
+                          on_stage_endpoints: None,
+                          default_constraints: Some(colibri::Constraints {
+                            max_height: Some(0),
+                            ideal_height: None,
+                          }),
+                          constraints: None,
+                        })
+                        .await
+                      {
+                        warn!("Failed to send receiver video constraints: {e:?}");
+                      }
+                    }
+                    else if !self.config.receive_only {
+                      info!("Sending source video type messages");
+                      let sources =
+                        std::iter::once((format!("{my_endpoint_id}-v0"), VideoType::Camera))
+                          .chain(jingle_session.extra_video_sources());
+                      for (source_name, video_type) in sources {
+                        if let Err(e) = colibri_channel
+                          .send(ColibriMessage::SourceVideoTypeMessage {
+                            source_name,
+                            video_type,
+                          })
+                          .await
+                        {
+                          warn!("Failed to send source video type message: {e:?}");
+                        }
+                      }
                     }
 
                     {
@@ -888,6 +1955,14 @@ impl StanzaFilter for JitsiConference {
                       tokio::spawn(async move {
                         let mut stream = ReceiverStream::new(rx);
                         while let Some(msg) = stream.next().await {
+                          if let Some((source_name, max_height)) =
+                            sender_video_constraint(&msg, &my_endpoint_id)
+                          {
+                            self_
+                              .sender_video_constraint_changed(source_name, max_height)
+                              .await;
+                          }
+
                           // Some message types are handled internally rather than passed to the on_colibri_message handler.
                           let handled = match &msg {
                             ColibriMessage::EndpointMessage {
@@ -959,6 +2034,37 @@ impl StanzaFilter for JitsiConference {
                 .find(|e| e.is("nick", ns::NICK))
                 .map(|e| Nick::try_from(e.clone()))
                 .transpose()?;
+              if from.resource_str() != self.endpoint_id()? {
+                let video_codecs = presence
+                  .payloads
+                  .iter()
+                  .find(|e| e.name() == "jitsi_participant_codecList")
+                  .map(|e| parse_video_codecs(&e.text()));
+                let changed = {
+                  let mut locked_inner = self.inner.lock().await;
+                  if presence.type_ == presence::Type::Unavailable {
+                    locked_inner.video_scalers.remove(from.resource_str());
+                    locked_inner
+                      .participant_video_codecs
+                      .remove(from.resource())
+                      .is_some()
+                  }
+                  else if let Some(video_codecs) = video_codecs {
+                    locked_inner
+                      .participant_video_codecs
+                      .insert(from.resource().clone(), video_codecs.clone())
+                      != Some(video_codecs)
+                  }
+                  else {
+                    false
+                  }
+                };
+                if changed {
+                  if let Err(e) = self.update_video_codec().await {
+                    warn!("failed to update video codec: {:?}", e);
+                  }
+                }
+              }
               if let Some(mut muc_user_payload) = presence
                 .payloads
                 .into_iter()
@@ -996,6 +2102,9 @@ impl StanzaFilter for JitsiConference {
                         .is_some()
                     {
                       debug!("participant left: {:?}", jid);
+                      if let Some(jingle_session) = self.jingle_session.lock().await.as_ref() {
+                        jingle_session.participant_left(&from.resource().to_string());
+                      }
                       if let Some(f) = &self
                         .inner
                         .lock()
@@ -1046,6 +2155,49 @@ impl StanzaFilter for JitsiConference {
   }
 }
 
+/// The source name and maximum height from a `SenderSourceConstraints` message, or from the
+/// older endpoint-wide `SenderVideoConstraints`, which applies to our negotiated video source.
+fn sender_video_constraint(message: &ColibriMessage, endpoint_id: &str) -> Option<(String, i32)> {
+  match message {
+    ColibriMessage::SenderSourceConstraints {
+      source_name,
+      max_height,
+    } => Some((source_name.clone(), *max_height)),
+    ColibriMessage::SenderVideoConstraints { video_constraints } => {
+      Some((format!("{endpoint_id}-v0"), video_constraints.ideal_height?))
+    },
+    _ => None,
+  }
+}
+
+/// Set an encoder's bitrate property, converting from bits per second to the unit it uses.
+fn set_encoder_bitrate(
+  encoder: &gstreamer::Element,
+  unit: BitrateUnit,
+  bitrate: u32,
+) -> Result<()> {
+  let property = ["target-bitrate", "bitrate"]
+    .into_iter()
+    .find(|property| encoder.has_property(property, None))
+    .with_context(|| format!("{} has no bitrate property", encoder.name()))?;
+  let bitrate = match unit {
+    BitrateUnit::BitsPerSecond => bitrate as u64,
+    BitrateUnit::KilobitsPerSecond => bitrate as u64 / 1000,
+  };
+  let pspec = encoder
+    .find_property(property)
+    .context("bitrate property disappeared")?;
+  let value = match pspec.value_type() {
+    glib::Type::I32 => (bitrate.min(i32::MAX as u64) as i32).to_value(),
+    glib::Type::U32 => (bitrate.min(u32::MAX as u64) as u32).to_value(),
+    glib::Type::I64 => (bitrate as i64).to_value(),
+    glib::Type::U64 => bitrate.to_value(),
+    other => bail!("unsupported type for {}: {}", property, other),
+  };
+  encoder.set_property_from_value(property, &value);
+  Ok(())
+}
+
 fn endpoint_id_for_jid(jid: &FullJid) -> Result<&str> {
   jid
     .node_str()
@@ -0,0 +1,428 @@
+//! The AV1 dependency descriptor RTP header extension, which tells the bridge how the frames of
+//! an SVC video stream depend on each other, so that it can drop layers per receiver.
+//!
+//! Only AV1 temporal scalability is supported. Spatial layers would need each layer's frame of a
+//! temporal unit packetized and described separately, which `rtpav1pay` does not do, and
+//! `vp9enc` does not expose the layer of each frame it produces, so VP9 is only described as a
+//! single layer.
+//!
+//! https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use gstreamer_rtp::RTPBuffer;
+
+pub(crate) const RTP_HDREXT_DEPENDENCY_DESCRIPTOR: &str =
+  "https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension";
+
+// Decode target indications.
+const DTI_NOT_PRESENT: u8 = 0;
+const DTI_DISCARDABLE: u8 = 1;
+const DTI_SWITCH: u8 = 2;
+
+/// An SVC scalability mode, named as in the WebRTC SVC specification. Only the modes with a
+/// single spatial layer (L1Tx) exist, since only AV1 temporal scalability is supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalabilityMode {
+  L1T1,
+  L1T2,
+  L1T3,
+}
+
+impl ScalabilityMode {
+  pub(crate) fn temporal_layers(self) -> u8 {
+    match self {
+      ScalabilityMode::L1T1 => 1,
+      ScalabilityMode::L1T2 => 2,
+      ScalabilityMode::L1T3 => 3,
+    }
+  }
+
+  /// The number of frames before the temporal layering pattern repeats.
+  fn period(self) -> u64 {
+    match self {
+      ScalabilityMode::L1T1 => 1,
+      ScalabilityMode::L1T2 => 2,
+      ScalabilityMode::L1T3 => 4,
+    }
+  }
+
+  /// The frame templates: the keyframe, then one per temporal layer. Each decode target is a
+  /// temporal layer along with those below it.
+  fn templates(self) -> Vec<Template> {
+    let decode_targets = self.temporal_layers();
+    let template = |temporal_id: u8, fdiffs: Vec<u8>| Template {
+      temporal_id,
+      dtis: (0..decode_targets)
+        .map(|decode_target| {
+          if temporal_id > decode_target {
+            DTI_NOT_PRESENT
+          }
+          else if temporal_id == decode_target && temporal_id > 0 {
+            DTI_DISCARDABLE
+          }
+          else {
+            DTI_SWITCH
+          }
+        })
+        .collect(),
+      fdiffs,
+    };
+    // Each frame references the closest earlier frame in a lower (or, for T0, the same)
+    // temporal layer.
+    let mut templates = vec![template(0, vec![]), template(0, vec![self.period() as u8])];
+    for temporal_id in 1..decode_targets {
+      templates.push(template(
+        temporal_id,
+        vec![(self.period() >> temporal_id) as u8],
+      ));
+    }
+    templates
+  }
+}
+
+impl FromStr for ScalabilityMode {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "L1T1" => Ok(ScalabilityMode::L1T1),
+      "L1T2" => Ok(ScalabilityMode::L1T2),
+      "L1T3" => Ok(ScalabilityMode::L1T3),
+      other => Err(anyhow!(
+        "unsupported scalability mode: {} (supported: L1T1, L1T2, L1T3)",
+        other
+      )),
+    }
+  }
+}
+
+impl fmt::Display for ScalabilityMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(self, f)
+  }
+}
+
+struct Template {
+  temporal_id: u8,
+  dtis: Vec<u8>,
+  fdiffs: Vec<u8>,
+}
+
+#[derive(Default)]
+struct BitWriter {
+  bytes: Vec<u8>,
+  bits: usize,
+}
+
+impl BitWriter {
+  fn write(&mut self, value: u32, bits: usize) {
+    for i in (0..bits).rev() {
+      if self.bits % 8 == 0 {
+        self.bytes.push(0);
+      }
+      if value >> i & 1 == 1 {
+        *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+      }
+      self.bits += 1;
+    }
+  }
+
+  /// Write `value` as a non-symmetric unsigned integer less than `n`.
+  fn write_ns(&mut self, value: u32, n: u32) {
+    let w = 32 - n.leading_zeros() as usize;
+    let m = (1 << w) - n;
+    if value < m {
+      self.write(value, w - 1);
+    }
+    else {
+      let v = value + m;
+      self.write(v >> 1, w - 1);
+      self.write(v & 1, 1);
+    }
+  }
+}
+
+struct Frame {
+  template_index: u8,
+  keyframe: bool,
+  first_packet: bool,
+}
+
+/// Writes a dependency descriptor into every RTP packet of one video stream.
+pub(crate) struct DependencyDescriptorWriter {
+  mode: ScalabilityMode,
+  ext_id: u8,
+  templates: Vec<Template>,
+  frame_number: u16,
+  frame: Option<Frame>,
+}
+
+impl DependencyDescriptorWriter {
+  pub(crate) fn new(mode: ScalabilityMode, ext_id: u8) -> Self {
+    Self {
+      mode,
+      ext_id,
+      templates: mode.templates(),
+      frame_number: 0,
+      frame: None,
+    }
+  }
+
+  /// Called with each encoded frame before it is payloaded, with the frame's temporal layer as
+  /// signalled by the encoder.
+  pub(crate) fn start_frame(&mut self, keyframe: bool, temporal_id: u8) {
+    let temporal_id = temporal_id.min(self.mode.temporal_layers() - 1);
+    let template_index = if keyframe {
+      0
+    }
+    else {
+      self
+        .templates
+        .iter()
+        .rposition(|template| template.temporal_id == temporal_id)
+        .unwrap_or_default() as u8
+    };
+    self.frame_number = self.frame_number.wrapping_add(1);
+    self.frame = Some(Frame {
+      template_index,
+      keyframe,
+      first_packet: true,
+    });
+  }
+
+  /// Called with each RTP packet of the current frame. The marker bit ends the frame.
+  pub(crate) fn write(&mut self, buffer: &mut gstreamer::BufferRef) -> Result<()> {
+    let mut rtp_buffer = RTPBuffer::from_buffer_writable(buffer)?;
+    let descriptor = self.next_descriptor(rtp_buffer.is_marker())?;
+    rtp_buffer
+      .add_extension_onebyte_header(self.ext_id, &descriptor)
+      .map_err(|e| anyhow!("failed to add dependency descriptor: {}", e))
+  }
+
+  /// The descriptor for the next packet of the current frame.
+  fn next_descriptor(&mut self, end_of_frame: bool) -> Result<Vec<u8>> {
+    let Some(frame) = &mut self.frame
+    else {
+      bail!("RTP packet before any frame");
+    };
+    let first_packet = frame.first_packet;
+    let keyframe = frame.keyframe;
+    let template_index = frame.template_index;
+    frame.first_packet = false;
+
+    let mut writer = BitWriter::default();
+    writer.write(first_packet as u32, 1);
+    writer.write(end_of_frame as u32, 1);
+    // The template ID offset is always zero.
+    writer.write(template_index as u32, 6);
+    writer.write(self.frame_number as u32, 16);
+    if keyframe && first_packet {
+      // Only the template dependency structure is present.
+      writer.write(0b10000, 5);
+      self.write_structure(&mut writer);
+    }
+    Ok(writer.bytes)
+  }
+
+  fn write_structure(&self, writer: &mut BitWriter) {
+    let decode_targets = self.mode.temporal_layers() as u32;
+    writer.write(0, 6);
+    writer.write(decode_targets - 1, 5);
+    for (i, template) in self.templates.iter().enumerate() {
+      let next_layer_idc = match self.templates.get(i + 1) {
+        Some(next) if next.temporal_id == template.temporal_id => 0,
+        Some(_) => 1,
+        None => 3,
+      };
+      writer.write(next_layer_idc, 2);
+    }
+    for template in &self.templates {
+      for dti in &template.dtis {
+        writer.write(*dti as u32, 2);
+      }
+    }
+    for template in &self.templates {
+      for fdiff in &template.fdiffs {
+        writer.write(1, 1);
+        writer.write(*fdiff as u32 - 1, 4);
+      }
+      writer.write(0, 1);
+    }
+    // No chains.
+    writer.write_ns(0, decode_targets + 1);
+    // No render resolutions.
+    writer.write(0, 1);
+  }
+}
+
+/// The temporal layer of an AV1 temporal unit, from the extension header of its first OBU that
+/// has one.
+pub(crate) fn av1_temporal_id(mut data: &[u8]) -> Option<u8> {
+  while let Some(&header) = data.first() {
+    let extension_flag = header & 0b100 != 0;
+    let has_size_field = header & 0b10 != 0;
+    if extension_flag {
+      return data.get(1).map(|extension| extension >> 5);
+    }
+    if !has_size_field {
+      return None;
+    }
+    // Skip the header and the LEB128 size to the next OBU.
+    let mut size = 0usize;
+    let mut offset = 1;
+    loop {
+      let byte = *data.get(offset)?;
+      size |= ((byte & 0x7f) as usize) << (7 * (offset - 1));
+      offset += 1;
+      if byte & 0x80 == 0 || offset > 8 {
+        break;
+      }
+    }
+    data = data.get(offset + size..)?;
+  }
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Packs a string of '0' and '1' characters, ignoring spaces, into bytes, padding the last
+  /// with zeros.
+  fn bits(s: &str) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    for c in s.chars().filter(|c| !c.is_whitespace()) {
+      writer.write((c == '1') as u32, 1);
+    }
+    writer.bytes
+  }
+
+  #[test]
+  fn writes_bits_msb_first() {
+    let mut writer = BitWriter::default();
+    writer.write(0b101, 3);
+    writer.write(0x1ff, 9);
+    assert_eq!(writer.bytes, [0b1011_1111, 0b1111_0000]);
+    assert_eq!(writer.bits, 12);
+  }
+
+  #[test]
+  fn writes_non_symmetric_integers() {
+    // With n = 5, values below 3 take two bits and the rest three.
+    let mut writer = BitWriter::default();
+    for value in 0..5 {
+      writer.write_ns(value, 5);
+    }
+    assert_eq!(writer.bytes, bits("00 01 10 110 111"));
+
+    // A power of two is written in plain binary.
+    let mut writer = BitWriter::default();
+    writer.write_ns(3, 4);
+    assert_eq!(writer.bytes, bits("11"));
+  }
+
+  #[test]
+  fn writes_l1t2_keyframe_structure() -> Result<()> {
+    let mut writer = DependencyDescriptorWriter::new(ScalabilityMode::L1T2, 11);
+    writer.start_frame(true, 0);
+    // start_of_frame, end_of_frame, template 0, frame 1;
+    // only the template dependency structure is present;
+    // template ID offset 0, two decode targets;
+    // templates T0 (keyframe), T0, T1;
+    // DTIs SS, SS, -D;
+    // fdiffs none, 2, 1;
+    // no chains;
+    // no render resolutions.
+    assert_eq!(
+      writer.next_descriptor(true)?,
+      bits(
+        "1 1 000000 0000000000000001 \
+         10000 \
+         000000 00001 \
+         00 01 11 \
+         1010 1010 0001 \
+         0 1 0001 0 1 0000 0 \
+         0 \
+         0"
+      )
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn writes_l1t3_keyframe_structure() -> Result<()> {
+    let mut writer = DependencyDescriptorWriter::new(ScalabilityMode::L1T3, 11);
+    writer.start_frame(true, 0);
+    // start_of_frame, not end_of_frame, template 0, frame 1;
+    // only the template dependency structure is present;
+    // template ID offset 0, three decode targets;
+    // templates T0 (keyframe), T0, T1, T2;
+    // DTIs SSS, SSS, -DS, --D;
+    // fdiffs none, 4, 2, 1;
+    // no chains;
+    // no render resolutions.
+    assert_eq!(
+      writer.next_descriptor(false)?,
+      bits(
+        "1 0 000000 0000000000000001 \
+         10000 \
+         000000 00010 \
+         00 01 01 11 \
+         101010 101010 000110 000001 \
+         0 1 0011 0 1 0001 0 1 0000 0 \
+         00 \
+         0"
+      )
+    );
+
+    // Later packets of the keyframe, and delta frames, only carry the mandatory fields.
+    assert_eq!(
+      writer.next_descriptor(true)?,
+      bits("0 1 000000 0000000000000001")
+    );
+    writer.start_frame(false, 2);
+    assert_eq!(
+      writer.next_descriptor(true)?,
+      bits("1 1 000011 0000000000000010")
+    );
+    writer.start_frame(false, 1);
+    assert_eq!(
+      writer.next_descriptor(true)?,
+      bits("1 1 000010 0000000000000011")
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_packets_before_a_frame() {
+    let mut writer = DependencyDescriptorWriter::new(ScalabilityMode::L1T1, 11);
+    assert!(writer.next_descriptor(true).is_err());
+  }
+
+  #[test]
+  fn reads_av1_temporal_id() {
+    // A temporal delimiter, then a frame OBU with an extension header for temporal layer 2.
+    assert_eq!(
+      av1_temporal_id(&[0x12, 0x00, 0x36, 0x40, 0x01, 0xaa]),
+      Some(2)
+    );
+    // Padding with a two byte LEB128 size (130) before a frame OBU in temporal layer 1.
+    let mut data = vec![0x7a, 0x82, 0x01];
+    data.extend([0; 130]);
+    data.extend([0x36, 0x20, 0x01, 0xaa]);
+    assert_eq!(av1_temporal_id(&data), Some(1));
+  }
+
+  #[test]
+  fn reads_no_av1_temporal_id_without_extension_headers() {
+    // A temporal delimiter, then a frame OBU without an extension header.
+    assert_eq!(av1_temporal_id(&[0x12, 0x00, 0x32, 0x01, 0xaa]), None);
+    // An OBU without a size field, so the next one cannot be found.
+    assert_eq!(av1_temporal_id(&[0x30, 0xaa, 0x36, 0x40]), None);
+    // An OBU whose size runs past the end of the data.
+    assert_eq!(av1_temporal_id(&[0x32, 0x05, 0xaa]), None);
+    assert_eq!(av1_temporal_id(&[]), None);
+  }
+}
@@ -1,6 +1,16 @@
-use std::{collections::HashMap, fmt, net::SocketAddr};
+use std::{
+  collections::HashMap,
+  fmt,
+  net::SocketAddr,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, bail, Context, Result};
+use colibri::VideoType;
 use futures::stream::StreamExt as _;
 use glib::{
   object::ObjectExt as _,
@@ -9,43 +19,47 @@ use glib::{
 use gstreamer::{
   prelude::{
     ElementExt as _, ElementExtManual as _, GObjectExtManualGst as _, GstBinExt as _,
-    GstBinExtManual as _, GstObjectExt as _, PadExt as _,
+    GstBinExtManual as _, GstObjectExt as _, PadExt as _, PadExtManual as _,
   },
   Bin, GhostPad,
 };
-#[cfg(feature = "log-rtp")]
-use gstreamer_rtp::RTPBuffer;
-use gstreamer_rtp::{prelude::RTPHeaderExtensionExt as _, RTPHeaderExtension};
+use gstreamer_rtp::{prelude::RTPHeaderExtensionExt as _, RTPBuffer, RTPHeaderExtension};
 use jitsi_xmpp_parsers::{
   jingle::{Action, Content, Description, Jingle, Transport},
   jingle_dtls_srtp::Fingerprint,
   jingle_ice_udp::Transport as IceUdpTransport,
   jingle_rtp::Description as RtpDescription,
-  jingle_ssma::{self, Parameter},
+  jingle_ssma::{self, Parameter, Semantics},
 };
 use nice_gst_meet as nice;
 use rand::random;
 use sha2::{Digest as _, Sha256};
-use tokio::{net::lookup_host, runtime::Handle, sync::oneshot, task::JoinHandle};
+use tokio::{
+  net::lookup_host,
+  runtime::Handle,
+  sync::{mpsc, oneshot},
+  task::JoinHandle,
+};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 use xmpp_parsers::{
   hashes::Algo,
   iq::Iq,
-  jingle::{Creator, Senders},
+  jingle::{ContentId, Creator, Senders, SessionId},
   jingle_dtls_srtp::Setup,
   jingle_grouping::{self, Content as GroupContent},
   jingle_ice_udp,
   jingle_rtcp_fb::RtcpFb,
   jingle_rtp::{self, PayloadType, RtcpMux},
   jingle_rtp_hdrext::RtpHdrext,
-  jingle_ssma::Semantics,
   Jid,
 };
 
 use crate::{
+  codec_registry::CodecRegistry,
   colibri::ColibriChannel,
-  conference::JitsiConference,
+  conference::{JitsiConference, KeyframeRequestStats, PacketDirection},
+  dependency_descriptor::{self, DependencyDescriptorWriter, ScalabilityMode},
   source::{MediaType, Source},
   util::generate_id,
 };
@@ -57,6 +71,13 @@ const RTP_HDREXT_TRANSPORT_CC: &str =
 const DEFAULT_STUN_PORT: u16 = 3478;
 const DEFAULT_TURNS_PORT: u16 = 5349;
 
+/// Keyframe requests for one of our video SSRCs that arrive sooner than this after the last one
+/// we forwarded to the encoder are dropped; the keyframe is already on its way.
+const KEYFRAME_REQUEST_MIN_INTERVAL: Duration = Duration::from_millis(500);
+/// Audio level changes of a received stream are reported at most this often, unless its voice
+/// activity flag changes.
+const AUDIO_LEVEL_MIN_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Clone, PartialEq)]
 enum CodecName {
   Opus,
@@ -71,10 +92,76 @@ struct Codec {
   name: CodecName,
   pt: u8,
   rtx_pt: Option<u8>,
+  /// The RFC 2198 redundant audio payload type wrapping this codec, if negotiated.
+  red_pt: Option<u8>,
+  /// Whether Opus in-band FEC was offered.
+  inband_fec: bool,
+  /// Whether Opus DTX was offered.
+  dtx: bool,
+  /// The offered fmtp parameters.
+  parameters: Vec<jingle_rtp::Parameter>,
   rtcp_fbs: Vec<RtcpFb>,
 }
 
 impl Codec {
+  fn new(name: CodecName, pt: &PayloadType) -> Self {
+    Self {
+      name,
+      pt: pt.id,
+      rtx_pt: None,
+      red_pt: None,
+      inband_fec: false,
+      dtx: false,
+      parameters: pt.parameters.clone(),
+      rtcp_fbs: pt.rtcp_fbs.clone(),
+    }
+  }
+
+  fn parameter(&self, name: &str) -> Option<&str> {
+    self
+      .parameters
+      .iter()
+      .find(|param| param.name == name)
+      .map(|param| param.value.as_str())
+  }
+
+  /// The GStreamer name of the H.264 profile in the offered `profile-level-id`.
+  fn h264_profile(&self) -> Option<&'static str> {
+    let profile_level_id = self.parameter("profile-level-id")?;
+    let profile_idc = u8::from_str_radix(profile_level_id.get(0..2)?, 16).ok()?;
+    let profile_iop = u8::from_str_radix(profile_level_id.get(2..4)?, 16).ok()?;
+    match profile_idc {
+      0x42 if profile_iop & 0x40 != 0 => Some("constrained-baseline"),
+      0x42 => Some("baseline"),
+      0x4d => Some("main"),
+      0x58 => Some("extended"),
+      0x64 if profile_iop & 0x0c == 0x0c => Some("constrained-high"),
+      0x64 => Some("high"),
+      0x6e => Some("high-10"),
+      0x7a => Some("high-4:2:2"),
+      0xf4 => Some("high-4:4:4"),
+      _ => None,
+    }
+  }
+
+  /// The H.264 profiles that an encoder may produce for the offered `profile-level-id`.
+  /// Constrained baseline is a subset of baseline. GStreamer's encoders have no constrained high
+  /// profile, so an encoder producing high is accepted for a constrained high offer.
+  fn h264_encoder_profiles(&self) -> &'static [&'static str] {
+    match self.h264_profile() {
+      Some("baseline") => &["baseline", "constrained-baseline"],
+      Some("constrained-baseline") => &["constrained-baseline"],
+      Some("main") => &["main"],
+      Some("extended") => &["extended"],
+      Some("constrained-high") => &["constrained-high", "high"],
+      Some("high") => &["high"],
+      Some("high-10") => &["high-10"],
+      Some("high-4:2:2") => &["high-4:2:2"],
+      Some("high-4:4:4") => &["high-4:4:4"],
+      _ => &[],
+    }
+  }
+
   fn is(&self, pt: u8) -> bool {
     self.pt == pt
   }
@@ -126,6 +213,17 @@ impl Codec {
     }
   }
 
+  /// A parser that completes the depayloaded caps (resolution, profile and so on) for muxers,
+  /// when the stream is not decoded.
+  fn parser_name(&self) -> Option<&'static str> {
+    match self.name {
+      CodecName::Opus | CodecName::Vp8 => None,
+      CodecName::H264 => Some("h264parse"),
+      CodecName::Vp9 => Some("vp9parse"),
+      CodecName::Av1 => Some("av1parse"),
+    }
+  }
+
   fn decoder_name(&self) -> &'static str {
     match self.name {
       CodecName::Opus => "opusdec",
@@ -152,18 +250,80 @@ struct ParsedRtpDescription {
   audio_hdrext_ssrc_audio_level: Option<u16>,
   audio_hdrext_transport_cc: Option<u16>,
   video_hdrext_transport_cc: Option<u16>,
+  video_hdrext_dependency_descriptor: Option<u16>,
+}
+
+/// A video source added with [JitsiConference::add_video_source], in addition to the one
+/// negotiated in the session-accept.
+struct ExtraVideoSource {
+  video_type: VideoType,
+  msid: String,
+  ssrc: u32,
+  rtx_ssrc: u32,
+  sink_element: gstreamer::Element,
+}
+
+/// Keyframe requests received for our video SSRCs, shared with the probe that forwards them to
+/// the send pipeline.
+#[derive(Default)]
+struct KeyframeRequests {
+  /// When a request was last forwarded for each SSRC.
+  last_forwarded: HashMap<u32, Instant>,
+  stats: KeyframeRequestStats,
+}
+
+impl KeyframeRequests {
+  /// Whether a request for `ssrc` arriving `now` should be forwarded, counting it either way.
+  fn allow(&mut self, ssrc: u32, now: Instant) -> bool {
+    let too_soon = self.last_forwarded.get(&ssrc).map_or(false, |last| {
+      now.duration_since(*last) < KEYFRAME_REQUEST_MIN_INTERVAL
+    });
+    if too_soon {
+      self.stats.dropped += 1;
+      false
+    }
+    else {
+      self.last_forwarded.insert(ssrc, now);
+      self.stats.forwarded += 1;
+      true
+    }
+  }
 }
 
 pub(crate) struct JingleSession {
+  sid: SessionId,
   pipeline: gstreamer::Pipeline,
-  audio_sink_element: gstreamer::Element,
-  video_sink_element: gstreamer::Element,
+  audio_sink_element: Option<gstreamer::Element>,
+  video_sink_element: Option<gstreamer::Element>,
   pub(crate) remote_ssrc_map: HashMap<u32, Source>,
   _ice_agent: nice::Agent,
   pub(crate) accept_iq_id: Option<String>,
   pub(crate) colibri_url: Option<String>,
   pub(crate) colibri_channel: Option<ColibriChannel>,
   pub(crate) stats_handler_task: Option<JoinHandle<()>>,
+  audio_only: bool,
+  /// The negotiated video codecs, in order of preference.
+  video_codecs: Vec<Codec>,
+  /// The video codec currently being sent.
+  video_codec: Option<Codec>,
+  /// One SSRC per simulcast layer, lowest resolution first.
+  video_ssrcs: Vec<u32>,
+  /// The scalability mode and header extension ID to write dependency descriptors with, if the
+  /// dependency descriptor was negotiated.
+  dependency_descriptor: Option<(ScalabilityMode, u8)>,
+  codec_registry: CodecRegistry,
+  /// The header extension ID to write transport-wide sequence numbers in video packets with, if
+  /// bandwidth estimation is enabled.
+  video_transport_cc: Option<u8>,
+  /// Every video SSRC we send, mapped to its RTX SSRC. Shared with the RTX sender, which is
+  /// created when the pipeline starts.
+  video_rtx_ssrc_map: Arc<Mutex<HashMap<u32, u32>>>,
+  extra_video_sources: HashMap<String, ExtraVideoSource>,
+  next_video_source_index: u32,
+  rtpfunnel: Option<gstreamer::Element>,
+  keyframe_requests: Arc<Mutex<KeyframeRequests>>,
+  /// The last audio level reported for each received SSRC, and when.
+  audio_levels_reported: Arc<Mutex<HashMap<u32, (u8, Instant)>>>,
   pipeline_state_null_rx: oneshot::Receiver<()>,
 }
 
@@ -178,14 +338,285 @@ impl JingleSession {
     self.pipeline.clone()
   }
 
-  pub(crate) fn audio_sink_element(&self) -> gstreamer::Element {
+  pub(crate) fn audio_sink_element(&self) -> Option<gstreamer::Element> {
     self.audio_sink_element.clone()
   }
 
-  pub(crate) fn video_sink_element(&self) -> gstreamer::Element {
+  pub(crate) fn video_sink_element(&self) -> Option<gstreamer::Element> {
     self.video_sink_element.clone()
   }
 
+  pub(crate) fn keyframe_request_stats(&self) -> KeyframeRequestStats {
+    self.keyframe_requests.lock().unwrap().stats
+  }
+
+  pub(crate) fn video_codec(&self) -> Option<String> {
+    self
+      .video_codec
+      .as_ref()
+      .map(|codec| codec.encoding_name().to_lowercase())
+  }
+
+  pub(crate) fn video_codecs(&self) -> Vec<String> {
+    self
+      .video_codecs
+      .iter()
+      .map(|codec| codec.encoding_name().to_lowercase())
+      .collect()
+  }
+
+  /// Start sending video in another negotiated codec, replacing the video payloader (and so the
+  /// video sink element). Returns whether the codec changed.
+  pub(crate) fn set_video_codec(&mut self, name: &str) -> Result<bool> {
+    let codec = self
+      .video_codecs
+      .iter()
+      .find(|codec| codec.is_codec(name))
+      .with_context(|| format!("video codec was not negotiated: {}", name))?
+      .clone();
+    if self.video_codec.as_ref().map(|current| current.pt) == Some(codec.pt) {
+      return Ok(false);
+    }
+
+    if let (Some(old_video_sink), Some(rtpfunnel)) =
+      (self.video_sink_element.clone(), self.rtpfunnel.clone())
+    {
+      let video_sink = JingleSession::make_video_sink(
+        &self.codec_registry,
+        &codec,
+        &self.video_ssrcs,
+        self.dependency_descriptor,
+        self.video_transport_cc,
+      )?;
+      JingleSession::replace_video_sink(&self.pipeline, &old_video_sink, &video_sink, &rtpfunnel)?;
+      self.video_sink_element = Some(video_sink);
+
+      for source in self.extra_video_sources.values_mut() {
+        let sink_element = JingleSession::make_video_payloader(
+          &self.codec_registry,
+          &codec,
+          source.ssrc,
+          self.dependency_descriptor,
+          self.video_transport_cc,
+        )?;
+        JingleSession::replace_video_sink(
+          &self.pipeline,
+          &source.sink_element,
+          &sink_element,
+          &rtpfunnel,
+        )?;
+        source.sink_element = sink_element;
+      }
+    }
+
+    self.video_codec = Some(codec);
+    Ok(true)
+  }
+
+  /// Swap a video sink element for a new one, leaving the new one's sink pads for the
+  /// application to link.
+  fn replace_video_sink(
+    pipeline: &gstreamer::Pipeline,
+    old_video_sink: &gstreamer::Element,
+    video_sink: &gstreamer::Element,
+    rtpfunnel: &gstreamer::Element,
+  ) -> Result<()> {
+    for old_sink_pad in old_video_sink.sink_pads() {
+      if let Some(upstream_pad) = old_sink_pad.peer() {
+        // Until the application links its new encoder, drop whatever its old one produces
+        // rather than letting it fail with not-linked.
+        let old_sink_pad = old_sink_pad.clone();
+        upstream_pad.add_probe(
+          gstreamer::PadProbeType::BUFFER | gstreamer::PadProbeType::BUFFER_LIST,
+          move |pad, _info| match pad.peer() {
+            Some(peer) if peer != old_sink_pad => gstreamer::PadProbeReturn::Remove,
+            _ => gstreamer::PadProbeReturn::Drop,
+          },
+        );
+        upstream_pad.unlink(&old_sink_pad)?;
+      }
+    }
+    JingleSession::remove_video_sink(pipeline, old_video_sink, rtpfunnel)?;
+
+    pipeline.add(video_sink)?;
+    JingleSession::link_video_sink(video_sink, rtpfunnel)?;
+    video_sink.sync_state_with_parent()?;
+    Ok(())
+  }
+
+  /// Unlink a video sink element from the rtpfunnel and remove it from the pipeline.
+  fn remove_video_sink(
+    pipeline: &gstreamer::Pipeline,
+    video_sink: &gstreamer::Element,
+    rtpfunnel: &gstreamer::Element,
+  ) -> Result<()> {
+    for src_pad in video_sink.src_pads() {
+      if let Some(funnel_pad) = src_pad.peer() {
+        src_pad.unlink(&funnel_pad)?;
+        rtpfunnel.release_request_pad(&funnel_pad);
+      }
+    }
+    video_sink.set_state(gstreamer::State::Null)?;
+    pipeline.remove(video_sink)?;
+    Ok(())
+  }
+
+  /// Create a payloader for a new video source in the current video codec, and the source-add
+  /// that signals it to the focus. Returns the source name.
+  pub(crate) fn add_video_source(
+    &mut self,
+    endpoint_id: &str,
+    video_type: VideoType,
+  ) -> Result<(String, Jingle)> {
+    let codec = self
+      .video_codec
+      .as_ref()
+      .context("audio-only conference has no video codec")?;
+    let rtpfunnel = self
+      .rtpfunnel
+      .as_ref()
+      .context("receive-only conference cannot send video")?;
+
+    let source_name = format!("{}-v{}", endpoint_id, self.next_video_source_index);
+    let ssrc: u32 = random();
+    let rtx_ssrc: u32 = random();
+    debug!(
+      "adding video source {}: SSRC {}, RTX SSRC {}",
+      source_name, ssrc, rtx_ssrc
+    );
+
+    let msid = format!(
+      "{}-video-{}-1 {}",
+      endpoint_id,
+      self.next_video_source_index,
+      Uuid::new_v4()
+    );
+
+    let sink_element = JingleSession::make_video_payloader(
+      &self.codec_registry,
+      codec,
+      ssrc,
+      self.dependency_descriptor,
+      self.video_transport_cc,
+    )?;
+    self.pipeline.add(&sink_element)?;
+    JingleSession::link_video_sink(&sink_element, rtpfunnel)?;
+    sink_element.sync_state_with_parent()?;
+    self.set_rtx_ssrc(ssrc, Some(rtx_ssrc));
+
+    let source_add = self.video_source_jingle(
+      Action::SourceAdd,
+      &source_name,
+      &video_type,
+      &msid,
+      ssrc,
+      rtx_ssrc,
+    )?;
+    self.next_video_source_index += 1;
+    self.extra_video_sources.insert(
+      source_name.clone(),
+      ExtraVideoSource {
+        video_type,
+        msid,
+        ssrc,
+        rtx_ssrc,
+        sink_element,
+      },
+    );
+    Ok((source_name, source_add))
+  }
+
+  /// Remove a video source added with [JingleSession::add_video_source], returning the
+  /// source-remove that signals it to the focus.
+  pub(crate) fn remove_video_source(&mut self, source_name: &str) -> Result<Jingle> {
+    let source = self
+      .extra_video_sources
+      .remove(source_name)
+      .with_context(|| format!("no such video source: {}", source_name))?;
+    debug!("removing video source {}", source_name);
+    if let Some(rtpfunnel) = &self.rtpfunnel {
+      JingleSession::remove_video_sink(&self.pipeline, &source.sink_element, rtpfunnel)?;
+    }
+    self.set_rtx_ssrc(source.ssrc, None);
+    self.video_source_jingle(
+      Action::SourceRemove,
+      source_name,
+      &source.video_type,
+      &source.msid,
+      source.ssrc,
+      source.rtx_ssrc,
+    )
+  }
+
+  pub(crate) fn video_source_sink_element(&self, source_name: &str) -> Option<gstreamer::Element> {
+    self
+      .extra_video_sources
+      .get(source_name)
+      .map(|source| source.sink_element.clone())
+  }
+
+  /// The names and video types of the video sources added with
+  /// [JingleSession::add_video_source].
+  pub(crate) fn extra_video_sources(&self) -> Vec<(String, VideoType)> {
+    self
+      .extra_video_sources
+      .iter()
+      .map(|(name, source)| (name.clone(), source.video_type.clone()))
+      .collect()
+  }
+
+  fn set_rtx_ssrc(&self, ssrc: u32, rtx_ssrc: Option<u32>) {
+    let mut video_rtx_ssrc_map = self.video_rtx_ssrc_map.lock().unwrap();
+    match rtx_ssrc {
+      Some(rtx_ssrc) => video_rtx_ssrc_map.insert(ssrc, rtx_ssrc),
+      None => video_rtx_ssrc_map.remove(&ssrc),
+    };
+    // If the pipeline has started, the RTX sender already exists and must be updated too.
+    if let Some(rtx_sender) = self.pipeline.by_name("rtprtxsend") {
+      rtx_sender.set_property("ssrc-map", rtx_ssrc_map_structure(&video_rtx_ssrc_map));
+    }
+  }
+
+  fn video_source_jingle(
+    &self,
+    action: Action,
+    source_name: &str,
+    video_type: &VideoType,
+    msid: &str,
+    ssrc: u32,
+    rtx_ssrc: u32,
+  ) -> Result<Jingle> {
+    let video_type = serde_json::to_value(video_type)?
+      .as_str()
+      .context("video type did not serialise as a string")?
+      .to_owned();
+
+    let mut description = RtpDescription::new("video".to_owned());
+    description.ssrcs = [ssrc, rtx_ssrc]
+      .into_iter()
+      .map(|ssrc| {
+        let mut source =
+          jingle_ssma::Source::new(ssrc, Some(source_name.to_owned()), Some(video_type.clone()));
+        source.parameters.push(Parameter {
+          name: "msid".to_owned(),
+          value: Some(msid.to_owned()),
+        });
+        source
+      })
+      .collect();
+    description.ssrc_groups = vec![jingle_ssma::Group {
+      semantics: Semantics::Fid,
+      sources: vec![
+        jingle_ssma::Source::new(ssrc, None, None),
+        jingle_ssma::Source::new(rtx_ssrc, None, None),
+      ],
+    }];
+
+    Ok(Jingle::new(action, self.sid.clone()).add_content(
+      Content::new(Creator::Responder, ContentId("video".to_owned())).with_description(description),
+    ))
+  }
+
   pub(crate) fn pause_all_sinks(&self) {
     if let Some(rtpbin) = self.pipeline.by_name("rtpbin") {
       rtpbin.foreach_src_pad(|_, pad| {
@@ -202,35 +633,78 @@ impl JingleSession {
     }
   }
 
+  /// Ask the sender of an SSRC for a keyframe. Returns false if the SSRC is not being received
+  /// yet.
+  pub(crate) fn request_keyframe(&self, ssrc: u32) -> Result<bool> {
+    let rtpbin = self.pipeline.by_name("rtpbin").context("no rtpbin")?;
+    let pad_name_prefix = format!("recv_rtp_src_0_{}_", ssrc);
+    match rtpbin
+      .src_pads()
+      .into_iter()
+      .find(|pad| pad.name().starts_with(&pad_name_prefix))
+    {
+      Some(pad) => {
+        if !pad.send_event(upstream_force_key_unit_event()) {
+          bail!("keyframe request for ssrc {} was not handled", ssrc);
+        }
+        Ok(true)
+      },
+      None => Ok(false),
+    }
+  }
+
   pub(crate) async fn pipeline_stopped(self) -> Result<()> {
     Ok(self.pipeline_state_null_rx.await?)
   }
 
   fn parse_rtp_description(
     description: &RtpDescription,
+    h264_profile: Option<&str>,
     remote_ssrc_map: &mut HashMap<u32, Source>,
   ) -> Result<Option<ParsedRtpDescription>> {
     let mut opus = None;
     let mut h264 = None;
+    let mut h264_candidates = vec![];
     let mut vp8 = None;
     let mut vp9 = None;
     let mut av1 = None;
     let mut audio_hdrext_ssrc_audio_level = None;
     let mut audio_hdrext_transport_cc = None;
     let mut video_hdrext_transport_cc = None;
+    let mut video_hdrext_dependency_descriptor = None;
 
     if description.media == "audio" {
       for pt in description.payload_types.iter() {
         // We don’t support any static codec, so name MUST be set.
         if pt.name.as_deref() == Some("opus") {
+          let enabled = |name: &str| {
+            pt.parameters
+              .iter()
+              .any(|param| param.name == name && param.value == "1")
+          };
           opus = Some(Codec {
-            name: CodecName::Opus,
-            pt: pt.id,
-            rtx_pt: None,
-            rtcp_fbs: pt.rtcp_fbs.clone(),
+            inband_fec: enabled("useinbandfec"),
+            dtx: enabled("usedtx"),
+            ..Codec::new(CodecName::Opus, pt)
           });
         }
       }
+      // RED is only used when it wraps nothing but Opus, e.g. "111/111".
+      if let Some(opus) = &mut opus {
+        for pt in description.payload_types.iter() {
+          if pt.name.as_deref() == Some("red")
+            && !pt.parameters.is_empty()
+            && pt.parameters.iter().all(|param| {
+              param
+                .value
+                .split('/')
+                .all(|block_pt| block_pt == opus.pt.to_string())
+            })
+          {
+            opus.red_pt = Some(pt.id);
+          }
+        }
+      }
       for hdrext in description.hdrexts.iter() {
         if hdrext.uri == RTP_HDREXT_SSRC_AUDIO_LEVEL {
           audio_hdrext_ssrc_audio_level = Some(hdrext.id);
@@ -246,41 +720,51 @@ impl JingleSession {
         if let Some(name) = &pt.name {
           match name.as_str() {
             "H264" => {
-              h264 = Some(Codec {
-                name: CodecName::H264,
-                pt: pt.id,
-                rtx_pt: None,
-                rtcp_fbs: pt.rtcp_fbs.clone(),
-              });
+              h264_candidates.push(Codec::new(CodecName::H264, pt));
             },
             "VP8" => {
-              vp8 = Some(Codec {
-                name: CodecName::Vp8,
-                pt: pt.id,
-                rtx_pt: None,
-                rtcp_fbs: pt.rtcp_fbs.clone(),
-              });
+              vp8 = Some(Codec::new(CodecName::Vp8, pt));
             },
             "VP9" => {
-              vp9 = Some(Codec {
-                name: CodecName::Vp9,
-                pt: pt.id,
-                rtx_pt: None,
-                rtcp_fbs: pt.rtcp_fbs.clone(),
-              });
+              vp9 = Some(Codec::new(CodecName::Vp9, pt));
             },
             "AV1" => {
-              av1 = Some(Codec {
-                name: CodecName::Av1,
-                pt: pt.id,
-                rtx_pt: None,
-                rtcp_fbs: pt.rtcp_fbs.clone(),
-              });
+              av1 = Some(Codec::new(CodecName::Av1, pt));
             },
             _ => (),
           }
         }
       }
+      // rtph264pay fragments large NAL units, which requires packetization-mode 1. Of those, take
+      // the first that accepts the profile our encoder produces, or the first if no profile is
+      // configured.
+      h264_candidates.retain(|codec| codec.parameter("packetization-mode") == Some("1"));
+      h264 = match h264_profile {
+        Some(profile) => {
+          let h264 = h264_candidates
+            .iter()
+            .find(|codec| {
+              codec
+                .h264_encoder_profiles()
+                .iter()
+                .any(|encoder_profile| *encoder_profile == profile)
+            })
+            .cloned();
+          if h264.is_none() && !h264_candidates.is_empty() {
+            warn!(
+              "not using H.264: none of the offered profiles accept {}: {}",
+              profile,
+              h264_candidates
+                .iter()
+                .map(|codec| codec.h264_profile().unwrap_or("unknown"))
+                .collect::<Vec<_>>()
+                .join(", ")
+            );
+          }
+          h264
+        },
+        None => h264_candidates.into_iter().next(),
+      };
       for pt in description.payload_types.iter() {
         if let Some(name) = &pt.name {
           if name == "rtx" {
@@ -316,6 +800,9 @@ impl JingleSession {
         if hdrext.uri == RTP_HDREXT_TRANSPORT_CC {
           video_hdrext_transport_cc = Some(hdrext.id);
         }
+        else if hdrext.uri == dependency_descriptor::RTP_HDREXT_DEPENDENCY_DESCRIPTOR {
+          video_hdrext_dependency_descriptor = Some(hdrext.id);
+        }
       }
     }
     else {
@@ -357,6 +844,7 @@ impl JingleSession {
       audio_hdrext_ssrc_audio_level,
       audio_hdrext_transport_cc,
       video_hdrext_transport_cc,
+      video_hdrext_dependency_descriptor,
     }))
   }
 
@@ -487,6 +975,449 @@ impl JingleSession {
     Ok((ice_agent, ice_stream_id, ice_component_id))
   }
 
+  fn make_video_payloader(
+    codec_registry: &CodecRegistry,
+    codec: &Codec,
+    ssrc: u32,
+    dependency_descriptor: Option<(ScalabilityMode, u8)>,
+    transport_cc: Option<u8>,
+  ) -> Result<gstreamer::Element> {
+    let element = codec_registry.make_payloader(
+      &codec.encoding_name().to_lowercase(),
+      codec.payloader_name(),
+    )?;
+    element.set_property("pt", codec.pt as u32);
+    if element.has_property("aggregate-mode", None) {
+      element.set_property_from_str("aggregate-mode", "zero-latency");
+    }
+    if element.has_property("picture-id-mode", None) {
+      element.set_property_from_str("picture-id-mode", "15-bit");
+    }
+    element.set_property("ssrc", ssrc);
+    if element.has_property("auto-header-extension", None) {
+      element.set_property("auto-header-extension", false);
+      element.connect("request-extension", false, move |values| {
+        let f = || {
+          let ext_id: u32 = values[1].get()?;
+          let ext_uri: String = values[2].get()?;
+          debug!(
+            "video payloader requested extension: {} {}",
+            ext_id, ext_uri
+          );
+          let hdrext =
+            RTPHeaderExtension::create_from_uri(&ext_uri).context("failed to create hdrext")?;
+          hdrext.set_id(ext_id);
+          Ok::<_, anyhow::Error>(hdrext)
+        };
+        match f() {
+          Ok(hdrext) => Some(hdrext.to_value()),
+          Err(e) => {
+            warn!("request-extension: {:?}", e);
+            None
+          },
+        }
+      });
+    }
+    else {
+      debug!("video payloader: no rtp header extension support");
+    }
+    if let Some(ext_id) = transport_cc {
+      JingleSession::add_transport_cc_extension(&element, ext_id)?;
+    }
+    if let Some((mode, ext_id)) = dependency_descriptor {
+      if codec.name == CodecName::Vp9 || codec.name == CodecName::Av1 {
+        JingleSession::add_dependency_descriptor_probes(&element, codec, mode, ext_id)?;
+      }
+    }
+    match codec.h264_encoder_profiles() {
+      profiles if codec.name == CodecName::H264 && !profiles.is_empty() => {
+        JingleSession::constrain_h264_profile(element, profiles)
+      },
+      _ => Ok(element),
+    }
+  }
+
+  /// Put a capsfilter in front of an H.264 payloader so that the encoder produces one of the
+  /// profiles accepted by the receiver. The level is left to the encoder.
+  fn constrain_h264_profile(
+    payloader: gstreamer::Element,
+    profiles: &[&str],
+  ) -> Result<gstreamer::Element> {
+    let bin = gstreamer::Bin::new();
+    let capsfilter = gstreamer::ElementFactory::make("capsfilter")
+      .property(
+        "caps",
+        gstreamer::Caps::builder("video/x-h264")
+          .field("profile", gstreamer::List::new(profiles.iter().copied()))
+          .build(),
+      )
+      .build()?;
+    bin.add(&capsfilter)?;
+    bin.add(&payloader)?;
+    capsfilter.link(&payloader)?;
+    bin.add_pad(
+      &GhostPad::builder_with_target(
+        &capsfilter
+          .static_pad("sink")
+          .context("capsfilter has no sink pad")?,
+      )?
+      .name("sink")
+      .build(),
+    )?;
+    bin.add_pad(
+      &GhostPad::builder_with_target(
+        &payloader
+          .static_pad("src")
+          .context("video payloader has no src pad")?,
+      )?
+      .name("src")
+      .build(),
+    )?;
+    Ok(bin.upcast())
+  }
+
+  /// Reserve a transport-wide sequence number in every packet the payloader produces. The
+  /// numbers are assigned by the RTP session, which matches them up with the bridge's
+  /// transport-cc feedback for the bandwidth estimator.
+  fn add_transport_cc_extension(payloader: &gstreamer::Element, ext_id: u8) -> Result<()> {
+    if !payloader.has_property("auto-header-extension", None) {
+      bail!("payloader has no rtp header extension support");
+    }
+    let hdrext = RTPHeaderExtension::create_from_uri(RTP_HDREXT_TRANSPORT_CC)
+      .context("failed to create transport-cc hdrext")?;
+    hdrext.set_id(ext_id as u32);
+    payloader.emit_by_name::<()>("add-extension", &[&hdrext]);
+    Ok(())
+  }
+
+  /// Write a dependency descriptor into every packet the payloader produces, describing the
+  /// layer of the frame that the packet came from.
+  fn add_dependency_descriptor_probes(
+    payloader: &gstreamer::Element,
+    codec: &Codec,
+    mode: ScalabilityMode,
+    ext_id: u8,
+  ) -> Result<()> {
+    // AV1 carries the temporal layer in the OBU extension header. vp9enc does not expose the
+    // layer of each frame it produces, so VP9 can only be sent without temporal layers.
+    if codec.name != CodecName::Av1 && mode.temporal_layers() > 1 {
+      bail!(
+        "scalability mode {} is not supported for {}: only L1T1 is",
+        mode,
+        codec.encoding_name()
+      );
+    }
+    let writer = Arc::new(Mutex::new(DependencyDescriptorWriter::new(mode, ext_id)));
+    let warned = AtomicBool::new(false);
+
+    let sink_writer = writer.clone();
+    payloader
+      .static_pad("sink")
+      .context("video payloader has no sink pad")?
+      .add_probe(gstreamer::PadProbeType::BUFFER, move |_pad, info| {
+        if let Some(gstreamer::PadProbeData::Buffer(buffer)) = &info.data {
+          let keyframe = !buffer.flags().contains(gstreamer::BufferFlags::DELTA_UNIT);
+          let temporal_id = if mode.temporal_layers() > 1 {
+            let temporal_id = buffer
+              .map_readable()
+              .ok()
+              .and_then(|map| dependency_descriptor::av1_temporal_id(&map));
+            if temporal_id.is_none() && !warned.swap(true, Ordering::Relaxed) {
+              warn!(
+                "AV1 frames have no OBU extension header, describing them as temporal layer 0: \
+                 configure the encoder for {}",
+                mode
+              );
+            }
+            temporal_id.unwrap_or_default()
+          }
+          else {
+            0
+          };
+          sink_writer
+            .lock()
+            .unwrap()
+            .start_frame(keyframe, temporal_id);
+        }
+        gstreamer::PadProbeReturn::Ok
+      });
+
+    payloader
+      .static_pad("src")
+      .context("video payloader has no src pad")?
+      .add_probe(
+        gstreamer::PadProbeType::BUFFER | gstreamer::PadProbeType::BUFFER_LIST,
+        move |_pad, info| {
+          let mut writer = writer.lock().unwrap();
+          let result = match &mut info.data {
+            Some(gstreamer::PadProbeData::Buffer(buffer)) => writer.write(buffer.make_mut()),
+            Some(gstreamer::PadProbeData::BufferList(list)) => {
+              let list = list.make_mut();
+              (0..list.len()).try_for_each(|i| match list.get_writable(i) {
+                Some(buffer) => writer.write(buffer),
+                None => Ok(()),
+              })
+            },
+            _ => Ok(()),
+          };
+          if let Err(e) = result {
+            warn!("failed to write dependency descriptor: {:?}", e);
+          }
+          gstreamer::PadProbeReturn::Ok
+        },
+      );
+    Ok(())
+  }
+
+  /// The element that the send pipeline links encoded video to. Without simulcast this is
+  /// simply the payloader. With simulcast it is a bin with a payloader per layer, exposing sink
+  /// pads `sink_0` (lowest resolution) to `sink_<N-1>` and a src pad per layer.
+  fn make_video_sink(
+    codec_registry: &CodecRegistry,
+    codec: &Codec,
+    ssrcs: &[u32],
+    dependency_descriptor: Option<(ScalabilityMode, u8)>,
+    transport_cc: Option<u8>,
+  ) -> Result<gstreamer::Element> {
+    if let [ssrc] = ssrcs {
+      return JingleSession::make_video_payloader(
+        codec_registry,
+        codec,
+        *ssrc,
+        dependency_descriptor,
+        transport_cc,
+      );
+    }
+    let bin = gstreamer::Bin::new();
+    for (layer, ssrc) in ssrcs.iter().enumerate() {
+      let payloader = JingleSession::make_video_payloader(
+        codec_registry,
+        codec,
+        *ssrc,
+        dependency_descriptor,
+        transport_cc,
+      )?;
+      bin.add(&payloader)?;
+      bin.add_pad(
+        &GhostPad::builder_with_target(
+          &payloader
+            .static_pad("sink")
+            .context("video payloader has no sink pad")?,
+        )?
+        .name(format!("sink_{}", layer))
+        .build(),
+      )?;
+      bin.add_pad(
+        &GhostPad::builder_with_target(
+          &payloader
+            .static_pad("src")
+            .context("video payloader has no src pad")?,
+        )?
+        .name(format!("src_{}", layer))
+        .build(),
+      )?;
+    }
+    Ok(bin.upcast())
+  }
+
+  /// Build the audio sink element around the Opus payloader. For stereo, a capsfilter in front
+  /// of the payloader makes the encoder produce two channels. With RED, each packet is sent with
+  /// the previous one as an RFC 2198 redundant block, so that a single lost packet can be
+  /// recovered.
+  fn make_audio_sink(
+    payloader: gstreamer::Element,
+    stereo: bool,
+    red_pt: Option<u8>,
+  ) -> Result<gstreamer::Element> {
+    if !stereo && red_pt.is_none() {
+      return Ok(payloader);
+    }
+    let bin = gstreamer::Bin::new();
+    bin.add(&payloader)?;
+    let sink = if stereo {
+      let capsfilter = gstreamer::ElementFactory::make("capsfilter")
+        .property(
+          "caps",
+          gstreamer::Caps::builder("audio/x-opus")
+            .field("channels", 2)
+            .build(),
+        )
+        .build()?;
+      bin.add(&capsfilter)?;
+      capsfilter.link(&payloader)?;
+      capsfilter
+    }
+    else {
+      payloader.clone()
+    };
+    let src = if let Some(red_pt) = red_pt {
+      let red_encoder = gstreamer::ElementFactory::make("rtpredenc")
+        .property("pt", red_pt as i32)
+        .property("distance", 1u32)
+        .property("allow-no-red-blocks", true)
+        .build()?;
+      bin.add(&red_encoder)?;
+      payloader.link(&red_encoder)?;
+      red_encoder
+    }
+    else {
+      payloader
+    };
+    bin.add_pad(
+      &GhostPad::builder_with_target(&sink.static_pad("sink").context("no audio sink pad")?)?
+        .name("sink")
+        .build(),
+    )?;
+    bin.add_pad(
+      &GhostPad::builder_with_target(&src.static_pad("src").context("no audio src pad")?)?
+        .name("src")
+        .build(),
+    )?;
+    Ok(bin.upcast())
+  }
+
+  fn link_video_sink(
+    video_sink: &gstreamer::Element,
+    rtpfunnel: &gstreamer::Element,
+  ) -> Result<()> {
+    for src_pad in video_sink.src_pads() {
+      debug!("linking video payloader {} -> rtpfunnel", src_pad.name());
+      let funnel_pad = rtpfunnel
+        .request_pad_simple("sink_%u")
+        .context("rtpfunnel has no sink pad")?;
+      src_pad.link(&funnel_pad)?;
+    }
+    Ok(())
+  }
+
+  /// Forward keyframe requests to the send pipeline. When the bridge or a receiver sends a PLI
+  /// or FIR for one of our video SSRCs, rtpbin pushes an upstream `GstForceKeyUnit` event
+  /// towards the rtpfunnel. We route it to the payloader sending that SSRC, so that it reaches
+  /// the encoder linked to [JingleSession::video_sink_element] (or to an extra video source's
+  /// sink element), at most once per [KEYFRAME_REQUEST_MIN_INTERVAL] for each SSRC.
+  fn forward_keyframe_requests(
+    rtpfunnel: &gstreamer::Element,
+    keyframe_requests: Arc<Mutex<KeyframeRequests>>,
+  ) -> Result<()> {
+    let rtpfunnel_weak = rtpfunnel.downgrade();
+    rtpfunnel
+      .static_pad("src")
+      .context("rtpfunnel has no src pad")?
+      .add_probe(
+        gstreamer::PadProbeType::EVENT_UPSTREAM,
+        move |_pad, info| {
+          let Some(gstreamer::PadProbeData::Event(event)) = &info.data
+          else {
+            return gstreamer::PadProbeReturn::Ok;
+          };
+          let Some(structure) = event.structure()
+          else {
+            return gstreamer::PadProbeReturn::Ok;
+          };
+          if event.type_() != gstreamer::EventType::CustomUpstream
+            || !structure.has_name("GstForceKeyUnit")
+          {
+            return gstreamer::PadProbeReturn::Ok;
+          }
+          let Some(rtpfunnel) = rtpfunnel_weak.upgrade()
+          else {
+            return gstreamer::PadProbeReturn::Drop;
+          };
+          // Without an SSRC, the request is for every video stream we send.
+          let requested_ssrc = structure.get::<u32>("ssrc").ok();
+          let now = Instant::now();
+          for pad in rtpfunnel.sink_pads() {
+            let Some(ssrc) = pad.current_caps().and_then(|caps| {
+              let structure = caps.structure(0)?;
+              (structure.get::<&str>("media").ok()? == "video")
+                .then(|| structure.get::<u32>("ssrc").ok())
+                .flatten()
+            })
+            else {
+              continue;
+            };
+            if requested_ssrc.map_or(false, |requested_ssrc| requested_ssrc != ssrc) {
+              continue;
+            }
+            if !keyframe_requests.lock().unwrap().allow(ssrc, now) {
+              debug!(
+                "dropping keyframe request for ssrc {}: one was forwarded less than {:?} ago",
+                ssrc, KEYFRAME_REQUEST_MIN_INTERVAL
+              );
+              continue;
+            }
+            debug!(
+              "forwarding keyframe request for ssrc {} to the encoder",
+              ssrc
+            );
+            if !pad.push_event(event.clone()) {
+              warn!(
+                "keyframe request for ssrc {} was not handled upstream",
+                ssrc
+              );
+            }
+          }
+          gstreamer::PadProbeReturn::Drop
+        },
+      );
+    Ok(())
+  }
+
+  /// Read the `ssrc-audio-level` header extension of received audio packets as they pass through
+  /// `identity`, and report changes to the conference. Only the extension is parsed; nothing is
+  /// decoded. Changes are reported one at a time, in the order they were received.
+  fn report_audio_levels(
+    conference: &JitsiConference,
+    identity: &gstreamer::Element,
+    ext_id: u8,
+    audio_pts: Vec<u8>,
+    last_reported: Arc<Mutex<HashMap<u32, (u8, Instant)>>>,
+  ) {
+    let (levels_tx, mut levels_rx) = mpsc::unbounded_channel();
+    let conference = conference.clone();
+    Handle::current().spawn(async move {
+      while let Some((ssrc, level)) = levels_rx.recv().await {
+        conference.audio_level_changed(ssrc, level).await;
+      }
+    });
+    identity.connect("handoff", false, move |values| {
+      let Ok(buffer) = values[1].get::<gstreamer::Buffer>()
+      else {
+        return None;
+      };
+      let Ok(rtp_buffer) = RTPBuffer::from_buffer_readable(&buffer)
+      else {
+        return None;
+      };
+      if !audio_pts.contains(&rtp_buffer.payload_type()) {
+        return None;
+      }
+      let Some(level) = rtp_buffer
+        .extension_onebyte_header(ext_id, 0)
+        .and_then(|data| data.first().copied())
+      else {
+        return None;
+      };
+      let ssrc = rtp_buffer.ssrc();
+      let now = Instant::now();
+      {
+        let mut last_reported = last_reported.lock().unwrap();
+        if let Some((last_level, last_time)) = last_reported.get(&ssrc) {
+          let vad_changed = (last_level ^ level) & 0x80 != 0;
+          if *last_level == level
+            || (!vad_changed && now.duration_since(*last_time) < AUDIO_LEVEL_MIN_INTERVAL)
+          {
+            return None;
+          }
+        }
+        last_reported.insert(ssrc, (level, now));
+      }
+      // The receiving task only stops once the pipeline is torn down.
+      let _ = levels_tx.send((ssrc, level));
+      None
+    });
+  }
+
   pub(crate) async fn initiate(conference: &JitsiConference, jingle: Jingle) -> Result<Self> {
     let initiator = jingle
       .initiator
@@ -496,19 +1427,29 @@ impl JingleSession {
 
     debug!("Received Jingle session-initiate from {}", initiator);
 
+    let audio_only = conference.config.audio_only;
+    let codec_registry = &conference.config.codec_registry;
+    codec_registry.apply_ranks();
+
     let mut ice_transport = None;
     let mut codecs = vec![];
     let mut audio_hdrext_ssrc_audio_level = None;
     let mut audio_hdrext_transport_cc = None;
     let mut video_hdrext_transport_cc = None;
+    let mut video_hdrext_dependency_descriptor = None;
 
     let mut remote_ssrc_map = HashMap::new();
 
     for content in &jingle.contents {
       if let Some(Description::Rtp(description)) = &content.description {
-        if let Some(description) =
-          JingleSession::parse_rtp_description(description, &mut remote_ssrc_map)?
-        {
+        if audio_only && description.media != "audio" {
+          debug!("audio-only: ignoring {} content", description.media);
+        }
+        else if let Some(description) = JingleSession::parse_rtp_description(
+          description,
+          conference.config.h264_profile.as_deref(),
+          &mut remote_ssrc_map,
+        )? {
           codecs.extend(description.codecs);
           audio_hdrext_ssrc_audio_level =
             audio_hdrext_ssrc_audio_level.or(description.audio_hdrext_ssrc_audio_level);
@@ -516,6 +1457,8 @@ impl JingleSession {
             audio_hdrext_transport_cc.or(description.audio_hdrext_transport_cc);
           video_hdrext_transport_cc =
             video_hdrext_transport_cc.or(description.video_hdrext_transport_cc);
+          video_hdrext_dependency_descriptor =
+            video_hdrext_dependency_descriptor.or(description.video_hdrext_dependency_descriptor);
         }
       }
 
@@ -531,6 +1474,106 @@ impl JingleSession {
 
     let ice_transport = ice_transport.context("missing ICE transport")?;
 
+    for codec in codecs.iter_mut() {
+      if codec.red_pt.is_some()
+        && (gstreamer::ElementFactory::find("rtpredenc").is_none()
+          || gstreamer::ElementFactory::find("rtpreddec").is_none())
+      {
+        warn!("not using RED: rtpredenc or rtpreddec element is not available");
+        codec.red_pt = None;
+      }
+    }
+
+    // The offered video codecs that we support, in our order of preference. We send the first.
+    let mut video_codecs: Vec<Codec> = vec![];
+    if !audio_only {
+      for name in &conference.config.video_codecs {
+        if let Some(codec) = codecs
+          .iter()
+          .find(|codec| codec.is_video() && codec.is_codec(name))
+        {
+          if !video_codecs.iter().any(|c| c.pt == codec.pt) {
+            video_codecs.push(codec.clone());
+          }
+        }
+      }
+      if conference.config.receive_only {
+        // We send nothing, so the configured codecs are only a preference for receiving.
+        if video_codecs.is_empty() {
+          debug!("none of the configured video codecs were offered, accepting all offered ones");
+          video_codecs = codecs
+            .iter()
+            .filter(|codec| codec.is_video())
+            .cloned()
+            .collect();
+        }
+      }
+      else {
+        match video_codecs.first() {
+          Some(codec) => info!("Sending video as {}", codec.encoding_name()),
+          None => bail!(
+            "none of the configured video codecs were offered: {}",
+            conference.config.video_codecs.join(", ")
+          ),
+        }
+      }
+    }
+
+    // The dependency descriptor is written with one-byte header extensions, so its ID must be
+    // below 15.
+    let dependency_descriptor = match (
+      conference.config.scalability_mode,
+      video_hdrext_dependency_descriptor,
+    ) {
+      (Some(mode), Some(id)) if (1..=14).contains(&id) => Some((mode, id as u8)),
+      (Some(mode), Some(id)) => {
+        warn!(
+          "not sending {}: dependency descriptor extension ID {} is too large",
+          mode, id
+        );
+        None
+      },
+      (Some(mode), None) if !audio_only => {
+        warn!(
+          "not sending {}: dependency descriptor extension was not offered",
+          mode
+        );
+        None
+      },
+      _ => None,
+    };
+
+    // Bandwidth estimation relies on transport-cc feedback, so transport-wide sequence numbers
+    // are only written (in one-byte header extensions) when it is enabled. It is done for
+    // video, along with any audio in the same session.
+    let video_transport_cc = match video_hdrext_transport_cc {
+      _ if !conference.config.bandwidth_estimation
+        || conference.config.receive_only
+        || audio_only =>
+      {
+        None
+      },
+      Some(id) if !(1..=14).contains(&id) => {
+        warn!(
+          "not estimating bandwidth: transport-cc extension ID {} is too large",
+          id
+        );
+        None
+      },
+      Some(_) if gstreamer::ElementFactory::find("rtpgccbwe").is_none() => {
+        warn!("not estimating bandwidth: rtpgccbwe element is not available");
+        None
+      },
+      Some(id) => Some(id as u8),
+      None => {
+        warn!("not estimating bandwidth: transport-cc extension was not offered");
+        None
+      },
+    };
+    let audio_transport_cc = audio_hdrext_transport_cc
+      .filter(|id| video_transport_cc.is_some() && (1..=14).contains(id))
+      .map(|id| id as u8);
+
     if let Some(remote_fingerprint) = &ice_transport.fingerprint {
       warn!(
         "Remote DTLS fingerprint (verification not implemented yet): {:?}",
@@ -549,13 +1592,21 @@ impl JingleSession {
     debug!("Local DTLS certificate:\n{}", dtls_cert_pem);
     debug!("Local DTLS fingerprint: {}", fingerprint_str);
 
+    let receive_only = conference.config.receive_only;
+
     let audio_ssrc: u32 = random();
-    let video_ssrc: u32 = random();
-    let video_rtx_ssrc: u32 = random();
+    let simulcast_layers = conference.config.simulcast_layers;
+    let video_ssrcs: Vec<u32> = (0..simulcast_layers).map(|_| random()).collect();
+    let video_rtx_ssrcs: Vec<u32> = (0..simulcast_layers).map(|_| random()).collect();
 
-    debug!("audio SSRC: {}", audio_ssrc);
-    debug!("video SSRC: {}", video_ssrc);
-    debug!("video RTX SSRC: {}", video_rtx_ssrc);
+    if receive_only {
+      debug!("receive-only: not creating any send streams");
+    }
+    else {
+      debug!("audio SSRC: {}", audio_ssrc);
+      debug!("video SSRCs: {:?}", video_ssrcs);
+      debug!("video RTX SSRCs: {:?}", video_rtx_ssrcs);
+    }
 
     let (ice_agent, ice_stream_id, ice_component_id) =
       JingleSession::setup_ice(conference, ice_transport).await?;
@@ -612,6 +1663,7 @@ impl JingleSession {
 
     {
       let codecs = codecs.clone();
+      let stereo = conference.config.stereo;
       rtpbin.connect("request-pt-map", false, move |values| {
         let f = || {
           debug!("rtpbin request-pt-map {:?}", values);
@@ -620,10 +1672,13 @@ impl JingleSession {
           for codec in codecs.iter() {
             if codec.is(pt) {
               if codec.is_audio() {
+                // The depayloader decodes in stereo if we asked for it.
                 caps = caps
                   .field("media", "audio")
                   .field("encoding-name", "OPUS")
-                  .field("clock-rate", 48000);
+                  .field("clock-rate", 48000)
+                  .field("encoding-params", "2")
+                  .field("sprop-stereo", if stereo { "1" } else { "0" });
                 if let Some(hdrext) = audio_hdrext_ssrc_audio_level {
                   caps = caps.field(&format!("extmap-{}", hdrext), RTP_HDREXT_SSRC_AUDIO_LEVEL);
                 }
@@ -638,12 +1693,24 @@ impl JingleSession {
                   .field("clock-rate", 90000)
                   .field("encoding-name", codec.encoding_name())
                   .field("rtcp-fb-nack-pli", true);
+                if codec.name == CodecName::H264 {
+                  for param in &codec.parameters {
+                    caps = caps.field(&param.name, param.value.as_str());
+                  }
+                }
                 if let Some(hdrext) = video_hdrext_transport_cc {
                   caps = caps.field(&format!("extmap-{}", hdrext), RTP_HDREXT_TRANSPORT_CC);
                 }
               }
               return Ok::<_, anyhow::Error>(Some(caps.build()));
             }
+            else if codec.red_pt == Some(pt) {
+              caps = caps
+                .field("media", "audio")
+                .field("clock-rate", 48000)
+                .field("encoding-name", "RED");
+              return Ok(Some(caps.build()));
+            }
             else if codec.is_rtx(pt) {
               caps = caps
                 .field("media", "video")
@@ -671,13 +1738,34 @@ impl JingleSession {
       });
     }
 
+    // Redundant audio is unwrapped after the jitterbuffer, which orders the RED packets, and
+    // before the packets are demuxed by payload type.
+    if let Some(red_pt) = codecs.iter().find_map(|codec| codec.red_pt) {
+      rtpbin.connect("request-fec-decoder", false, move |values| {
+        let f = || {
+          let session: u32 = values[1].get()?;
+          debug!("creating RED decoder for session {}", session);
+          Ok::<_, anyhow::Error>(
+            gstreamer::ElementFactory::make("rtpreddec")
+              .property("pt", red_pt as i32)
+              .build()?,
+          )
+        };
+        match f() {
+          Ok(element) => Some(element.to_value()),
+          Err(e) => {
+            warn!("request-fec-decoder: {:?}", e);
+            None
+          },
+        }
+      });
+    }
+
     let handle = Handle::current();
-    let jingle_session = conference.jingle_session.clone();
-    let buffer_size = conference.config.buffer_size;
+    let conference_ = conference.clone();
     rtpbin.connect("new-jitterbuffer", false, move |values| {
-      let handle = handle.clone();
-      let jingle_session = jingle_session.clone();
-      let f = move || {
+      let conference = &conference_;
+      let f = || {
         let rtpjitterbuffer: gstreamer::Element = values[1].get()?;
         let session: u32 = values[2].get()?;
         let ssrc: u32 = values[3].get()?;
@@ -686,9 +1774,10 @@ impl JingleSession {
           session, ssrc
         );
 
-        let source = handle.block_on(async move {
+        let source = handle.block_on(async {
           Ok::<_, anyhow::Error>(
-            jingle_session
+            conference
+              .jingle_session
               .lock()
               .await
               .as_ref()
@@ -700,12 +1789,12 @@ impl JingleSession {
           )
         })?;
         debug!("jitterbuffer is for remote source: {:?}", source);
-        if source.media_type == MediaType::Video && source.participant_id.is_some() {
-          debug!("enabling RTX for ssrc {}", ssrc);
-          rtpjitterbuffer.set_property("do-retransmission", true);
-          rtpjitterbuffer.set_property("drop-on-latency", true);
-          rtpjitterbuffer.set_property("latency", buffer_size);
-        }
+        // Retransmissions can only be requested from participants, not from the bridge.
+        handle.block_on(conference.add_jitterbuffer(
+          source.media_type,
+          source.participant_id.is_some(),
+          &rtpjitterbuffer,
+        ));
         Ok::<_, anyhow::Error>(())
       };
       if let Err(e) = f() {
@@ -714,47 +1803,133 @@ impl JingleSession {
       None
     });
 
-    let pts: Vec<(String, u32)> = codecs
-      .iter()
-      .filter(|codec| codec.is_video())
-      .flat_map(|codec| {
-        codec
-          .rtx_pt
-          .map(|rtx_pt| (codec.pt.to_string(), rtx_pt as u32))
-      })
-      .collect();
-    {
-      let pts = pts.clone();
-      rtpbin.connect("request-aux-sender", false, move |values| {
+    let video_rtx_ssrc_map = Arc::new(Mutex::new(
+      video_ssrcs
+        .iter()
+        .copied()
+        .zip(video_rtx_ssrcs.iter().copied())
+        .collect::<HashMap<_, _>>(),
+    ));
+
+    // RTX is only used for video.
+    if !audio_only {
+      let pts: Vec<(String, u32)> = codecs
+        .iter()
+        .filter(|codec| codec.is_video())
+        .flat_map(|codec| {
+          codec
+            .rtx_pt
+            .map(|rtx_pt| (codec.pt.to_string(), rtx_pt as u32))
+        })
+        .collect();
+      {
+        let pts = pts.clone();
+        let video_rtx_ssrc_map = video_rtx_ssrc_map.clone();
+        let handle = Handle::current();
+        let conference = conference.clone();
+        let start_bitrate = conference.config.start_bitrate;
+        rtpbin.connect("request-aux-sender", false, move |values| {
+          let f = || {
+            let session: u32 = values[1].get()?;
+            debug!("creating RTX sender for session {}", session);
+            let mut pt_map = gstreamer::Structure::builder("application/x-rtp-pt-map");
+            for (pt, rtx_pt) in pts.iter() {
+              pt_map = pt_map.field(pt, rtx_pt);
+            }
+            let bin = gstreamer::Bin::new();
+            let rtx_sender = gstreamer::ElementFactory::make("rtprtxsend")
+              .name("rtprtxsend")
+              .property("payload-type-map", pt_map.build())
+              .property(
+                "ssrc-map",
+                rtx_ssrc_map_structure(&video_rtx_ssrc_map.lock().unwrap()),
+              )
+              .build()?;
+            bin.add(&rtx_sender)?;
+            // The bandwidth estimator paces everything that is sent, including retransmissions,
+            // and is fed transport-cc feedback by the RTP session.
+            let src = if video_transport_cc.is_some() {
+              debug!("creating bandwidth estimator for session {}", session);
+              let bwe = gstreamer::ElementFactory::make("rtpgccbwe")
+                .property("estimated-bitrate", start_bitrate.saturating_mul(1000))
+                .build()?;
+              bin.add(&bwe)?;
+              rtx_sender.link(&bwe)?;
+              let handle = handle.clone();
+              let conference = conference.clone();
+              {
+                let handle = handle.clone();
+                let conference = conference.clone();
+                bwe.connect_notify(Some("estimated-bitrate"), move |bwe, _| {
+                  let bitrate: u32 = bwe.property("estimated-bitrate");
+                  let conference = conference.clone();
+                  handle.spawn(async move { conference.bandwidth_estimate_changed(bitrate).await });
+                });
+              }
+              // Report the starting estimate, so that the encoder starts at it rather than at
+              // whatever it was configured with.
+              let bitrate: u32 = bwe.property("estimated-bitrate");
+              handle.spawn(async move { conference.bandwidth_estimate_changed(bitrate).await });
+              bwe
+            }
+            else {
+              rtx_sender.clone()
+            };
+            bin.add_pad(
+              &gstreamer::GhostPad::builder_with_target(
+                &src.static_pad("src").context("aux sender has no src pad")?,
+              )?
+              .name(format!("src_{}", session))
+              .build(),
+            )?;
+            bin.add_pad(
+              &gstreamer::GhostPad::builder_with_target(
+                &rtx_sender
+                  .static_pad("sink")
+                  .context("rtprtxsend has no sink pad")?,
+              )?
+              .name(format!("sink_{}", session))
+              .build(),
+            )?;
+            Ok::<_, anyhow::Error>(Some(bin.to_value()))
+          };
+          match f() {
+            Ok(o) => o,
+            Err(e) => {
+              warn!("request-aux-sender: {:?}", e);
+              None
+            },
+          }
+        });
+      }
+
+      rtpbin.connect("request-aux-receiver", false, move |values| {
         let f = || {
           let session: u32 = values[1].get()?;
-          debug!("creating RTX sender for session {}", session);
+          debug!("creating RTX receiver for session {}", session);
           let mut pt_map = gstreamer::Structure::builder("application/x-rtp-pt-map");
-          let mut ssrc_map = gstreamer::Structure::builder("application/x-rtp-ssrc-map");
           for (pt, rtx_pt) in pts.iter() {
             pt_map = pt_map.field(pt, rtx_pt);
           }
-          ssrc_map = ssrc_map.field(&video_ssrc.to_string(), &(video_rtx_ssrc as u32));
           let bin = gstreamer::Bin::new();
-          let rtx_sender = gstreamer::ElementFactory::make("rtprtxsend")
+          let rtx_receiver = gstreamer::ElementFactory::make("rtprtxreceive")
             .property("payload-type-map", pt_map.build())
-            .property("ssrc-map", ssrc_map.build())
             .build()?;
-          bin.add(&rtx_sender)?;
+          bin.add(&rtx_receiver)?;
           bin.add_pad(
             &gstreamer::GhostPad::builder_with_target(
-              &rtx_sender
+              &rtx_receiver
                 .static_pad("src")
-                .context("rtprtxsend has no src pad")?,
+                .context("rtprtxreceive has no src pad")?,
             )?
             .name(format!("src_{}", session))
             .build(),
           )?;
           bin.add_pad(
             &gstreamer::GhostPad::builder_with_target(
-              &rtx_sender
+              &rtx_receiver
                 .static_pad("sink")
-                .context("rtprtxsend has no sink pad")?,
+                .context("rtprtxreceive has no sink pad")?,
             )?
             .name(format!("sink_{}", session))
             .build(),
@@ -764,61 +1939,20 @@ impl JingleSession {
         match f() {
           Ok(o) => o,
           Err(e) => {
-            warn!("request-aux-sender: {:?}", e);
+            warn!("request-aux-receiver: {:?}", e);
             None
           },
         }
       });
     }
 
-    rtpbin.connect("request-aux-receiver", false, move |values| {
-      let f = || {
-        let session: u32 = values[1].get()?;
-        debug!("creating RTX receiver for session {}", session);
-        let mut pt_map = gstreamer::Structure::builder("application/x-rtp-pt-map");
-        for (pt, rtx_pt) in pts.iter() {
-          pt_map = pt_map.field(pt, rtx_pt);
-        }
-        let bin = gstreamer::Bin::new();
-        let rtx_receiver = gstreamer::ElementFactory::make("rtprtxreceive")
-          .property("payload-type-map", pt_map.build())
-          .build()?;
-        bin.add(&rtx_receiver)?;
-        bin.add_pad(
-          &gstreamer::GhostPad::builder_with_target(
-            &rtx_receiver
-              .static_pad("src")
-              .context("rtprtxreceive has no src pad")?,
-          )?
-          .name(format!("src_{}", session))
-          .build(),
-        )?;
-        bin.add_pad(
-          &gstreamer::GhostPad::builder_with_target(
-            &rtx_receiver
-              .static_pad("sink")
-              .context("rtprtxreceive has no sink pad")?,
-          )?
-          .name(format!("sink_{}", session))
-          .build(),
-        )?;
-        Ok::<_, anyhow::Error>(Some(bin.to_value()))
-      };
-      match f() {
-        Ok(o) => o,
-        Err(e) => {
-          warn!("request-aux-receiver: {:?}", e);
-          None
-        },
-      }
-    });
-
     {
       let handle = Handle::current();
       let conference = conference.clone();
       let pipeline = pipeline.clone();
       let rtpbin_ = rtpbin.clone();
       let codecs = codecs.clone();
+      let codec_registry = codec_registry.clone();
       rtpbin.connect("pad-added", false, move |values| {
         let rtpbin = &rtpbin_;
         let f = || {
@@ -953,115 +2087,199 @@ impl JingleSession {
               .link(&pre_decoder_queue)
               .context("failed to link depayloader to queue")?;
 
-            let decoder = match source.media_type {
-              MediaType::Audio => {
-                let codec = codecs
-                  .iter()
-                  .filter(|codec| codec.is_audio())
-                  .find(|codec| codec.is(pt));
-                if let Some(codec) = codec {
-                  gstreamer::ElementFactory::make(codec.decoder_name()).build()?
-                  // TODO: fec
-                }
-                else {
-                  bail!("received audio with unsupported PT {}", pt);
-                }
-              },
-              MediaType::Video => {
-                let codec = codecs
-                  .iter()
-                  .filter(|codec| codec.is_video())
-                  .find(|codec| codec.is(pt));
-                if let Some(codec) = codec {
-                  let decoder = gstreamer::ElementFactory::make(codec.decoder_name()).build()?;
-                  decoder.set_property("automatic-request-sync-points", true);
-                  decoder.set_property_from_str(
-                    "automatic-request-sync-point-flags",
-                    "GST_VIDEO_DECODER_REQUEST_SYNC_POINT_CORRUPT_OUTPUT",
-                  );
-                  decoder
-                }
-                else {
-                  bail!("received video with unsupported PT {}", pt);
-                }
-              },
+            let encoded = match source.media_type {
+              MediaType::Audio => conference.config.recv_audio_encoded,
+              MediaType::Video => conference.config.recv_video_encoded,
             };
 
-            pipeline
-              .add(&decoder)
-              .context("failed to add decoder to pipeline")?;
-            decoder.sync_state_with_parent()?;
-            pre_decoder_queue
-              .link(&decoder)
-              .context("failed to link queue to decoder")?;
-
-            let post_decoder_queue = gstreamer::ElementFactory::make("queue").build()?;
-            pipeline
-              .add(&post_decoder_queue)
-              .context("failed to add queue to pipeline")?;
-            post_decoder_queue.sync_state_with_parent()?;
-            decoder
-              .link(&post_decoder_queue)
-              .context("failed to link decoder to queue")?;
+            let src_pad = if encoded {
+              let codec = codecs
+                .iter()
+                .find(|codec| codec.is(pt))
+                .with_context(|| format!("received unsupported PT {}", pt))?;
+              match codec
+                .parser_name()
+                .filter(|name| gstreamer::ElementFactory::find(name).is_some())
+              {
+                Some(parser_name) => {
+                  let parser = gstreamer::ElementFactory::make(parser_name).build()?;
+                  pipeline
+                    .add(&parser)
+                    .context("failed to add parser to pipeline")?;
+                  parser.sync_state_with_parent()?;
+                  pre_decoder_queue
+                    .link(&parser)
+                    .context("failed to link queue to parser")?;
+                  parser.static_pad("src").context("parser has no src pad")?
+                },
+                None => pre_decoder_queue
+                  .static_pad("src")
+                  .context("queue has no src pad")?,
+              }
+            }
+            else {
+              let decoder = match source.media_type {
+                MediaType::Audio => {
+                  let codec = codecs
+                    .iter()
+                    .filter(|codec| codec.is_audio())
+                    .find(|codec| codec.is(pt));
+                  if let Some(codec) = codec {
+                    let decoder = codec_registry.make_decoder("opus", codec.decoder_name())?;
+                    // The jitterbuffer signals lost packets, which opusdec conceals, recovering
+                    // them from the FEC data in the next packet when there is any.
+                    if decoder.has_property("use-inband-fec", None) {
+                      decoder.set_property("plc", true);
+                      decoder.set_property("use-inband-fec", codec.inband_fec);
+                    }
+                    decoder
+                  }
+                  else {
+                    bail!("received audio with unsupported PT {}", pt);
+                  }
+                },
+                MediaType::Video => {
+                  let codec = codecs
+                    .iter()
+                    .filter(|codec| codec.is_video())
+                    .find(|codec| codec.is(pt));
+                  if let Some(codec) = codec {
+                    let decoder = codec_registry
+                      .make_decoder(&codec.encoding_name().to_lowercase(), codec.decoder_name())?;
+                    // Only GstVideoDecoder subclasses can request sync points.
+                    if decoder.has_property("automatic-request-sync-points", None) {
+                      decoder.set_property("automatic-request-sync-points", true);
+                      decoder.set_property_from_str(
+                        "automatic-request-sync-point-flags",
+                        "GST_VIDEO_DECODER_REQUEST_SYNC_POINT_CORRUPT_OUTPUT",
+                      );
+                    }
+                    decoder
+                  }
+                  else {
+                    bail!("received video with unsupported PT {}", pt);
+                  }
+                },
+              };
 
-            let src_pad = match source.media_type {
-              MediaType::Audio => post_decoder_queue
-                .static_pad("src")
-                .context("queue has no src pad")?,
-              MediaType::Video => {
-                let videoscale = gstreamer::ElementFactory::make("videoscale").build()?;
-                pipeline
-                  .add(&videoscale)
-                  .context("failed to add videoscale to pipeline")?;
-                videoscale.sync_state_with_parent()?;
-                post_decoder_queue
-                  .link(&videoscale)
-                  .context("failed to link queue to videoscale")?;
-
-                let capsfilter = gstreamer::ElementFactory::make("capsfilter").build()?;
-                capsfilter.set_property_from_str(
-                  "caps",
-                  &format!(
-                    "video/x-raw, width={}, height={}",
-                    conference.config.recv_video_scale_width,
-                    conference.config.recv_video_scale_height
-                  ),
-                );
-                pipeline
-                  .add(&capsfilter)
-                  .context("failed to add capsfilter to pipeline")?;
-                capsfilter.sync_state_with_parent()?;
-                videoscale
-                  .link(&capsfilter)
-                  .context("failed to link videoscale to capsfilter")?;
-
-                let videoconvert = gstreamer::ElementFactory::make("videoconvert").build()?;
-                pipeline
-                  .add(&videoconvert)
-                  .context("failed to add videoconvert to pipeline")?;
-                videoconvert.sync_state_with_parent()?;
-                capsfilter
-                  .link(&videoconvert)
-                  .context("failed to link capsfilter to videoconvert")?;
-
-                let post_videoconvert_queue = gstreamer::ElementFactory::make("queue").build()?;
-                pipeline
-                  .add(&post_videoconvert_queue)
-                  .context("failed to add queue to pipeline")?;
-                post_videoconvert_queue.sync_state_with_parent()?;
-                videoconvert
-                  .link(&post_videoconvert_queue)
+              pipeline
+                .add(&decoder)
+                .context("failed to add decoder to pipeline")?;
+              decoder.sync_state_with_parent()?;
+              pre_decoder_queue
+                .link(&decoder)
+                .context("failed to link queue to decoder")?;
+
+              let post_decoder_queue = gstreamer::ElementFactory::make("queue").build()?;
+              pipeline
+                .add(&post_decoder_queue)
+                .context("failed to add queue to pipeline")?;
+              post_decoder_queue.sync_state_with_parent()?;
+              decoder
+                .link(&post_decoder_queue)
+                .context("failed to link decoder to queue")?;
+
+              match source.media_type {
+                MediaType::Audio => match conference.config.audio_recv.caps() {
+                  Some(caps) => {
+                    let audioconvert = gstreamer::ElementFactory::make("audioconvert").build()?;
+                    pipeline
+                      .add(&audioconvert)
+                      .context("failed to add audioconvert to pipeline")?;
+                    audioconvert.sync_state_with_parent()?;
+                    post_decoder_queue
+                      .link(&audioconvert)
+                      .context("failed to link queue to audioconvert")?;
+
+                    let audioresample = gstreamer::ElementFactory::make("audioresample").build()?;
+                    pipeline
+                      .add(&audioresample)
+                      .context("failed to add audioresample to pipeline")?;
+                    audioresample.sync_state_with_parent()?;
+                    audioconvert
+                      .link(&audioresample)
+                      .context("failed to link audioconvert to audioresample")?;
+
+                    let capsfilter = gstreamer::ElementFactory::make("capsfilter")
+                      .property("caps", caps)
+                      .build()?;
+                    pipeline
+                      .add(&capsfilter)
+                      .context("failed to add capsfilter to pipeline")?;
+                    capsfilter.sync_state_with_parent()?;
+                    audioresample
+                      .link(&capsfilter)
+                      .context("failed to link audioresample to capsfilter")?;
+
+                    capsfilter
+                      .static_pad("src")
+                      .context("capsfilter has no src pad")?
+                  },
+                  None => post_decoder_queue
+                    .static_pad("src")
+                    .context("queue has no src pad")?,
+                },
+                MediaType::Video => {
+                  let videoscale = gstreamer::ElementFactory::make("videoscale").build()?;
+                  pipeline
+                    .add(&videoscale)
+                    .context("failed to add videoscale to pipeline")?;
+                  videoscale.sync_state_with_parent()?;
+                  post_decoder_queue
+                    .link(&videoscale)
+                    .context("failed to link queue to videoscale")?;
+
+                  let capsfilter = gstreamer::ElementFactory::make("capsfilter").build()?;
+                  handle.block_on(conference.add_video_scaler(
+                    &participant_id,
+                    videoscale.clone(),
+                    capsfilter.clone(),
+                  ));
+                  pipeline
+                    .add(&capsfilter)
+                    .context("failed to add capsfilter to pipeline")?;
+                  capsfilter.sync_state_with_parent()?;
+                  videoscale
+                    .link(&capsfilter)
+                    .context("failed to link videoscale to capsfilter")?;
+
+                  let videoconvert = gstreamer::ElementFactory::make("videoconvert").build()?;
+                  pipeline
+                    .add(&videoconvert)
+                    .context("failed to add videoconvert to pipeline")?;
+                  videoconvert.sync_state_with_parent()?;
+                  capsfilter
+                    .link(&videoconvert)
+                    .context("failed to link capsfilter to videoconvert")?;
+
+                  let post_videoconvert_queue = gstreamer::ElementFactory::make("queue").build()?;
+                  pipeline
+                    .add(&post_videoconvert_queue)
+                    .context("failed to add queue to pipeline")?;
+                  post_videoconvert_queue.sync_state_with_parent()?;
+                  match &conference.config.recv_video_format {
+                    Some(format) => videoconvert.link_filtered(
+                      &post_videoconvert_queue,
+                      &gstreamer::Caps::builder("video/x-raw")
+                        .field("format", format.as_str())
+                        .build(),
+                    ),
+                    None => videoconvert.link(&post_videoconvert_queue),
+                  }
                   .context("failed to link videoconvert to queue")?;
 
-                post_videoconvert_queue
-                  .static_pad("src")
-                  .context("queue has no src pad")?
-              },
+                  post_videoconvert_queue
+                    .static_pad("src")
+                    .context("queue has no src pad")?
+                },
+              }
             };
 
             if let Some(sink_element) = maybe_sink_element {
               let sink_pad = sink_element
                 .request_pad_simple("sink_%u")
+                // Muxers name their request pads by media type, e.g. webmmux's video_%u.
+                .or_else(|| sink_element.compatible_pad(&src_pad, None))
                 .context("no suitable sink pad provided by sink element in recv pipeline")?;
               let ghost_pad = GhostPad::builder_with_target(&sink_pad)?
                 .name(format!(
@@ -1106,6 +2324,14 @@ impl JingleSession {
               }
             }
 
+            // Whatever is now linked can only start decoding (or writing) from a keyframe, which
+            // might otherwise be a long way off.
+            if source.media_type == MediaType::Video
+              && !pad.send_event(upstream_force_key_unit_event())
+            {
+              debug!("keyframe request for ssrc {} was not handled", ssrc);
+            }
+
             pipeline.debug_to_dot_file(
               gstreamer::DebugGraphDetails::ALL,
               &format!("ssrc-added-{}", ssrc),
@@ -1124,103 +2350,105 @@ impl JingleSession {
       });
     }
 
-    let opus = codecs.iter().find(|codec| codec.name == CodecName::Opus);
-    let audio_sink_element = if let Some(opus) = opus {
-      let audio_sink_element = gstreamer::ElementFactory::make(opus.payloader_name()).build()?;
-      audio_sink_element.set_property("pt", opus.pt as u32);
-      audio_sink_element
+    let keyframe_requests = Arc::new(Mutex::new(KeyframeRequests::default()));
+    let (audio_sink_element, video_sink_element, rtpfunnel) = if receive_only {
+      (None, None, None)
     }
     else {
-      bail!("no opus payload type in jingle session-initiate");
-    };
-    audio_sink_element.set_property("min-ptime", 10i64 * 1000 * 1000);
-    audio_sink_element.set_property("ssrc", audio_ssrc);
-    if audio_sink_element.has_property("auto-header-extension", None) {
-      audio_sink_element.set_property("auto-header-extension", false);
-      audio_sink_element.connect("request-extension", false, move |values| {
-        let f = || {
-          let ext_id: u32 = values[1].get()?;
-          let ext_uri: String = values[2].get()?;
-          debug!(
-            "audio payloader requested extension: {} {}",
-            ext_id, ext_uri
-          );
-          let hdrext =
-            RTPHeaderExtension::create_from_uri(&ext_uri).context("failed to create hdrext")?;
-          hdrext.set_id(ext_id);
-          Ok::<_, anyhow::Error>(hdrext)
-        };
-        match f() {
-          Ok(hdrext) => Some(hdrext.to_value()),
-          Err(e) => {
-            warn!("request-extension: {:?}", e);
-            None
-          },
-        }
-      });
-    }
-    else {
-      debug!("audio payloader: no rtp header extension support");
-    }
-    pipeline.add(&audio_sink_element)?;
-
-    let codec_name = conference.config.video_codec.as_str();
-    let codec = codecs.iter().find(|codec| codec.is_codec(codec_name));
-    let video_sink_element = if let Some(codec) = codec {
-      let element = gstreamer::ElementFactory::make(codec.payloader_name()).build()?;
-      element.set_property("pt", codec.pt as u32);
-      if codec.name == CodecName::H264 {
-        element.set_property_from_str("aggregate-mode", "zero-latency");
+      let opus = codecs.iter().find(|codec| codec.name == CodecName::Opus);
+      let audio_sink_element = if let Some(opus) = opus {
+        let audio_sink_element = codec_registry.make_payloader("opus", opus.payloader_name())?;
+        audio_sink_element.set_property("pt", opus.pt as u32);
+        audio_sink_element
       }
-      else if codec.name == CodecName::Vp8 || codec.name == CodecName::Vp9 {
-        element.set_property_from_str("picture-id-mode", "15-bit");
+      else {
+        bail!("no opus payload type in jingle session-initiate");
+      };
+      // Keep packets to the signalled duration. The encoder's frame size must still match it.
+      let (min_ptime, max_ptime) = match conference.config.audio_send.ptime {
+        Some(ptime) => (ptime as i64 * 1000 * 1000, ptime as i64 * 1000 * 1000),
+        None => (10i64 * 1000 * 1000, -1),
+      };
+      audio_sink_element.set_property("min-ptime", min_ptime);
+      audio_sink_element.set_property("max-ptime", max_ptime);
+      audio_sink_element.set_property("ssrc", audio_ssrc);
+      if audio_sink_element.has_property("auto-header-extension", None) {
+        audio_sink_element.set_property("auto-header-extension", false);
+        audio_sink_element.connect("request-extension", false, move |values| {
+          let f = || {
+            let ext_id: u32 = values[1].get()?;
+            let ext_uri: String = values[2].get()?;
+            debug!(
+              "audio payloader requested extension: {} {}",
+              ext_id, ext_uri
+            );
+            let hdrext =
+              RTPHeaderExtension::create_from_uri(&ext_uri).context("failed to create hdrext")?;
+            hdrext.set_id(ext_id);
+            Ok::<_, anyhow::Error>(hdrext)
+          };
+          match f() {
+            Ok(hdrext) => Some(hdrext.to_value()),
+            Err(e) => {
+              warn!("request-extension: {:?}", e);
+              None
+            },
+          }
+        });
       }
-      element
-    }
-    else {
-      bail!("unsupported video codec: {}", codec_name);
-    };
-    video_sink_element.set_property("ssrc", video_ssrc);
-    if video_sink_element.has_property("auto-header-extension", None) {
-      video_sink_element.set_property("auto-header-extension", false);
-      video_sink_element.connect("request-extension", false, move |values| {
-        let f = || {
-          let ext_id: u32 = values[1].get()?;
-          let ext_uri: String = values[2].get()?;
-          debug!(
-            "video payloader requested extension: {} {}",
-            ext_id, ext_uri
-          );
-          let hdrext =
-            RTPHeaderExtension::create_from_uri(&ext_uri).context("failed to create hdrext")?;
-          hdrext.set_id(ext_id);
-          Ok::<_, anyhow::Error>(hdrext)
-        };
-        match f() {
-          Ok(hdrext) => Some(hdrext.to_value()),
-          Err(e) => {
-            warn!("request-extension: {:?}", e);
-            None
-          },
-        }
-      });
-    }
-    else {
-      debug!("video payloader: no rtp header extension support");
-    }
-    pipeline.add(&video_sink_element)?;
+      else {
+        debug!("audio payloader: no rtp header extension support");
+      }
+      if let Some(ext_id) = audio_transport_cc {
+        JingleSession::add_transport_cc_extension(&audio_sink_element, ext_id)?;
+      }
+      // With DTX, the encoder produces tiny packets during silence, which needn't be sent.
+      if opus.map_or(false, |opus| opus.dtx) && audio_sink_element.has_property("dtx", None) {
+        audio_sink_element.set_property("dtx", true);
+      }
+      let audio_sink_element = JingleSession::make_audio_sink(
+        audio_sink_element,
+        conference.config.audio_send.stereo,
+        opus.and_then(|opus| opus.red_pt),
+      )?;
+      pipeline.add(&audio_sink_element)?;
+
+      let video_sink_element = if audio_only {
+        None
+      }
+      else {
+        let codec = video_codecs.first().context("no video codec")?;
+        let video_sink_element = JingleSession::make_video_sink(
+          codec_registry,
+          codec,
+          &video_ssrcs,
+          dependency_descriptor,
+          video_transport_cc,
+        )?;
+        pipeline.add(&video_sink_element)?;
+        Some(video_sink_element)
+      };
 
-    let rtpfunnel = gstreamer::ElementFactory::make("rtpfunnel").build()?;
-    pipeline.add(&rtpfunnel)?;
+      let rtpfunnel = gstreamer::ElementFactory::make("rtpfunnel").build()?;
+      pipeline.add(&rtpfunnel)?;
 
-    debug!("linking video payloader -> rtpfunnel");
-    video_sink_element.link(&rtpfunnel)?;
+      if let Some(video_sink_element) = &video_sink_element {
+        JingleSession::link_video_sink(video_sink_element, &rtpfunnel)?;
+      }
+
+      debug!("linking audio payloader -> rtpfunnel");
+      audio_sink_element.link(&rtpfunnel)?;
 
-    debug!("linking audio payloader -> rtpfunnel");
-    audio_sink_element.link(&rtpfunnel)?;
+      debug!("linking rtpfunnel -> rtpbin");
+      rtpfunnel.link_pads(None, &rtpbin, Some("send_rtp_sink_0"))?;
+      JingleSession::forward_keyframe_requests(&rtpfunnel, keyframe_requests.clone())?;
 
-    debug!("linking rtpfunnel -> rtpbin");
-    rtpfunnel.link_pads(None, &rtpbin, Some("send_rtp_sink_0"))?;
+      (
+        Some(audio_sink_element),
+        video_sink_element,
+        Some(rtpfunnel),
+      )
+    };
 
     let rtp_recv_identity = gstreamer::ElementFactory::make("identity").build()?;
     pipeline.add(&rtp_recv_identity)?;
@@ -1231,6 +2459,47 @@ impl JingleSession {
     let rtcp_send_identity = gstreamer::ElementFactory::make("identity").build()?;
     pipeline.add(&rtcp_send_identity)?;
 
+    for (identity, direction, rtcp) in [
+      (&rtp_recv_identity, PacketDirection::Recv, false),
+      (&rtp_send_identity, PacketDirection::Send, false),
+      (&rtcp_recv_identity, PacketDirection::Recv, true),
+      (&rtcp_send_identity, PacketDirection::Send, true),
+    ] {
+      let packet_handlers = conference.packet_handlers.clone();
+      identity.connect("handoff", false, move |values| {
+        let handler = if rtcp {
+          &packet_handlers.rtcp
+        }
+        else {
+          &packet_handlers.rtp
+        };
+        if let Some(handler) = handler.read().unwrap().as_ref() {
+          match values[1].get::<gstreamer::Buffer>() {
+            Ok(buffer) => handler(direction, &buffer),
+            Err(e) => warn!("packet handoff: {:?}", e),
+          }
+        }
+        None
+      });
+    }
+
+    let audio_levels_reported = Arc::new(Mutex::new(HashMap::new()));
+    if let Some(ext_id) = audio_hdrext_ssrc_audio_level {
+      let audio_pts = codecs
+        .iter()
+        .filter(|codec| codec.name == CodecName::Opus)
+        .flat_map(|codec| [Some(codec.pt), codec.red_pt])
+        .flatten()
+        .collect();
+      JingleSession::report_audio_levels(
+        conference,
+        &rtp_recv_identity,
+        ext_id as u8,
+        audio_pts,
+        audio_levels_reported.clone(),
+      );
+    }
+
     #[cfg(feature = "log-rtp")]
     {
       if conference.config.log_rtp {
@@ -1299,8 +2568,12 @@ impl JingleSession {
     rtcp_recv_identity.link_pads(None, &rtpbin, Some("recv_rtcp_sink_0"))?;
 
     debug!("linking rtpbin -> dtlssrtpenc");
-    rtpbin.link_pads(Some("send_rtp_src_0"), &rtp_send_identity, None)?;
-    rtp_send_identity.link_pads(None, &dtlssrtpenc, Some("rtp_sink_0"))?;
+    // rtpbin only has a send RTP pad if something is linked to its send sink; RTCP is always
+    // sent, so that the bridge gets receiver reports.
+    if !receive_only {
+      rtpbin.link_pads(Some("send_rtp_src_0"), &rtp_send_identity, None)?;
+      rtp_send_identity.link_pads(None, &dtlssrtpenc, Some("rtp_sink_0"))?;
+    }
     rtpbin.link_pads(Some("send_rtcp_src_0"), &rtcp_send_identity, None)?;
     rtcp_send_identity.link_pads(None, &dtlssrtpenc, Some("rtcp_sink_0"))?;
 
@@ -1355,7 +2628,13 @@ impl JingleSession {
       )
       .with_responder(Jid::Full(conference.jid.clone()));
 
-    for initiate_content in &jingle.contents {
+    let accepted_contents: Vec<_> = jingle
+      .contents
+      .iter()
+      .filter(|content| !audio_only || content.name.0 == "audio")
+      .collect();
+
+    for initiate_content in &accepted_contents {
       let mut description = RtpDescription::new(initiate_content.name.0.clone());
 
       description.payload_types = if initiate_content.name.0 == "audio" {
@@ -1363,7 +2642,40 @@ impl JingleSession {
         if let Some(codec) = codec {
           let mut pt = PayloadType::new(codec.pt, "opus".to_owned(), 48000, 2);
           pt.rtcp_fbs = codec.rtcp_fbs.clone();
-          vec![pt]
+          let audio_send = &conference.config.audio_send;
+          let parameters = [
+            ("stereo", conference.config.stereo.then(|| "1".to_owned())),
+            ("sprop-stereo", audio_send.stereo.then(|| "1".to_owned())),
+            ("useinbandfec", codec.inband_fec.then(|| "1".to_owned())),
+            ("usedtx", codec.dtx.then(|| "1".to_owned())),
+            (
+              "maxaveragebitrate",
+              conference
+                .config
+                .audio_recv
+                .max_average_bitrate
+                .map(|bitrate| bitrate.to_string()),
+            ),
+          ];
+          for (name, value) in parameters {
+            if let Some(value) = value {
+              pt.parameters.push(jingle_rtp::Parameter {
+                name: name.to_owned(),
+                value,
+              });
+            }
+          }
+          pt.ptime = audio_send.ptime;
+          let mut pts = vec![pt];
+          if let Some(red_pt) = codec.red_pt {
+            let mut red_pt = PayloadType::new(red_pt, "red".to_owned(), 48000, 2);
+            red_pt.parameters = vec![jingle_rtp::Parameter {
+              name: String::new(),
+              value: format!("{}/{}", codec.pt, codec.pt),
+            }];
+            pts.push(red_pt);
+          }
+          pts
         }
         else {
           bail!("no opus payload type in jingle session-initiate");
@@ -1371,11 +2683,12 @@ impl JingleSession {
       }
       else {
         let mut pts = vec![];
-        let codec_name = conference.config.video_codec.as_str();
-        let codec = codecs.iter().find(|codec| codec.is_codec(codec_name));
-        if let Some(codec) = codec {
+        for codec in &video_codecs {
           let mut pt = PayloadType::new(codec.pt, codec.encoding_name().to_owned(), 90000, 1);
           pt.rtcp_fbs = codec.rtcp_fbs.clone();
+          if codec.name == CodecName::H264 {
+            pt.parameters = codec.parameters.clone();
+          }
           pts.push(pt);
           if let Some(rtx_pt) = codec.rtx_pt {
             let mut rtx_pt = PayloadType::new(rtx_pt, "rtx".to_owned(), 90000, 1);
@@ -1392,60 +2705,78 @@ impl JingleSession {
             pts.push(rtx_pt);
           }
         }
-        else {
-          bail!("unsupported video codec: {}", codec_name);
-        }
         pts
       };
 
       description.rtcp_mux = Some(RtcpMux);
 
-      let endpoint_id = conference.endpoint_id()?;
+      if !receive_only {
+        let endpoint_id = conference.endpoint_id()?;
 
-      let mslabel = format!("{}-{}-0-1", endpoint_id, initiate_content.name.0);
-      let label = Uuid::new_v4().to_string();
+        let mslabel = format!("{}-{}-0-1", endpoint_id, initiate_content.name.0);
+        let label = Uuid::new_v4().to_string();
 
-      description.ssrc = Some(if initiate_content.name.0 == "audio" {
-        audio_ssrc.to_string()
-      }
-      else {
-        video_ssrc.to_string()
-      });
+        description.ssrc = Some(if initiate_content.name.0 == "audio" {
+          audio_ssrc.to_string()
+        }
+        else {
+          video_ssrcs[0].to_string()
+        });
 
-      description.ssrcs = if initiate_content.name.0 == "audio" {
-        vec![jingle_ssma::Source::new(
-          audio_ssrc,
-          Some(format!("{endpoint_id}-a0")),
-          None,
-        )]
-      }
-      else {
-        let source_name = format!("{endpoint_id}-v0");
-        vec![
-          jingle_ssma::Source::new(video_ssrc, Some(source_name.clone()), Some("camera".into())),
-          jingle_ssma::Source::new(video_rtx_ssrc, Some(source_name), Some("camera".into())),
-        ]
-      };
+        description.ssrcs = if initiate_content.name.0 == "audio" {
+          vec![jingle_ssma::Source::new(
+            audio_ssrc,
+            Some(format!("{endpoint_id}-a0")),
+            None,
+          )]
+        }
+        else {
+          let source_name = format!("{endpoint_id}-v0");
+          video_ssrcs
+            .iter()
+            .chain(&video_rtx_ssrcs)
+            .map(|ssrc| {
+              jingle_ssma::Source::new(*ssrc, Some(source_name.clone()), Some("camera".into()))
+            })
+            .collect()
+        };
 
-      for ssrc in description.ssrcs.iter_mut() {
-        ssrc.parameters.push(Parameter {
-          name: "msid".to_owned(),
-          value: Some(format!("{} {}", mslabel, label)),
-        });
-      }
+        for ssrc in description.ssrcs.iter_mut() {
+          ssrc.parameters.push(Parameter {
+            name: "msid".to_owned(),
+            value: Some(format!("{} {}", mslabel, label)),
+          });
+        }
 
-      description.ssrc_groups = if initiate_content.name.0 == "audio" {
-        vec![]
+        description.ssrc_groups = if initiate_content.name.0 == "audio" {
+          vec![]
+        }
+        else {
+          let mut groups = vec![];
+          if video_ssrcs.len() > 1 {
+            groups.push(jingle_ssma::Group {
+              semantics: Semantics::Sim,
+              sources: video_ssrcs
+                .iter()
+                .map(|ssrc| jingle_ssma::Source::new(*ssrc, None, None))
+                .collect(),
+            });
+          }
+          groups.extend(
+            video_ssrcs
+              .iter()
+              .zip(&video_rtx_ssrcs)
+              .map(|(ssrc, rtx_ssrc)| jingle_ssma::Group {
+                semantics: Semantics::Fid,
+                sources: vec![
+                  jingle_ssma::Source::new(*ssrc, None, None),
+                  jingle_ssma::Source::new(*rtx_ssrc, None, None),
+                ],
+              }),
+          );
+          groups
+        };
       }
-      else {
-        vec![jingle_ssma::Group {
-          semantics: Semantics::Fid,
-          sources: vec![
-            jingle_ssma::Source::new(video_ssrc, None, None),
-            jingle_ssma::Source::new(video_rtx_ssrc, None, None),
-          ],
-        }]
-      };
 
       if initiate_content.name.0 == "audio" {
         if let Some(hdrext) = audio_hdrext_ssrc_audio_level {
@@ -1466,6 +2797,12 @@ impl JingleSession {
             .hdrexts
             .push(RtpHdrext::new(hdrext, RTP_HDREXT_TRANSPORT_CC.to_owned()));
         }
+        if let Some((_, hdrext)) = dependency_descriptor {
+          description.hdrexts.push(RtpHdrext::new(
+            hdrext as u16,
+            dependency_descriptor::RTP_HDREXT_DEPENDENCY_DESCRIPTOR.to_owned(),
+          ));
+        }
       }
 
       let mut transport = IceUdpTransport::new().with_fingerprint(Fingerprint {
@@ -1504,7 +2841,12 @@ impl JingleSession {
 
       jingle_accept = jingle_accept.add_content(
         Content::new(Creator::Responder, initiate_content.name.clone())
-          .with_senders(Senders::Both)
+          .with_senders(if receive_only {
+            Senders::Initiator
+          }
+          else {
+            Senders::Both
+          })
           .with_description(description)
           .with_transport(transport),
       );
@@ -1512,7 +2854,10 @@ impl JingleSession {
 
     jingle_accept = jingle_accept.set_group(jingle_grouping::Group {
       semantics: jingle_grouping::Semantics::Bundle,
-      contents: vec![GroupContent::new("video"), GroupContent::new("audio")],
+      contents: accepted_contents
+        .iter()
+        .map(|content| GroupContent::new(content.name.0.as_str()))
+        .collect(),
     });
 
     let accept_iq_id = generate_id();
@@ -1522,6 +2867,7 @@ impl JingleSession {
     conference.xmpp_tx.send(session_accept_iq.into()).await?;
 
     Ok(Self {
+      sid: jingle.sid.clone(),
       pipeline,
       audio_sink_element,
       video_sink_element,
@@ -1531,13 +2877,41 @@ impl JingleSession {
       colibri_url: ice_transport.web_socket.clone().map(|ws| ws.url),
       colibri_channel: None,
       stats_handler_task: None,
+      audio_only,
+      video_codec: video_codecs.first().cloned(),
+      video_codecs,
+      video_ssrcs,
+      dependency_descriptor,
+      codec_registry: codec_registry.clone(),
+      video_transport_cc,
+      video_rtx_ssrc_map,
+      extra_video_sources: HashMap::new(),
+      next_video_source_index: 1,
+      rtpfunnel,
+      keyframe_requests,
+      audio_levels_reported,
       pipeline_state_null_rx,
     })
   }
 
+  /// Forget the audio levels last reported for a participant who has left, so that a later
+  /// stream with the same SSRC is reported from scratch.
+  pub(crate) fn participant_left(&self, participant_id: &str) {
+    let mut audio_levels_reported = self.audio_levels_reported.lock().unwrap();
+    for source in self.remote_ssrc_map.values() {
+      if source.participant_id.as_deref() == Some(participant_id) {
+        audio_levels_reported.remove(&source.ssrc);
+      }
+    }
+  }
+
   pub(crate) async fn source_add(&mut self, jingle: Jingle) -> Result<()> {
     for content in &jingle.contents {
       if let Some(Description::Rtp(description)) = &content.description {
+        if self.audio_only && description.media != "audio" {
+          debug!("audio-only: ignoring {} sources", description.media);
+          continue;
+        }
         for ssrc in &description.ssrcs {
           let owner = ssrc
             .info
@@ -1567,6 +2941,24 @@ impl JingleSession {
   }
 }
 
+/// An upstream force-key-unit event, as created by `gst_video_event_new_upstream_force_key_unit`.
+/// When it reaches rtpbin's RTP session, a PLI is sent to the sender of the stream.
+fn upstream_force_key_unit_event() -> gstreamer::Event {
+  gstreamer::event::CustomUpstream::new(
+    gstreamer::Structure::builder("GstForceKeyUnit")
+      .field("all-headers", false)
+      .build(),
+  )
+}
+
+fn rtx_ssrc_map_structure(video_rtx_ssrc_map: &HashMap<u32, u32>) -> gstreamer::Structure {
+  let mut ssrc_map = gstreamer::Structure::builder("application/x-rtp-ssrc-map");
+  for (ssrc, rtx_ssrc) in video_rtx_ssrc_map {
+    ssrc_map = ssrc_map.field(&ssrc.to_string(), rtx_ssrc);
+  }
+  ssrc_map.build()
+}
+
 fn participant_id_for_owner(owner: String) -> Result<Option<String>> {
   if owner == "jvb" {
     Ok(None)
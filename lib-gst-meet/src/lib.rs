@@ -1,5 +1,7 @@
+mod codec_registry;
 pub mod colibri;
 mod conference;
+mod dependency_descriptor;
 mod jingle;
 mod pinger;
 mod source;
@@ -11,7 +13,13 @@ mod xmpp;
 pub use xmpp_parsers;
 
 pub use crate::{
-  conference::{Feature, JitsiConference, JitsiConferenceConfig, Participant},
+  codec_registry::{CodecRegistry, ElementConfig},
+  conference::{
+    parse_video_codecs, AudioLevel, AudioRecvConfig, AudioSendConfig, BitrateUnit, Feature,
+    JitsiConference, JitsiConferenceConfig, JitterBufferConfig, KeyframeRequestStats,
+    PacketDirection, Participant, VideoScalePolicy,
+  },
+  dependency_descriptor::ScalabilityMode,
   source::MediaType,
   stanza_filter::StanzaFilter,
   xmpp::connection::{Authentication, Connection},
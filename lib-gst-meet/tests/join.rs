@@ -0,0 +1,913 @@
+//! Signalling tests against a mock deployment: no media flows.
+
+use std::{thread, time::Duration};
+
+use anyhow::{Context, Result};
+use colibri::{ColibriMessage, VideoType};
+use glib::object::ObjectExt as _;
+use gst_meet_test_support::{
+  MockDeployment, MockDeploymentConfig, MockParticipant, ServerEvent, H264_CH_PT, OPUS_PT, RED_PT,
+  TIMEOUT, VP8_PT, VP9_PT,
+};
+use gstreamer::prelude::{ElementExt as _, GstBinExt as _};
+use jitsi_xmpp_parsers::jingle::{Description, Jingle};
+use lib_gst_meet::{
+  xmpp_parsers::jingle::Senders, AudioRecvConfig, AudioSendConfig, Authentication, CodecRegistry,
+  Connection, JitsiConference, JitsiConferenceConfig, JitterBufferConfig, MediaType, Participant,
+  ScalabilityMode, VideoScalePolicy,
+};
+use tokio::{sync::mpsc, time::timeout};
+
+const ROOM: &str = "test";
+
+fn main_context() -> glib::MainContext {
+  let main_loop = glib::MainLoop::new(None, false);
+  let context = main_loop.context();
+  thread::spawn(move || main_loop.run());
+  context
+}
+
+async fn connect(
+  deployment: &MockDeployment,
+  authentication: Authentication,
+) -> Result<Connection> {
+  let (connection, background) = Connection::new(
+    deployment.web_socket_url(),
+    deployment.xmpp_domain(),
+    authentication,
+    ROOM,
+    false,
+  )
+  .await?;
+  tokio::spawn(background);
+  connection.connect().await?;
+  Ok(connection)
+}
+
+fn config(deployment: &MockDeployment) -> Result<JitsiConferenceConfig> {
+  Ok(JitsiConferenceConfig {
+    muc: format!("{}@conference.{}", ROOM, deployment.xmpp_domain()).parse()?,
+    focus: format!("focus@auth.{}/focus", deployment.xmpp_domain()).parse()?,
+    nick: "gst-meet".to_owned(),
+    region: None,
+    video_codecs: vec!["vp8".to_owned()],
+    h264_profile: None,
+    codec_registry: CodecRegistry::default(),
+    extra_muc_features: vec![],
+    receive_only: false,
+    audio_only: false,
+    simulcast_layers: 1,
+    scalability_mode: None,
+    bandwidth_estimation: false,
+    start_bitrate: 800,
+    stereo: false,
+    audio_send: AudioSendConfig::default(),
+    audio_recv: AudioRecvConfig::default(),
+    recv_audio_encoded: false,
+    recv_video_encoded: false,
+    recv_video_scale_width: 1280,
+    recv_video_scale_height: 720,
+    recv_video_scale_policy: VideoScalePolicy::default(),
+    recv_video_format: None,
+    audio_jitterbuffer: JitterBufferConfig::new(MediaType::Audio, 200),
+    video_jitterbuffer: JitterBufferConfig::new(MediaType::Video, 200),
+    #[cfg(feature = "log-rtp")]
+    log_rtp: false,
+    #[cfg(feature = "log-rtp")]
+    log_rtcp: false,
+  })
+}
+
+/// Join the conference, returning it along with the session-accept the mock focus received.
+async fn join(deployment: &MockDeployment) -> Result<(JitsiConference, Jingle)> {
+  gstreamer::init()?;
+  let connection = connect(deployment, Authentication::Anonymous).await?;
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config(deployment)?),
+    deployment.accept_session(),
+  );
+  Ok((conference?, session_accept?))
+}
+
+fn participant_id(participant: &Participant) -> String {
+  participant.muc_jid.resource_str().to_owned()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joins_conference() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  let (conference, session_accept) = join(&deployment).await?;
+
+  assert!(
+    session_accept.group.is_some(),
+    "session-accept has no BUNDLE group"
+  );
+  for (name, pt) in [("audio", OPUS_PT), ("video", VP8_PT)] {
+    let content = session_accept
+      .contents
+      .iter()
+      .find(|content| content.name.0 == name)
+      .with_context(|| format!("session-accept has no {} content", name))?;
+    assert_eq!(content.senders, Senders::Both);
+    let Some(Description::Rtp(description)) = &content.description
+    else {
+      panic!("{} content has no RTP description", name);
+    };
+    assert!(description
+      .payload_types
+      .iter()
+      .any(|payload_type| payload_type.id == pt));
+    assert!(
+      !description.ssrcs.is_empty(),
+      "no {} sources signalled",
+      name
+    );
+  }
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joins_receive_only() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.receive_only = true;
+  let join = tokio::spawn(JitsiConference::join(connection, main_context(), config));
+
+  let presence = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::JoinedMuc { presence } => Some(presence),
+      _ => None,
+    })
+    .await?;
+  for name in ["audiomuted", "videomuted"] {
+    let muted = presence
+      .children()
+      .find(|child| child.name() == name)
+      .with_context(|| format!("no {} in presence", name))?;
+    assert_eq!(muted.text(), "true");
+  }
+
+  let session_accept = deployment.accept_session().await?;
+  let conference = join.await??;
+  for content in &session_accept.contents {
+    assert_eq!(content.senders, Senders::Initiator);
+    let Some(Description::Rtp(description)) = &content.description
+    else {
+      panic!("{} content has no RTP description", content.name.0);
+    };
+    assert!(description.ssrcs.is_empty());
+  }
+  assert!(conference.audio_sink_element().await.is_err());
+  assert!(conference.video_sink_element().await.is_err());
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joins_receive_only_without_offered_video_codec() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.receive_only = true;
+  // The mock focus does not offer AV1, but we send nothing, so joining must not fail.
+  config.video_codecs = vec!["av1".to_owned()];
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, session_accept) = (conference?, session_accept?);
+  let video = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "video")
+    .context("session-accept has no video content")?;
+  let Some(Description::Rtp(description)) = &video.description
+  else {
+    panic!("video content has no RTP description");
+  };
+  // Every offered video codec is accepted for receiving instead.
+  assert!(description
+    .payload_types
+    .iter()
+    .any(|payload_type| payload_type.id == VP8_PT));
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joins_audio_only() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.audio_only = true;
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, session_accept) = (conference?, session_accept?);
+
+  let content_names: Vec<_> = session_accept
+    .contents
+    .iter()
+    .map(|content| content.name.0.as_str())
+    .collect();
+  assert_eq!(content_names, ["audio"]);
+  assert!(conference.audio_sink_element().await.is_ok());
+  assert!(conference.video_sink_element().await.is_err());
+
+  deployment
+    .colibri
+    .wait_for(|message| {
+      matches!(
+        message,
+        ColibriMessage::ReceiverVideoConstraints {
+          last_n: Some(0),
+          ..
+        }
+      )
+    })
+    .await?;
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn negotiates_preferred_video_codec() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  // The mock focus does not offer H.264.
+  config.video_codecs = vec!["h264".to_owned(), "vp8".to_owned()];
+  let join = tokio::spawn(JitsiConference::join(connection, main_context(), config));
+
+  let presence = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::JoinedMuc { presence } => Some(presence),
+      _ => None,
+    })
+    .await?;
+  let codec_list = presence
+    .children()
+    .find(|child| child.name() == "jitsi_participant_codecList")
+    .context("no codec list in presence")?;
+  assert_eq!(codec_list.text(), "h264,vp8");
+
+  let session_accept = deployment.accept_session().await?;
+  let conference = join.await??;
+  assert_eq!(conference.video_codec().await?, "vp8");
+  let video = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "video")
+    .context("session-accept has no video content")?;
+  let Some(Description::Rtp(description)) = &video.description
+  else {
+    panic!("video content has no RTP description");
+  };
+  assert_eq!(description.payload_types[0].id, VP8_PT);
+  // We advertise the codec we actually send, without needing an on_video_codec_changed callback.
+  deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::Presence(presence) => presence
+        .children()
+        .find(|child| child.name() == "jitsi_participant_codecType")
+        .filter(|codec_type| codec_type.text() == "vp8")
+        .map(|_| ()),
+      _ => None,
+    })
+    .await?;
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn negotiates_h264_profile() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.video_codecs = vec!["h264".to_owned()];
+  config.h264_profile = Some("constrained-high".to_owned());
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, session_accept) = (conference?, session_accept?);
+
+  let video = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "video")
+    .context("session-accept has no video content")?;
+  let Some(Description::Rtp(description)) = &video.description
+  else {
+    panic!("video content has no RTP description");
+  };
+  let [h264] = &description.payload_types[..]
+  else {
+    panic!("expected only the chosen H.264 payload type");
+  };
+  assert_eq!(h264.id, H264_CH_PT);
+  assert!(h264
+    .parameters
+    .iter()
+    .any(|param| param.name == "profile-level-id" && param.value == "640c1f"));
+  assert_eq!(conference.video_codec().await?, "h264");
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn accepts_constrained_high_for_high_encoder() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.video_codecs = vec!["h264".to_owned()];
+  config.h264_profile = Some("high".to_owned());
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, session_accept) = (conference?, session_accept?);
+
+  let video = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "video")
+    .context("session-accept has no video content")?;
+  let Some(Description::Rtp(description)) = &video.description
+  else {
+    panic!("video content has no RTP description");
+  };
+  assert!(description
+    .payload_types
+    .iter()
+    .any(|payload_type| payload_type.id == H264_CH_PT));
+  assert_eq!(conference.video_codec().await?, "h264");
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn skips_h264_without_matching_profile() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.video_codecs = vec!["h264".to_owned(), "vp8".to_owned()];
+  config.h264_profile = Some("main".to_owned());
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, _session_accept) = (conference?, session_accept?);
+  assert_eq!(conference.video_codec().await?, "vp8");
+  assert!(conference.set_video_codec("h264").await.is_err());
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn negotiates_dependency_descriptor() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.video_codecs = vec!["vp9".to_owned()];
+  config.scalability_mode = Some(ScalabilityMode::L1T1);
+  let join = tokio::spawn(JitsiConference::join(connection, main_context(), config));
+
+  let presence = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::JoinedMuc { presence } => Some(presence),
+      _ => None,
+    })
+    .await?;
+  let source_info = presence
+    .children()
+    .find(|child| child.name() == "SourceInfo")
+    .context("no SourceInfo in presence")?;
+  assert!(source_info.text().contains(r#""scalabilityMode":"L1T1""#));
+
+  let session_accept = deployment.accept_session().await?;
+  let conference = join.await??;
+  let video = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "video")
+    .context("session-accept has no video content")?;
+  let Some(Description::Rtp(description)) = &video.description
+  else {
+    panic!("video content has no RTP description");
+  };
+  assert!(description.hdrexts.iter().any(|hdrext| hdrext.uri
+    == "https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension"));
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_vp9_temporal_scalability() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.video_codecs = vec!["vp8".to_owned(), "vp9".to_owned()];
+  config.scalability_mode = Some(ScalabilityMode::L1T3);
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, _session_accept) = (conference?, session_accept?);
+  assert_eq!(conference.video_codec().await?, "vp8");
+  // vp9enc does not expose the temporal layer of each frame, so there is no way to describe
+  // it to the bridge.
+  assert!(conference.set_video_codec("vp9").await.is_err());
+  assert_eq!(conference.video_codec().await?, "vp8");
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn negotiates_audio_redundancy() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  let (conference, session_accept) = join(&deployment).await?;
+
+  let audio = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "audio")
+    .context("session-accept has no audio content")?;
+  let Some(Description::Rtp(description)) = &audio.description
+  else {
+    panic!("audio content has no RTP description");
+  };
+  let opus = description
+    .payload_types
+    .iter()
+    .find(|payload_type| payload_type.id == OPUS_PT)
+    .context("no opus payload type")?;
+  assert!(opus
+    .parameters
+    .iter()
+    .any(|param| param.name == "useinbandfec" && param.value == "1"));
+  // RED needs elements from gst-plugins-good 1.20.
+  if gstreamer::ElementFactory::find("rtpredenc").is_some() {
+    let red = description
+      .payload_types
+      .iter()
+      .find(|payload_type| payload_type.id == RED_PT)
+      .context("no red payload type")?;
+    assert_eq!(red.parameters[0].value, format!("{}/{}", OPUS_PT, OPUS_PT));
+  }
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn signals_opus_parameters() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.stereo = true;
+  config.audio_send = AudioSendConfig {
+    stereo: true,
+    ptime: Some(20),
+  };
+  config.audio_recv.max_average_bitrate = Some(128000);
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, session_accept) = (conference?, session_accept?);
+
+  let audio = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "audio")
+    .context("session-accept has no audio content")?;
+  let Some(Description::Rtp(description)) = &audio.description
+  else {
+    panic!("audio content has no RTP description");
+  };
+  let opus = description
+    .payload_types
+    .iter()
+    .find(|payload_type| payload_type.id == OPUS_PT)
+    .context("no opus payload type")?;
+  for (name, value) in [
+    ("stereo", "1"),
+    ("sprop-stereo", "1"),
+    ("maxaveragebitrate", "128000"),
+  ] {
+    assert!(
+      opus
+        .parameters
+        .iter()
+        .any(|param| param.name == name && param.value == value),
+      "no {}={} in opus fmtp",
+      name,
+      value
+    );
+  }
+  assert_eq!(opus.ptime, Some(20));
+
+  let payloader = conference
+    .pipeline()
+    .await?
+    .iterate_recurse()
+    .into_iter()
+    .flatten()
+    .find(|element| {
+      element
+        .factory()
+        .map_or(false, |factory| factory.name() == "rtpopuspay")
+    })
+    .context("no opus payloader")?;
+  assert_eq!(payloader.property::<i64>("min-ptime"), 20_000_000);
+  assert_eq!(payloader.property::<i64>("max-ptime"), 20_000_000);
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn adds_and_removes_video_source() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  let (conference, _) = join(&deployment).await?;
+
+  let source_name = conference.add_video_source(VideoType::Desktop).await?;
+  assert!(conference
+    .video_source_sink_element(&source_name)
+    .await
+    .is_ok());
+
+  let source_add = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::SourceAdd(jingle) => Some(jingle),
+      _ => None,
+    })
+    .await?;
+  let Some(Description::Rtp(description)) = &source_add.contents[0].description
+  else {
+    panic!("source-add has no RTP description");
+  };
+  assert_eq!(description.media, "video");
+  assert_eq!(description.ssrcs.len(), 2);
+  for source in &description.ssrcs {
+    assert_eq!(source.name.as_deref(), Some(source_name.as_str()));
+    assert_eq!(source.video_type.as_deref(), Some("desktop"));
+  }
+  assert_eq!(description.ssrc_groups.len(), 1);
+
+  let presence = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::Presence(presence) => Some(presence),
+      _ => None,
+    })
+    .await?;
+  let source_info = presence
+    .children()
+    .find(|child| child.name() == "SourceInfo")
+    .context("no SourceInfo in presence")?;
+  assert!(source_info.text().contains(&source_name));
+
+  conference.remove_video_source(&source_name).await?;
+  let source_remove = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::SourceRemove(jingle) => Some(jingle),
+      _ => None,
+    })
+    .await?;
+  let Some(Description::Rtp(removed)) = &source_remove.contents[0].description
+  else {
+    panic!("source-remove has no RTP description");
+  };
+  assert_eq!(
+    removed
+      .ssrcs
+      .iter()
+      .map(|source| source.id)
+      .collect::<Vec<_>>(),
+    description
+      .ssrcs
+      .iter()
+      .map(|source| source.id)
+      .collect::<Vec<_>>()
+  );
+  assert!(conference
+    .video_source_sink_element(&source_name)
+    .await
+    .is_err());
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn switches_video_codec_for_participants() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.video_codecs = vec!["vp9".to_owned(), "vp8".to_owned()];
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context(), config),
+    deployment.accept_session(),
+  );
+  let (conference, session_accept) = (conference?, session_accept?);
+  assert_eq!(conference.video_codec().await?, "vp9");
+  let video = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "video")
+    .context("session-accept has no video content")?;
+  let Some(Description::Rtp(description)) = &video.description
+  else {
+    panic!("video content has no RTP description");
+  };
+  for pt in [VP9_PT, VP8_PT] {
+    assert!(description
+      .payload_types
+      .iter()
+      .any(|payload_type| payload_type.id == pt));
+  }
+  assert!(conference.set_video_codec("h264").await.is_err());
+
+  let (changed_tx, mut changed_rx) = mpsc::unbounded_channel();
+  conference
+    .on_video_codec_changed(move |_conference, codec| {
+      let changed_tx = changed_tx.clone();
+      Box::pin(async move {
+        changed_tx.send(codec)?;
+        Ok(())
+      })
+    })
+    .await;
+
+  // Bob cannot decode VP9, so we should fall back to VP8 while he is in the room.
+  let mut bob = MockParticipant::new("bob", Some(3333), Some(4444));
+  bob.video_codecs = vec!["vp8".to_owned()];
+  deployment.xmpp.add_participant(&bob).await?;
+  let codec = timeout(TIMEOUT, changed_rx.recv())
+    .await?
+    .context("channel closed")?;
+  assert_eq!(codec, "vp8");
+  assert_eq!(conference.video_codec().await?, "vp8");
+  deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::Presence(presence) => presence
+        .children()
+        .find(|child| child.name() == "jitsi_participant_codecType")
+        .filter(|codec_type| codec_type.text() == "vp8")
+        .map(|_| ()),
+      _ => None,
+    })
+    .await?;
+
+  deployment.xmpp.remove_participant(&bob).await?;
+  let codec = timeout(TIMEOUT, changed_rx.recv())
+    .await?
+    .context("channel closed")?;
+  assert_eq!(codec, "vp9");
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_conference_from_focus() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  gstreamer::init()?;
+  let connection = connect(&deployment, Authentication::Anonymous).await?;
+  let mut config = config(&deployment)?;
+  config.stereo = true;
+  let room = config.muc.to_string();
+  let join = tokio::spawn(JitsiConference::join(connection, main_context(), config));
+
+  let properties = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::ConferenceRequested {
+        room: requested,
+        properties,
+      } if requested == room => Some(properties),
+      _ => None,
+    })
+    .await?;
+  assert_eq!(properties.get("stereo").map(String::as_str), Some("true"));
+  assert_eq!(
+    properties.get("startBitrate").map(String::as_str),
+    Some("800")
+  );
+
+  deployment.accept_session().await?;
+  join.await??.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_participants() -> Result<()> {
+  let alice = MockParticipant::new("alice", Some(1111), Some(2222));
+  let bob = MockParticipant::new("bob", Some(3333), None);
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice.clone()],
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join(&deployment).await?;
+
+  let (joined_tx, mut joined_rx) = mpsc::unbounded_channel();
+  conference
+    .on_participant(move |_conference, participant| {
+      let joined_tx = joined_tx.clone();
+      Box::pin(async move {
+        joined_tx.send(participant)?;
+        Ok(())
+      })
+    })
+    .await;
+  let (left_tx, mut left_rx) = mpsc::unbounded_channel();
+  conference
+    .on_participant_left(move |_conference, participant| {
+      let left_tx = left_tx.clone();
+      Box::pin(async move {
+        left_tx.send(participant)?;
+        Ok(())
+      })
+    })
+    .await;
+
+  let participant = timeout(TIMEOUT, joined_rx.recv())
+    .await?
+    .context("channel closed")?;
+  assert_eq!(participant_id(&participant), alice.id);
+  assert_eq!(participant.nick.as_deref(), Some(alice.nick.as_str()));
+
+  deployment.xmpp.add_participant(&bob).await?;
+  let participant = timeout(TIMEOUT, joined_rx.recv())
+    .await?
+    .context("channel closed")?;
+  assert_eq!(participant_id(&participant), bob.id);
+
+  deployment.xmpp.remove_participant(&alice).await?;
+  let participant = timeout(TIMEOUT, left_rx.recv())
+    .await?
+    .context("channel closed")?;
+  assert_eq!(participant_id(&participant), alice.id);
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exchanges_colibri_messages() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  let (conference, _) = join(&deployment).await?;
+
+  let (received_tx, mut received_rx) = mpsc::unbounded_channel();
+  conference
+    .on_colibri_message(move |_conference, message| {
+      let received_tx = received_tx.clone();
+      Box::pin(async move {
+        received_tx.send(message)?;
+        Ok(())
+      })
+    })
+    .await;
+
+  conference
+    .send_colibri_message(ColibriMessage::EndpointMessage {
+      from: None,
+      to: Some("alice".to_owned()),
+      msg_payload: serde_json::json!({ "hello": "bridge" }),
+    })
+    .await?;
+  deployment
+    .colibri
+    .wait_for(|message| {
+      matches!(
+        message,
+        ColibriMessage::EndpointMessage { to: Some(to), .. } if to == "alice"
+      )
+    })
+    .await?;
+
+  deployment
+    .colibri
+    .send(ColibriMessage::EndpointMessage {
+      from: Some("alice".to_owned()),
+      to: None,
+      msg_payload: serde_json::json!({ "hello": "client" }),
+    })
+    .await?;
+  let message = timeout(TIMEOUT, received_rx.recv())
+    .await?
+    .context("channel closed")?;
+  assert!(matches!(
+    message,
+    ColibriMessage::EndpointMessage { from: Some(from), .. } if from == "alice"
+  ));
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn applies_sender_video_constraints() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig::default()).await?;
+  let (conference, _) = join(&deployment).await?;
+
+  let source_name = conference.video_source_name()?;
+  let capsfilter = gstreamer::ElementFactory::make("capsfilter")
+    .property(
+      "caps",
+      gstreamer::Caps::builder("video/x-raw")
+        .field("width", 1280)
+        .field("height", 720)
+        .build(),
+    )
+    .build()?;
+  conference
+    .set_sender_video_capsfilter(&source_name, Some(capsfilter.clone()))
+    .await?;
+
+  let (constraint_tx, mut constraint_rx) = mpsc::unbounded_channel();
+  conference
+    .on_sender_video_constraint(move |_conference, source_name, max_height| {
+      let constraint_tx = constraint_tx.clone();
+      Box::pin(async move {
+        constraint_tx.send((source_name, max_height))?;
+        Ok(())
+      })
+    })
+    .await;
+
+  deployment
+    .colibri
+    .send(serde_json::from_value(serde_json::json!({
+      "colibriClass": "SenderSourceConstraints",
+      "sourceName": source_name,
+      "maxHeight": 180,
+    }))?)
+    .await?;
+  let constraint = timeout(TIMEOUT, constraint_rx.recv())
+    .await?
+    .context("channel closed")?;
+  assert_eq!(constraint, (source_name.clone(), 180));
+  assert_eq!(
+    conference.sender_video_constraint(&source_name).await,
+    Some(180)
+  );
+
+  let caps: gstreamer::Caps = capsfilter.property("caps");
+  let structure = caps.structure(0).context("empty caps")?;
+  assert_eq!(structure.get::<i32>("height")?, 180);
+  assert_eq!(structure.get::<i32>("width")?, 320);
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_bad_credentials() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    accounts: vec![("user".to_owned(), "secret".to_owned())],
+    ..Default::default()
+  })
+  .await?;
+
+  let connecting = connect(
+    &deployment,
+    Authentication::Plain {
+      username: "user".to_owned(),
+      password: "wrong".to_owned(),
+    },
+  );
+  // The connection's read loop fails on the SASL failure, but connect() does not currently
+  // observe that, so bound the wait rather than expecting an error.
+  let result = timeout(Duration::from_secs(1), connecting).await;
+  assert!(
+    !matches!(result, Ok(Ok(_))),
+    "connected with bad credentials"
+  );
+
+  let username = deployment
+    .xmpp
+    .wait_for(|event| match event {
+      ServerEvent::AuthenticationFailed { username, .. } => Some(username),
+      _ => None,
+    })
+    .await?;
+  assert_eq!(username.as_deref(), Some("user"));
+  Ok(())
+}
@@ -0,0 +1,1079 @@
+//! Media tests against a mock deployment with a loopback bridge: ICE, DTLS-SRTP and RTP flow
+//! over 127.0.0.1.
+
+use std::{
+  collections::{HashMap, HashSet},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  thread,
+  time::Duration,
+};
+
+use anyhow::{Context, Result};
+use gst_meet_test_support::{
+  MockDeployment, MockDeploymentConfig, MockParticipant, PEER_AUDIO_LEVEL, TIMEOUT,
+  TRANSPORT_CC_HDREXT_ID,
+};
+use gstreamer::prelude::{
+  ElementExt as _, GstBinExt as _, GstObjectExt as _, ObjectExt as _, PadExt as _,
+  PadExtManual as _,
+};
+use gstreamer_rtp::RTPBuffer;
+use jitsi_xmpp_parsers::{
+  jingle::{Description, Jingle},
+  jingle_ssma::Semantics,
+};
+use lib_gst_meet::{
+  AudioLevel, AudioRecvConfig, AudioSendConfig, Authentication, BitrateUnit, CodecRegistry,
+  Connection, JitsiConference, JitsiConferenceConfig, JitterBufferConfig, KeyframeRequestStats,
+  MediaType, PacketDirection, VideoScalePolicy,
+};
+use tokio::time::{sleep, timeout};
+
+const ROOM: &str = "test";
+
+async fn join(deployment: &MockDeployment) -> Result<(JitsiConference, Jingle)> {
+  join_with(deployment, |_config| {}).await
+}
+
+async fn join_with(
+  deployment: &MockDeployment,
+  configure: impl FnOnce(&mut JitsiConferenceConfig),
+) -> Result<(JitsiConference, Jingle)> {
+  gstreamer::init()?;
+
+  let main_loop = glib::MainLoop::new(None, false);
+  let main_context = main_loop.context();
+  thread::spawn(move || main_loop.run());
+
+  let (connection, background) = Connection::new(
+    deployment.web_socket_url(),
+    deployment.xmpp_domain(),
+    Authentication::Anonymous,
+    ROOM,
+    false,
+  )
+  .await?;
+  tokio::spawn(background);
+  connection.connect().await?;
+
+  let mut config = JitsiConferenceConfig {
+    muc: format!("{}@conference.{}", ROOM, deployment.xmpp_domain()).parse()?,
+    focus: format!("focus@auth.{}/focus", deployment.xmpp_domain()).parse()?,
+    nick: "gst-meet".to_owned(),
+    region: None,
+    video_codecs: vec!["vp8".to_owned()],
+    h264_profile: None,
+    codec_registry: CodecRegistry::default(),
+    extra_muc_features: vec![],
+    receive_only: false,
+    audio_only: false,
+    simulcast_layers: 1,
+    scalability_mode: None,
+    bandwidth_estimation: false,
+    start_bitrate: 800,
+    stereo: false,
+    audio_send: AudioSendConfig::default(),
+    audio_recv: AudioRecvConfig::default(),
+    recv_audio_encoded: false,
+    recv_video_encoded: false,
+    recv_video_scale_width: 320,
+    recv_video_scale_height: 180,
+    recv_video_scale_policy: VideoScalePolicy::default(),
+    recv_video_format: None,
+    audio_jitterbuffer: JitterBufferConfig::new(MediaType::Audio, 200),
+    video_jitterbuffer: JitterBufferConfig::new(MediaType::Video, 200),
+    #[cfg(feature = "log-rtp")]
+    log_rtp: false,
+    #[cfg(feature = "log-rtp")]
+    log_rtcp: false,
+  };
+  configure(&mut config);
+
+  let (conference, session_accept) = tokio::join!(
+    JitsiConference::join(connection, main_context, config),
+    deployment.accept_session(),
+  );
+  Ok((conference?, session_accept?))
+}
+
+fn signalled_ssrc(session_accept: &Jingle, media: &str) -> Result<u32> {
+  session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == media)
+    .and_then(|content| match &content.description {
+      Some(Description::Rtp(description)) => description.ssrcs.first(),
+      _ => None,
+    })
+    .map(|source| source.id)
+    .with_context(|| format!("no {} SSRC in session-accept", media))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_media_to_bridge() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, session_accept) = join(&deployment).await?;
+
+  let bin = gstreamer::parse::bin_from_description(
+    "audiotestsrc is-live=true ! opusenc name=audio \
+     videotestsrc is-live=true ! video/x-raw,width=320,height=180 ! vp8enc deadline=1 name=video",
+    false,
+  )?;
+  conference.add_bin(&bin).await?;
+  bin
+    .by_name("audio")
+    .context("no audio element")?
+    .link(&conference.audio_sink_element().await?)?;
+  bin
+    .by_name("video")
+    .context("no video element")?
+    .link(&conference.video_sink_element().await?)?;
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  let peer = deployment.peer.as_ref().context("no loopback peer")?;
+  peer
+    .wait_for_packets(signalled_ssrc(&session_accept, "audio")?, 10)
+    .await?;
+  peer
+    .wait_for_packets(signalled_ssrc(&session_accept, "video")?, 10)
+    .await?;
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_simulcast_layers() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, session_accept) =
+    join_with(&deployment, |config| config.simulcast_layers = 3).await?;
+
+  let ssrcs = session_accept
+    .contents
+    .iter()
+    .find(|content| content.name.0 == "video")
+    .and_then(|content| match &content.description {
+      Some(Description::Rtp(description)) => description
+        .ssrc_groups
+        .iter()
+        .find(|group| group.semantics == Semantics::Sim),
+      _ => None,
+    })
+    .map(|group| {
+      group
+        .sources
+        .iter()
+        .map(|source| source.id)
+        .collect::<Vec<_>>()
+    })
+    .context("no SIM group in session-accept")?;
+  assert_eq!(ssrcs.len(), 3);
+
+  let bin = gstreamer::parse::bin_from_description(
+    "videotestsrc is-live=true ! video/x-raw,width=80,height=45 ! vp8enc deadline=1 name=video_0 \
+     videotestsrc is-live=true ! video/x-raw,width=160,height=90 ! vp8enc deadline=1 name=video_1 \
+     videotestsrc is-live=true ! video/x-raw,width=320,height=180 ! vp8enc deadline=1 name=video_2",
+    false,
+  )?;
+  conference.add_bin(&bin).await?;
+  let video_sink = conference.video_sink_element().await?;
+  for layer in 0..3 {
+    bin
+      .by_name(&format!("video_{}", layer))
+      .context("no video element")?
+      .link_pads(None, &video_sink, Some(&format!("sink_{}", layer)))?;
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  let peer = deployment.peer.as_ref().context("no loopback peer")?;
+  for ssrc in ssrcs {
+    peer.wait_for_packets(ssrc, 10).await?;
+  }
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receives_participant_media() -> Result<()> {
+  let alice = MockParticipant::new("alice", Some(1111), Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join(&deployment).await?;
+
+  let audio_buffers = Arc::new(AtomicU64::new(0));
+  let video_buffers = Arc::new(AtomicU64::new(0));
+  {
+    let audio_buffers = audio_buffers.clone();
+    let video_buffers = video_buffers.clone();
+    conference
+      .on_participant(move |conference, participant| {
+        let audio_buffers = audio_buffers.clone();
+        let video_buffers = video_buffers.clone();
+        Box::pin(async move {
+          let bin = gstreamer::parse::bin_from_description(
+            "fakesink name=audio async=false fakesink name=video async=false",
+            false,
+          )?;
+          for (name, counter) in [("audio", audio_buffers), ("video", video_buffers)] {
+            let sink_pad = bin
+              .by_name(name)
+              .context("missing fakesink")?
+              .static_pad("sink")
+              .context("fakesink has no sink pad")?;
+            sink_pad.add_probe(gstreamer::PadProbeType::BUFFER, move |_pad, _info| {
+              counter.fetch_add(1, Ordering::Relaxed);
+              gstreamer::PadProbeReturn::Ok
+            });
+            bin.add_pad(
+              &gstreamer::GhostPad::builder_with_target(&sink_pad)?
+                .name(name)
+                .build(),
+            )?;
+          }
+          bin.set_property(
+            "name",
+            format!("participant_{}", participant.muc_jid.resource()),
+          );
+          conference.add_bin(&bin).await
+        })
+      })
+      .await;
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while audio_buffers.load(Ordering::Relaxed) == 0 || video_buffers.load(Ordering::Relaxed) == 0 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for participant media")?;
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn decodes_with_configured_decoder() -> Result<()> {
+  let alice = MockParticipant::new("alice", None, Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  // The first decoder is not installed, so the second one is used.
+  let mut codec_registry = CodecRegistry::default();
+  codec_registry.add_decoder("vp8=no-such-decoder")?;
+  codec_registry.add_decoder("vp8=vp8dec threads=2")?;
+  let (conference, _) = join_with(&deployment, |config| {
+    config.codec_registry = codec_registry;
+  })
+  .await?;
+  let video_buffers = Arc::new(AtomicU64::new(0));
+  {
+    let video_buffers = video_buffers.clone();
+    conference
+      .on_participant(move |conference, participant| {
+        let video_buffers = video_buffers.clone();
+        Box::pin(async move {
+          let bin =
+            gstreamer::parse::bin_from_description("fakesink name=video async=false", false)?;
+          let sink_pad = bin
+            .by_name("video")
+            .context("missing fakesink")?
+            .static_pad("sink")
+            .context("fakesink has no sink pad")?;
+          sink_pad.add_probe(gstreamer::PadProbeType::BUFFER, move |_pad, _info| {
+            video_buffers.fetch_add(1, Ordering::Relaxed);
+            gstreamer::PadProbeReturn::Ok
+          });
+          bin.add_pad(
+            &gstreamer::GhostPad::builder_with_target(&sink_pad)?
+              .name("video")
+              .build(),
+          )?;
+          bin.set_property(
+            "name",
+            format!("participant_{}", participant.muc_jid.resource()),
+          );
+          conference.add_bin(&bin).await
+        })
+      })
+      .await;
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while video_buffers.load(Ordering::Relaxed) == 0 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for decoded video")?;
+  let decoders = elements(&conference.pipeline().await?, "vp8dec");
+  assert_eq!(decoders.len(), 1);
+  assert_eq!(decoders[0].property::<u32>("threads"), 2);
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receives_encoded_participant_media() -> Result<()> {
+  let alice = MockParticipant::new("alice", Some(1111), Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join_with(&deployment, |config| {
+    config.recv_audio_encoded = true;
+    config.recv_video_encoded = true;
+  })
+  .await?;
+
+  let caps_received = Arc::new(Mutex::new(Vec::new()));
+  {
+    let caps_received = caps_received.clone();
+    conference
+      .on_participant(move |conference, participant| {
+        let caps_received = caps_received.clone();
+        Box::pin(async move {
+          let bin = gstreamer::parse::bin_from_description(
+            "fakesink name=audio async=false fakesink name=video async=false",
+            false,
+          )?;
+          for name in ["audio", "video"] {
+            let sink_pad = bin
+              .by_name(name)
+              .context("missing fakesink")?
+              .static_pad("sink")
+              .context("fakesink has no sink pad")?;
+            let caps_received = caps_received.clone();
+            sink_pad.add_probe(gstreamer::PadProbeType::BUFFER, move |pad, _info| {
+              if let Some(caps) = pad.current_caps() {
+                if let Some(structure) = caps.structure(0) {
+                  caps_received
+                    .lock()
+                    .unwrap()
+                    .push(structure.name().to_string());
+                }
+              }
+              gstreamer::PadProbeReturn::Remove
+            });
+            bin.add_pad(
+              &gstreamer::GhostPad::builder_with_target(&sink_pad)?
+                .name(name)
+                .build(),
+            )?;
+          }
+          bin.set_property(
+            "name",
+            format!("participant_{}", participant.muc_jid.resource()),
+          );
+          conference.add_bin(&bin).await
+        })
+      })
+      .await;
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while caps_received.lock().unwrap().len() < 2 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for participant media")?;
+
+  let mut caps_received = caps_received.lock().unwrap().clone();
+  caps_received.sort();
+  assert_eq!(caps_received, ["audio/x-opus", "video/x-vp8"]);
+
+  conference.leave().await
+}
+
+fn rtp_ssrc(buffer: &gstreamer::Buffer) -> Option<u32> {
+  let map = buffer.map_readable().ok()?;
+  Some(u32::from_be_bytes(map.get(8..12)?.try_into().ok()?))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn taps_rtp_and_rtcp() -> Result<()> {
+  let alice = MockParticipant::new("alice", Some(1111), None);
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, session_accept) = join(&deployment).await?;
+
+  let rtp_ssrcs = Arc::new(Mutex::new(HashSet::new()));
+  let rtcp_sent = Arc::new(AtomicU64::new(0));
+  {
+    let rtp_ssrcs = rtp_ssrcs.clone();
+    conference.on_rtp(move |direction, buffer| {
+      if let Some(ssrc) = rtp_ssrc(buffer) {
+        rtp_ssrcs.lock().unwrap().insert((direction, ssrc));
+      }
+    });
+  }
+  {
+    let rtcp_sent = rtcp_sent.clone();
+    conference.on_rtcp(move |direction, _buffer| {
+      if direction == PacketDirection::Send {
+        rtcp_sent.fetch_add(1, Ordering::Relaxed);
+      }
+    });
+  }
+
+  let bin = gstreamer::parse::bin_from_description(
+    "audiotestsrc is-live=true ! opusenc name=audio",
+    false,
+  )?;
+  conference.add_bin(&bin).await?;
+  bin
+    .by_name("audio")
+    .context("no audio element")?
+    .link(&conference.audio_sink_element().await?)?;
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  assert_eq!(
+    conference.remote_ssrcs("alice").await?,
+    [(1111, MediaType::Audio)]
+  );
+  let expected = [
+    (PacketDirection::Recv, 1111),
+    (
+      PacketDirection::Send,
+      signalled_ssrc(&session_accept, "audio")?,
+    ),
+  ];
+  timeout(TIMEOUT, async {
+    while !expected
+      .iter()
+      .all(|packet| rtp_ssrcs.lock().unwrap().contains(packet))
+      || rtcp_sent.load(Ordering::Relaxed) == 0
+    {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for RTP and RTCP packets")?;
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scales_received_video_by_policy() -> Result<()> {
+  let alice = MockParticipant::new("alice", None, Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join_with(&deployment, |config| {
+    config.recv_video_scale_width = 160;
+    config.recv_video_scale_height = 160;
+    config.recv_video_scale_policy = VideoScalePolicy::Fit;
+  })
+  .await?;
+
+  let video_size = Arc::new(Mutex::new(None));
+  {
+    let video_size = video_size.clone();
+    conference
+      .on_participant(move |conference, participant| {
+        let video_size = video_size.clone();
+        Box::pin(async move {
+          let bin =
+            gstreamer::parse::bin_from_description("fakesink name=video async=false", false)?;
+          let sink_pad = bin
+            .by_name("video")
+            .context("missing fakesink")?
+            .static_pad("sink")
+            .context("fakesink has no sink pad")?;
+          sink_pad.add_probe(gstreamer::PadProbeType::BUFFER, move |pad, _info| {
+            if let Some(structure) = pad
+              .current_caps()
+              .as_ref()
+              .and_then(|caps| caps.structure(0))
+            {
+              *video_size.lock().unwrap() = structure
+                .get::<i32>("width")
+                .ok()
+                .zip(structure.get::<i32>("height").ok());
+            }
+            gstreamer::PadProbeReturn::Ok
+          });
+          bin.add_pad(
+            &gstreamer::GhostPad::builder_with_target(&sink_pad)?
+              .name("video")
+              .build(),
+          )?;
+          bin.set_property(
+            "name",
+            format!("participant_{}", participant.muc_jid.resource()),
+          );
+          conference.add_bin(&bin).await
+        })
+      })
+      .await;
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  let wait_for_size = |size| {
+    let video_size = video_size.clone();
+    timeout(TIMEOUT, async move {
+      while *video_size.lock().unwrap() != Some(size) {
+        sleep(Duration::from_millis(50)).await;
+      }
+    })
+  };
+
+  // The mock participant sends 320x180.
+  wait_for_size((160, 90))
+    .await
+    .context("timed out waiting for video fitted within 160x160")?;
+
+  conference
+    .set_participant_video_scale_policy("alice", Some(VideoScalePolicy::Letterbox))
+    .await;
+  wait_for_size((160, 160))
+    .await
+    .context("timed out waiting for letterboxed video")?;
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn converts_received_media() -> Result<()> {
+  let alice = MockParticipant::new("alice", Some(1111), Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let format = AudioRecvConfig::parse_format("S16LE")?;
+  let (conference, _) = join_with(&deployment, |config| {
+    config.audio_recv = AudioRecvConfig {
+      rate: Some(16000),
+      channels: Some(1),
+      format: Some(format),
+      ..Default::default()
+    };
+    config.recv_video_format = Some("I420".to_owned());
+  })
+  .await?;
+
+  let caps_received = Arc::new(Mutex::new(HashMap::new()));
+  {
+    let caps_received = caps_received.clone();
+    conference
+      .on_participant(move |conference, participant| {
+        let caps_received = caps_received.clone();
+        Box::pin(async move {
+          let bin = gstreamer::parse::bin_from_description(
+            "fakesink name=audio async=false fakesink name=video async=false",
+            false,
+          )?;
+          for name in ["audio", "video"] {
+            let sink_pad = bin
+              .by_name(name)
+              .context("missing fakesink")?
+              .static_pad("sink")
+              .context("fakesink has no sink pad")?;
+            let caps_received = caps_received.clone();
+            sink_pad.add_probe(gstreamer::PadProbeType::BUFFER, move |pad, _info| {
+              if let Some(caps) = pad.current_caps() {
+                caps_received.lock().unwrap().insert(name, caps);
+              }
+              gstreamer::PadProbeReturn::Remove
+            });
+            bin.add_pad(
+              &gstreamer::GhostPad::builder_with_target(&sink_pad)?
+                .name(name)
+                .build(),
+            )?;
+          }
+          bin.set_property(
+            "name",
+            format!("participant_{}", participant.muc_jid.resource()),
+          );
+          conference.add_bin(&bin).await
+        })
+      })
+      .await;
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while caps_received.lock().unwrap().len() < 2 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for participant media")?;
+
+  let caps_received = caps_received.lock().unwrap().clone();
+  let audio = caps_received["audio"]
+    .structure(0)
+    .context("empty audio caps")?;
+  assert_eq!(audio.get::<i32>("rate")?, 16000);
+  assert_eq!(audio.get::<i32>("channels")?, 1);
+  assert_eq!(audio.get::<&str>("format")?, "S16LE");
+  let video = caps_received["video"]
+    .structure(0)
+    .context("empty video caps")?;
+  assert_eq!(video.get::<&str>("format")?, "I420");
+
+  conference.leave().await
+}
+
+fn elements(pipeline: &gstreamer::Pipeline, factory_name: &str) -> Vec<gstreamer::Element> {
+  pipeline
+    .iterate_recurse()
+    .into_iter()
+    .flatten()
+    .filter(|element| {
+      element
+        .factory()
+        .map_or(false, |factory| factory.name() == factory_name)
+    })
+    .collect()
+}
+
+fn jitterbuffers(pipeline: &gstreamer::Pipeline) -> Vec<gstreamer::Element> {
+  elements(pipeline, "rtpjitterbuffer")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn configures_jitterbuffers_per_media() -> Result<()> {
+  let alice = MockParticipant::new("alice", Some(1111), Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join_with(&deployment, |config| {
+    config
+      .audio_jitterbuffer
+      .update_from_str("latency=50 faststart-min-packets=3")
+      .unwrap();
+  })
+  .await?;
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  let pipeline = conference.pipeline().await?;
+  timeout(TIMEOUT, async {
+    while jitterbuffers(&pipeline).len() < 2 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for jitterbuffers")?;
+
+  let settings = |jitterbuffer: &gstreamer::Element| {
+    (
+      jitterbuffer.property::<u32>("latency"),
+      jitterbuffer.property::<u32>("faststart-min-packets"),
+      jitterbuffer.property::<bool>("do-retransmission"),
+    )
+  };
+  let mut current: Vec<_> = jitterbuffers(&pipeline).iter().map(settings).collect();
+  current.sort();
+  assert_eq!(current, [(50, 3, false), (200, 0, true)]);
+
+  let mut video_jitterbuffer = conference.jitterbuffer_config(MediaType::Video).await;
+  video_jitterbuffer.update_from_str("latency=300 do-retransmission=false")?;
+  conference
+    .set_jitterbuffer_config(MediaType::Video, video_jitterbuffer)
+    .await;
+  let mut current: Vec<_> = jitterbuffers(&pipeline).iter().map(settings).collect();
+  current.sort();
+  assert_eq!(current, [(50, 3, false), (300, 0, false)]);
+
+  conference.leave().await
+}
+
+/// The media SSRCs of the PLIs in a compound RTCP packet.
+fn pli_media_ssrcs(buffer: &gstreamer::Buffer) -> Vec<u32> {
+  let mut ssrcs = vec![];
+  let Ok(map) = buffer.map_readable()
+  else {
+    return ssrcs;
+  };
+  let mut packet = &map[..];
+  while packet.len() >= 4 {
+    let length = (u16::from_be_bytes([packet[2], packet[3]]) as usize + 1) * 4;
+    // Payload-specific feedback (206) with FMT 1 is a PLI.
+    if packet[1] == 206 && packet[0] & 0x1f == 1 && packet.len() >= 12 {
+      ssrcs.push(u32::from_be_bytes([
+        packet[8], packet[9], packet[10], packet[11],
+      ]));
+    }
+    packet = &packet[length.min(packet.len())..];
+  }
+  ssrcs
+}
+
+fn count_plis(conference: &JitsiConference, ssrc: u32) -> Arc<AtomicU64> {
+  let plis = Arc::new(AtomicU64::new(0));
+  {
+    let plis = plis.clone();
+    conference.on_rtcp(move |direction, buffer| {
+      if direction == PacketDirection::Send && pli_media_ssrcs(buffer).contains(&ssrc) {
+        plis.fetch_add(1, Ordering::Relaxed);
+      }
+    });
+  }
+  plis
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_keyframes() -> Result<()> {
+  let alice = MockParticipant::new("alice", None, Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join(&deployment).await?;
+  let plis = count_plis(&conference, 2222);
+  let video_received = Arc::new(AtomicU64::new(0));
+  {
+    let video_received = video_received.clone();
+    conference.on_rtp(move |direction, buffer| {
+      if direction == PacketDirection::Recv && rtp_ssrc(buffer) == Some(2222) {
+        video_received.fetch_add(1, Ordering::Relaxed);
+      }
+    });
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while video_received.load(Ordering::Relaxed) == 0 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for video")?;
+  // Nothing is linked to the participant's video, so no keyframe has been requested yet.
+  assert_eq!(plis.load(Ordering::Relaxed), 0);
+
+  conference.request_keyframe("alice", None).await?;
+  timeout(TIMEOUT, async {
+    while plis.load(Ordering::Relaxed) == 0 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for PLI")?;
+
+  assert!(conference.request_keyframe("bob", None).await.is_err());
+  assert!(conference
+    .request_keyframe("alice", Some(1234))
+    .await
+    .is_err());
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_keyframe_when_linked() -> Result<()> {
+  let alice = MockParticipant::new("alice", None, Some(2222));
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join(&deployment).await?;
+  let plis = count_plis(&conference, 2222);
+  conference
+    .on_participant(move |conference, participant| {
+      Box::pin(async move {
+        let bin = gstreamer::parse::bin_from_description("fakesink name=video async=false", false)?;
+        let sink_pad = bin
+          .by_name("video")
+          .context("missing fakesink")?
+          .static_pad("sink")
+          .context("fakesink has no sink pad")?;
+        bin.add_pad(
+          &gstreamer::GhostPad::builder_with_target(&sink_pad)?
+            .name("video")
+            .build(),
+        )?;
+        bin.set_property(
+          "name",
+          format!("participant_{}", participant.muc_jid.resource()),
+        );
+        conference.add_bin(&bin).await
+      })
+    })
+    .await;
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while plis.load(Ordering::Relaxed) == 0 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for PLI")?;
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forwards_keyframe_requests_to_encoder() -> Result<()> {
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, session_accept) = join(&deployment).await?;
+
+  let bin = gstreamer::parse::bin_from_description(
+    "videotestsrc is-live=true ! video/x-raw,width=320,height=180 ! vp8enc deadline=1 name=video",
+    false,
+  )?;
+  conference.add_bin(&bin).await?;
+  let encoder = bin.by_name("video").context("no video element")?;
+  encoder.link(&conference.video_sink_element().await?)?;
+
+  let force_key_units = Arc::new(AtomicU64::new(0));
+  {
+    let force_key_units = force_key_units.clone();
+    encoder
+      .static_pad("src")
+      .context("vp8enc has no src pad")?
+      .add_probe(
+        gstreamer::PadProbeType::EVENT_UPSTREAM,
+        move |_pad, info| {
+          if let Some(gstreamer::PadProbeData::Event(event)) = &info.data {
+            if event
+              .structure()
+              .map_or(false, |structure| structure.has_name("GstForceKeyUnit"))
+            {
+              force_key_units.fetch_add(1, Ordering::Relaxed);
+            }
+          }
+          gstreamer::PadProbeReturn::Ok
+        },
+      );
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  let video_ssrc = signalled_ssrc(&session_accept, "video")?;
+  let peer = deployment.peer.as_ref().context("no loopback peer")?;
+  peer.wait_for_packets(video_ssrc, 10).await?;
+
+  // The second request arrives too soon after the first, and is dropped.
+  peer.send_pli(video_ssrc)?;
+  peer.send_pli(video_ssrc)?;
+  timeout(TIMEOUT, async {
+    loop {
+      let stats = conference.keyframe_request_stats().await?;
+      if stats.forwarded + stats.dropped >= 2 {
+        return Ok::<_, anyhow::Error>(());
+      }
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for keyframe requests")??;
+  assert_eq!(
+    conference.keyframe_request_stats().await?,
+    KeyframeRequestStats {
+      forwarded: 1,
+      dropped: 1
+    }
+  );
+  assert_eq!(force_key_units.load(Ordering::Relaxed), 1);
+
+  sleep(Duration::from_secs(1)).await;
+  peer.send_pli(video_ssrc)?;
+  timeout(TIMEOUT, async {
+    while force_key_units.load(Ordering::Relaxed) < 2 {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for the encoder to be asked for a keyframe")?;
+  assert_eq!(conference.keyframe_request_stats().await?.forwarded, 2);
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_audio_levels() -> Result<()> {
+  let alice = MockParticipant::new("alice", Some(1111), None);
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    participants: vec![alice],
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, _) = join(&deployment).await?;
+  let audio_levels = Arc::new(Mutex::new(vec![]));
+  {
+    let audio_levels = audio_levels.clone();
+    conference
+      .on_audio_level(move |_conference, audio_level| {
+        audio_levels.lock().unwrap().push(audio_level);
+        Box::pin(async { Ok(()) })
+      })
+      .await;
+  }
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while audio_levels.lock().unwrap().is_empty() {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for an audio level")?;
+  // The peer always sends the same level, so it is only reported once.
+  sleep(Duration::from_millis(500)).await;
+  assert_eq!(
+    *audio_levels.lock().unwrap(),
+    [AudioLevel {
+      participant_id: Some("alice".to_owned()),
+      ssrc: 1111,
+      level_dbov: -((PEER_AUDIO_LEVEL & 0x7f) as i8),
+      vad: true,
+    }]
+  );
+
+  conference.leave().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn estimates_send_bandwidth() -> Result<()> {
+  gstreamer::init()?;
+  if gstreamer::ElementFactory::find("rtpgccbwe").is_none() {
+    eprintln!("skipping: rtpgccbwe (from gst-plugins-rs) is not installed");
+    return Ok(());
+  }
+  let deployment = MockDeployment::start(MockDeploymentConfig {
+    media: true,
+    ..Default::default()
+  })
+  .await?;
+  let (conference, session_accept) = join_with(&deployment, |config| {
+    config.bandwidth_estimation = true;
+    config.start_bitrate = 500;
+  })
+  .await?;
+  let video_ssrc = signalled_ssrc(&session_accept, "video")?;
+
+  let transport_cc_packets = Arc::new(AtomicU64::new(0));
+  {
+    let transport_cc_packets = transport_cc_packets.clone();
+    conference.on_rtp(move |direction, buffer| {
+      if direction != PacketDirection::Send || rtp_ssrc(buffer) != Some(video_ssrc) {
+        return;
+      }
+      if RTPBuffer::from_buffer_readable(buffer).map_or(false, |rtp_buffer| {
+        rtp_buffer
+          .extension_onebyte_header(TRANSPORT_CC_HDREXT_ID, 0)
+          .is_some()
+      }) {
+        transport_cc_packets.fetch_add(1, Ordering::Relaxed);
+      }
+    });
+  }
+  let estimates = Arc::new(Mutex::new(vec![]));
+  {
+    let estimates = estimates.clone();
+    conference
+      .on_bandwidth_estimate(move |_conference, bitrate| {
+        estimates.lock().unwrap().push(bitrate);
+        Box::pin(async { Ok(()) })
+      })
+      .await;
+  }
+
+  let bin = gstreamer::parse::bin_from_description(
+    "videotestsrc is-live=true ! video/x-raw,width=320,height=180 \
+     ! vp8enc deadline=1 name=video_encoder",
+    false,
+  )?;
+  conference.add_bin(&bin).await?;
+  let video_encoder = bin
+    .by_name("video_encoder")
+    .context("no video_encoder element")?;
+  video_encoder.link(&conference.video_sink_element().await?)?;
+  conference
+    .set_bandwidth_estimate_encoder(Some(video_encoder.clone()), BitrateUnit::BitsPerSecond)
+    .await?;
+  conference
+    .set_pipeline_state(gstreamer::State::Playing)
+    .await?;
+
+  timeout(TIMEOUT, async {
+    while transport_cc_packets.load(Ordering::Relaxed) < 10 || estimates.lock().unwrap().is_empty()
+    {
+      sleep(Duration::from_millis(50)).await;
+    }
+  })
+  .await
+  .context("timed out waiting for transport-cc packets and a bandwidth estimate")?;
+  let estimate = conference
+    .bandwidth_estimate()
+    .await
+    .context("no bandwidth estimate")?;
+  assert_eq!(
+    video_encoder.property::<i32>("target-bitrate"),
+    estimate as i32
+  );
+
+  conference.leave().await
+}